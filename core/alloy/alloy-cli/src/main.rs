@@ -1,4 +1,4 @@
-use alloy_core::{DaemonCommand, DaemonRequest, DaemonResponse, DaemonStatus};
+use alloy_core::{AlloyConfig, DaemonCommand, DaemonRequest, DaemonResponse, DaemonStatus};
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
@@ -27,23 +27,36 @@ enum Command {
     List,
     /// run an executable inside a bottle
     Run {
-        #[arg(value_name = "NAME")]
+        #[arg(value_name = "NAME_OR_ID")]
         name: String,
         #[arg(value_name = "EXECUTABLE")]
         executable: String,
         #[arg(value_name = "ARGS", trailing_var_arg = true)]
         args: Vec<String>,
     },
-    /// remove a bottle and its data
+    /// move a bottle and its log to the trash
     Destroy {
-        #[arg(value_name = "NAME")]
+        #[arg(value_name = "NAME_OR_ID")]
+        name: String,
+        /// remove the bottle for good instead
+        #[arg(long)]
+        permanent: bool,
+    },
+    /// bring a bottle back out of the trash
+    Restore {
+        #[arg(value_name = "NAME_OR_ID")]
         name: String,
     },
+    /// list or purge destroyed bottles
+    Trash {
+        #[command(subcommand)]
+        command: TrashCommand,
+    },
     /// list community recipes
     Recipes,
     /// apply a recipe to a bottle
     Apply {
-        #[arg(value_name = "BOTTLE")]
+        #[arg(value_name = "NAME_OR_ID")]
         bottle: String,
         #[arg(value_name = "RECIPE_ID")]
         recipe: String,
@@ -52,13 +65,24 @@ enum Command {
     Ping,
 }
 
+#[derive(Debug, Subcommand)]
+enum TrashCommand {
+    /// list trashed bottles, longest deleted first
+    List,
+    /// remove one trashed bottle for good, or all of them
+    Purge {
+        #[arg(value_name = "NAME_OR_ID")]
+        name: Option<String>,
+    },
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
-    let socket = cli
-        .socket
-        .or_else(|| std::env::var("SILICON_ALLOY_SOCKET").map(PathBuf::from).ok())
-        .unwrap_or_else(default_socket_path);
+    let socket = match cli.socket {
+        Some(socket) => socket,
+        None => AlloyConfig::load()?.socket,
+    };
 
     let request = DaemonRequest {
         id: Uuid::new_v4(),
@@ -75,7 +99,12 @@ async fn main() -> Result<()> {
                 args,
                 env: None,
             },
-            Command::Destroy { name } => DaemonCommand::Destroy { name },
+            Command::Destroy { name, permanent } => DaemonCommand::Destroy { name, permanent },
+            Command::Restore { name } => DaemonCommand::Restore { name },
+            Command::Trash { command } => match command {
+                TrashCommand::List => DaemonCommand::ListTrash,
+                TrashCommand::Purge { name } => DaemonCommand::PurgeTrash { name },
+            },
             Command::Recipes => DaemonCommand::ListRecipes,
             Command::Apply { bottle, recipe } => DaemonCommand::ApplyRecipe { bottle, recipe },
             Command::Ping => DaemonCommand::Ping,
@@ -99,13 +128,6 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-fn default_socket_path() -> PathBuf {
-    let base = dirs::runtime_dir()
-        .or_else(|| dirs::data_dir())
-        .unwrap_or_else(|| PathBuf::from("/tmp"));
-    base.join("silicon-alloy").join("daemon.sock")
-}
-
 async fn send_request(socket: &PathBuf, request: DaemonRequest) -> Result<DaemonResponse> {
     let mut stream = UnixStream::connect(socket)
         .await
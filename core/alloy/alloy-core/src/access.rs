@@ -0,0 +1,154 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::BTreeSet;
+use std::fs::DirBuilder;
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::net::{UnixListener, UnixStream};
+
+/// what a connected peer may do.
+#[derive(Debug, Clone)]
+pub enum Access {
+    Full,
+    ReadOnly(Arc<BTreeSet<String>>),
+}
+
+impl Access {
+    pub fn allows(&self, command: &str) -> bool {
+        match self {
+            Access::Full => true,
+            Access::ReadOnly(commands) => commands.contains(command),
+        }
+    }
+}
+
+/// which local users may talk to the daemon. the user running it always
+/// gets full access; `SILICON_ALLOY_ALLOWED_UIDS` and
+/// `SILICON_ALLOY_READ_ONLY_UIDS` admit others, and
+/// `SILICON_ALLOY_READ_ONLY_METHODS` overrides what read-only means.
+#[derive(Debug, Clone)]
+pub struct AccessPolicy {
+    owner: u32,
+    allowed: BTreeSet<u32>,
+    read_only: BTreeSet<u32>,
+    read_only_commands: Arc<BTreeSet<String>>,
+}
+
+impl AccessPolicy {
+    pub fn from_env(read_only_commands: &[&str]) -> Result<Self> {
+        let allowed = match std::env::var("SILICON_ALLOY_ALLOWED_UIDS") {
+            Ok(value) => parse_uids(&value).context("SILICON_ALLOY_ALLOWED_UIDS")?,
+            Err(_) => BTreeSet::new(),
+        };
+        let read_only = match std::env::var("SILICON_ALLOY_READ_ONLY_UIDS") {
+            Ok(value) => parse_uids(&value).context("SILICON_ALLOY_READ_ONLY_UIDS")?,
+            Err(_) => BTreeSet::new(),
+        };
+        let read_only_commands = match std::env::var("SILICON_ALLOY_READ_ONLY_METHODS") {
+            Ok(value) => split_list(&value).map(str::to_string).collect(),
+            Err(_) => read_only_commands.iter().map(|c| c.to_string()).collect(),
+        };
+        Ok(Self {
+            owner: current_uid(),
+            allowed,
+            read_only,
+            read_only_commands: Arc::new(read_only_commands),
+        })
+    }
+
+    /// the access granted to whoever is on the other end of `stream`, or
+    /// `None` when they must be turned away.
+    pub fn authorize(&self, stream: &UnixStream) -> Result<Option<Access>> {
+        let uid = stream
+            .peer_cred()
+            .context("unable to read peer credentials")?
+            .uid();
+        Ok(self.access_for(uid))
+    }
+
+    pub fn access_for(&self, uid: u32) -> Option<Access> {
+        if uid == self.owner || self.allowed.contains(&uid) {
+            Some(Access::Full)
+        } else if self.read_only.contains(&uid) {
+            Some(Access::ReadOnly(self.read_only_commands.clone()))
+        } else {
+            None
+        }
+    }
+
+    fn is_shared(&self) -> bool {
+        !self.allowed.is_empty() || !self.read_only.is_empty()
+    }
+}
+
+/// binds the socket with mode 0600 inside a 0700 directory. when other users
+/// are admitted the modes widen to 0666 / 0711 and the peer check in
+/// [`AccessPolicy::authorize`] does the gatekeeping.
+pub fn bind_socket(path: &Path, policy: &AccessPolicy) -> Result<UnixListener> {
+    socket_dir(path, policy)?;
+    let socket_mode = if policy.is_shared() { 0o666 } else { 0o600 };
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("failed to bind {}", path.display()))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(socket_mode))?;
+    Ok(listener)
+}
+
+/// creates the socket directory with the right mode, or checks the one that
+/// is already there. a directory we didn't create may hold other files, so
+/// it is never chmodded: a wrong owner or mode stops the daemon instead.
+pub fn socket_dir<'a>(path: &'a Path, policy: &AccessPolicy) -> Result<&'a Path> {
+    let parent = path
+        .parent()
+        .ok_or_else(|| anyhow!("socket path {} has no parent", path.display()))?;
+    let dir_mode = if policy.is_shared() { 0o711 } else { 0o700 };
+    if let Some(above) = parent.parent() {
+        std::fs::create_dir_all(above)
+            .with_context(|| format!("unable to create {}", above.display()))?;
+    }
+    match DirBuilder::new().mode(dir_mode).create(parent) {
+        // the umask may have taken bits we need
+        Ok(()) => std::fs::set_permissions(parent, std::fs::Permissions::from_mode(dir_mode))?,
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("unable to create socket directory {}", parent.display()))
+        }
+    }
+    let metadata = std::fs::metadata(parent)?;
+    if metadata.uid() != current_uid() {
+        bail!(
+            "socket directory {} belongs to uid {}, refusing to use it",
+            parent.display(),
+            metadata.uid()
+        );
+    }
+    let mode = metadata.permissions().mode() & 0o777;
+    if mode & 0o066 != 0 || (policy.is_shared() && mode & 0o001 == 0) {
+        bail!(
+            "socket directory {} has mode {:04o}, expected {:04o}",
+            parent.display(),
+            mode,
+            dir_mode
+        );
+    }
+    Ok(parent)
+}
+
+fn current_uid() -> u32 {
+    // geteuid has no failure mode
+    unsafe { libc::geteuid() }
+}
+
+fn parse_uids(value: &str) -> Result<BTreeSet<u32>> {
+    split_list(value)
+        .map(|uid| {
+            uid.parse()
+                .with_context(|| format!("invalid uid {uid:?}"))
+        })
+        .collect()
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|item| !item.is_empty())
+}
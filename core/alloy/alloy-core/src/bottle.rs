@@ -27,6 +27,7 @@ pub struct BottleMetadata {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct BottleSummary {
     pub name: String,
+    pub id: Uuid,
     pub path: PathBuf,
     pub runtime: RuntimeMetadata,
 }
@@ -77,12 +78,17 @@ impl FromStr for BottleName {
 pub struct BottleManager {
     bottles_dir: PathBuf,
     logs_dir: PathBuf,
+    pub(crate) trash_dir: PathBuf,
     runtime: RuntimeLocator,
 }
 
 impl BottleManager {
     pub fn new(runtime: RuntimeLocator) -> Result<Self> {
-        let base = data_root()?;
+        Self::with_root(&data_root()?, runtime)
+    }
+
+    /// keeps bottles, logs and the trash under `base` instead of the data root.
+    pub fn with_root(base: &Path, runtime: RuntimeLocator) -> Result<Self> {
         let bottles_dir = base.join("bottles");
         let logs_dir = base.join("logs");
         fs::create_dir_all(&bottles_dir)
@@ -93,6 +99,7 @@ impl BottleManager {
         Ok(Self {
             bottles_dir,
             logs_dir,
+            trash_dir: base.join("trash"),
             runtime,
         })
     }
@@ -101,15 +108,15 @@ impl BottleManager {
         &self.runtime
     }
 
-    fn bottle_path(&self, name: &BottleName) -> PathBuf {
+    pub(crate) fn bottle_path(&self, name: &BottleName) -> PathBuf {
         self.bottles_dir.join(name.as_str())
     }
 
-    fn metadata_path(&self, name: &BottleName) -> PathBuf {
+    pub(crate) fn metadata_path(&self, name: &BottleName) -> PathBuf {
         self.bottle_path(name).join("silicon-alloy.json")
     }
 
-    fn log_path(&self, name: &BottleName) -> PathBuf {
+    pub(crate) fn log_path(&self, name: &BottleName) -> PathBuf {
         self.logs_dir.join(format!("{}.log", name.as_str()))
     }
 
@@ -157,6 +164,7 @@ impl BottleManager {
             if let Ok(metadata) = serde_json::from_slice::<BottleMetadata>(&bytes) {
                 summaries.push(BottleSummary {
                     name,
+                    id: metadata.id,
                     path: entry.path(),
                     runtime: metadata.runtime,
                 });
@@ -166,11 +174,45 @@ impl BottleManager {
         Ok(summaries)
     }
 
-    pub async fn destroy_bottle(&self, name: &BottleName) -> Result<()> {
+    /// the bottle `selector` names: its full id, its name as given or as
+    /// `BottleName` cleans it up, or the start of its id, tried in that order.
+    pub async fn resolve(&self, selector: &str) -> Result<BottleName> {
+        let selector = selector.trim();
+        let bottles = self.list_bottles().await?;
+        if let Ok(id) = selector.parse::<Uuid>() {
+            return bottles
+                .into_iter()
+                .find(|bottle| bottle.id == id)
+                .map(|bottle| BottleName(bottle.name))
+                .ok_or_else(|| anyhow!("bottle {id} does not exist"));
+        }
+        if let Ok(name) = BottleName::from_str(selector) {
+            if self.bottle_path(&name).exists() {
+                return Ok(name);
+            }
+        }
+        let mut found: Vec<_> = bottles
+            .into_iter()
+            .filter(|bottle| is_id_prefix(selector, bottle.id))
+            .collect();
+        match found.len() {
+            0 => bail!("bottle {selector} does not exist"),
+            1 => Ok(BottleName(found.remove(0).name)),
+            n => bail!("{n} bottles have an id starting with {selector}; give more of it"),
+        }
+    }
+
+    /// moves the bottle to the trash, or removes it and its log for good
+    /// when `permanent` is set.
+    pub async fn destroy_bottle(&self, name: &BottleName, permanent: bool) -> Result<()> {
         let prefix_path = self.bottle_path(name);
         if !prefix_path.exists() {
             bail!("bottle {} does not exist", name.as_str());
         }
+        if !permanent {
+            self.trash_bottle(name).await?;
+            return Ok(());
+        }
         remove_dir_all(&prefix_path).await?;
         let log_path = self.log_path(name);
         if log_path.exists() {
@@ -284,6 +326,13 @@ where
     Ok(())
 }
 
+/// whether `selector` is the start of `id`, dashes and all.
+pub(crate) fn is_id_prefix(selector: &str, id: Uuid) -> bool {
+    !selector.is_empty()
+        && selector.chars().all(|ch| ch.is_ascii_hexdigit() || ch == '-')
+        && id.to_string().starts_with(&selector.to_ascii_lowercase())
+}
+
 fn data_root() -> Result<PathBuf> {
     if let Some(dir) = dirs::data_dir() {
         let path = dir.join("SiliconAlloy");
@@ -299,8 +348,35 @@ fn data_root() -> Result<PathBuf> {
 }
 
 #[cfg(test)]
-mod tests {
-    use super::BottleName;
+pub(crate) mod tests {
+    use super::*;
+
+    /// a manager under a fresh temp root, with a runtime that only has to
+    /// describe itself.
+    pub(crate) fn manager() -> (BottleManager, PathBuf) {
+        let root = std::env::temp_dir().join(format!("alloy-bottles-{}", Uuid::new_v4()));
+        let dist = root.join("dist");
+        fs::create_dir_all(dist.join("share").join("silicon-alloy")).unwrap();
+        fs::write(dist.join("share").join("silicon-alloy").join("BUILDINFO"), "version=9.0\n").unwrap();
+        let runtime = RuntimeLocator::with_root(dist).unwrap();
+        (BottleManager::with_root(&root, runtime).unwrap(), root)
+    }
+
+    /// a bottle as `create_bottle` leaves it, without running wineboot.
+    pub(crate) fn bottle(manager: &BottleManager, name: &str) -> (BottleName, Uuid) {
+        let name = BottleName::from_str(name).unwrap();
+        fs::create_dir_all(manager.bottle_path(&name).join("drive_c")).unwrap();
+        let metadata = BottleMetadata {
+            id: Uuid::new_v4(),
+            name: name.as_str().to_string(),
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            runtime: manager.runtime().metadata().clone(),
+            notes: None,
+        };
+        fs::write(manager.metadata_path(&name), serde_json::to_vec(&metadata).unwrap()).unwrap();
+        fs::write(manager.log_path(&name), "[stdout] hello\n").unwrap();
+        (name, metadata.id)
+    }
 
     #[test]
     fn bottle_name_sanitizes() {
@@ -313,5 +389,28 @@ mod tests {
     fn bottle_name_rejects_blank() {
         assert!("!!!".parse::<BottleName>().is_err());
     }
-}
 
+    #[tokio::test]
+    async fn bottles_resolve_by_id_name_or_id_prefix() {
+        let (manager, root) = manager();
+        let (steam, steam_id) = bottle(&manager, "steam");
+        let (office, office_id) = bottle(&manager, "office");
+
+        assert_eq!(manager.resolve(&steam_id.to_string()).await.unwrap(), steam);
+        assert_eq!(manager.resolve("Steam").await.unwrap(), steam);
+        assert_eq!(manager.resolve(" office ").await.unwrap(), office);
+        let prefix = &office_id.to_string()[..8];
+        assert_eq!(manager.resolve(&prefix.to_uppercase()).await.unwrap(), office);
+        assert!(manager.resolve(&Uuid::new_v4().to_string()).await.is_err());
+        assert!(manager.resolve("notepad").await.is_err());
+        // a prefix both ids share names neither
+        let shared = steam_id
+            .to_string()
+            .chars()
+            .zip(office_id.to_string().chars())
+            .take_while(|(a, b)| a == b)
+            .count();
+        assert!(manager.resolve(&steam_id.to_string()[..shared]).await.is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
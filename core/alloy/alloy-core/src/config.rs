@@ -0,0 +1,140 @@
+use crate::limits::Limits;
+use crate::trash::Retention;
+use anyhow::{anyhow, Context, Result};
+use directories::ProjectDirs;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// the settings alloy-daemon and alloyctl take from the shared config file.
+/// they live in its `[alloy]` table; everything else in the file belongs to
+/// silicon-alloy-daemon. environment variables still win over the file.
+#[derive(Debug, Clone)]
+pub struct AlloyConfig {
+    pub socket: PathBuf,
+    /// a single runtime dist folder, as `SILICON_ALLOY_RUNTIME_DIR`
+    pub runtime: Option<PathBuf>,
+    pub recipes: Option<PathBuf>,
+    pub max_request_bytes: usize,
+    /// seconds, 0 for never
+    pub client_idle_timeout: u64,
+    /// seconds, 0 for never
+    pub read_timeout: u64,
+    pub max_connections: usize,
+    /// days a destroyed bottle is kept in the trash, 0 for until purged
+    pub trash_max_age_days: u64,
+    /// bytes the trash may take up, 0 for no limit
+    pub trash_max_bytes: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    alloy: AlloyTable,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AlloyTable {
+    socket: Option<PathBuf>,
+    runtime: Option<PathBuf>,
+    recipes: Option<PathBuf>,
+    max_request_bytes: Option<usize>,
+    client_idle_timeout: Option<u64>,
+    read_timeout: Option<u64>,
+    max_connections: Option<usize>,
+    trash_max_age_days: Option<u64>,
+    trash_max_bytes: Option<u64>,
+}
+
+impl AlloyConfig {
+    pub fn load() -> Result<Self> {
+        let path = match std::env::var("SILICON_ALLOY_CONFIG") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => ProjectDirs::from("com", "SiliconAlloy", "SiliconAlloy")
+                .ok_or_else(|| anyhow!("unable to determine project directories"))?
+                .config_dir()
+                .join("daemon.toml"),
+        };
+        Self::load_from(&path)
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let file: ConfigFile = match std::fs::read_to_string(path) {
+            Ok(data) => toml::from_str(&data)
+                .with_context(|| format!("invalid config file {}", path.display()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => ConfigFile::default(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("unable to read {}", path.display()))
+            }
+        };
+        let env = |var: &str| std::env::var(var).ok().map(PathBuf::from);
+        let config = Self {
+            socket: env("SILICON_ALLOY_SOCKET")
+                .or(file.alloy.socket)
+                .unwrap_or_else(default_socket_path),
+            runtime: env("SILICON_ALLOY_RUNTIME_DIR").or(file.alloy.runtime),
+            recipes: env("SILICON_ALLOY_RECIPES").or(file.alloy.recipes),
+            max_request_bytes: number("SILICON_ALLOY_MAX_REQUEST_BYTES")?
+                .or(file.alloy.max_request_bytes)
+                .unwrap_or(1 << 20),
+            client_idle_timeout: number("SILICON_ALLOY_CLIENT_IDLE_TIMEOUT")?
+                .or(file.alloy.client_idle_timeout)
+                .unwrap_or(600),
+            read_timeout: number("SILICON_ALLOY_READ_TIMEOUT")?
+                .or(file.alloy.read_timeout)
+                .unwrap_or(30),
+            max_connections: number("SILICON_ALLOY_MAX_CONNECTIONS")?
+                .or(file.alloy.max_connections)
+                .unwrap_or(64),
+            trash_max_age_days: number("SILICON_ALLOY_TRASH_MAX_AGE_DAYS")?
+                .or(file.alloy.trash_max_age_days)
+                .unwrap_or(30),
+            trash_max_bytes: number("SILICON_ALLOY_TRASH_MAX_BYTES")?
+                .or(file.alloy.trash_max_bytes)
+                .unwrap_or(0),
+        };
+        if config.max_request_bytes == 0 || config.max_connections == 0 {
+            return Err(anyhow!("max_request_bytes and max_connections must be at least 1"));
+        }
+        Ok(config)
+    }
+
+    pub fn limits(&self) -> Limits {
+        let seconds = |value: u64| (value > 0).then(|| Duration::from_secs(value));
+        Limits {
+            max_request_bytes: self.max_request_bytes,
+            idle_timeout: seconds(self.client_idle_timeout),
+            read_timeout: seconds(self.read_timeout),
+        }
+    }
+
+    /// what alloy-daemon keeps of the trash.
+    pub fn trash_retention(&self) -> Retention {
+        Retention {
+            max_age: (self.trash_max_age_days > 0)
+                .then(|| Duration::from_secs(self.trash_max_age_days.saturating_mul(24 * 60 * 60))),
+            max_bytes: (self.trash_max_bytes > 0).then_some(self.trash_max_bytes),
+        }
+    }
+}
+
+fn number<T: std::str::FromStr>(var: &str) -> Result<Option<T>> {
+    match std::env::var(var) {
+        Ok(raw) => raw
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| anyhow!("invalid {var}: expected a number, got {raw:?}")),
+        Err(_) => Ok(None),
+    }
+}
+
+/// alloy-daemon's historical socket, kept apart from silicon-alloy-daemon's
+/// so both can run side by side.
+fn default_socket_path() -> PathBuf {
+    let base = dirs::runtime_dir()
+        .or_else(dirs::data_dir)
+        .unwrap_or_else(|| PathBuf::from("/tmp"));
+    base.join("silicon-alloy").join("daemon.sock")
+}
@@ -0,0 +1,94 @@
+use crate::rpc::{DaemonCommand, DaemonRequest};
+use anyhow::{bail, Context, Result};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{ErrorKind, Read, Seek, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+use uuid::Uuid;
+
+/// flock + pidfile next to the socket, held for the life of the daemon.
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    pub fn acquire(socket_path: &Path) -> Result<Self> {
+        let path = socket_path.with_extension("lock");
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("unable to open lock file {}", path.display()))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut pid = String::new();
+                let _ = file.read_to_string(&mut pid);
+                bail!(
+                    "another alloy-daemon (pid {}) is already serving {}",
+                    pid.trim(),
+                    socket_path.display()
+                );
+            }
+            Err(TryLockError::Error(err)) => {
+                return Err(err).with_context(|| format!("unable to lock {}", path.display()));
+            }
+        }
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "{}", std::process::id())?;
+        Ok(Self { _file: file })
+    }
+}
+
+/// removes a socket left behind by a crashed daemon. a socket that still
+/// answers a ping, or anything that isn't a socket, is left alone and
+/// reported as an error.
+pub async fn clear_stale_socket(path: &Path) -> Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    if !metadata.file_type().is_socket() {
+        bail!("{} exists and is not a socket, refusing to replace it", path.display());
+    }
+
+    let mut stream = match UnixStream::connect(path).await {
+        Ok(stream) => stream,
+        Err(err) if err.kind() == ErrorKind::ConnectionRefused => {
+            std::fs::remove_file(path)
+                .with_context(|| format!("unable to remove stale socket {}", path.display()))?;
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let ping = DaemonRequest {
+        id: Uuid::new_v4(),
+        command: DaemonCommand::Ping,
+    };
+    let mut payload = serde_json::to_vec(&ping)?;
+    payload.push(b'\n');
+    let answered = tokio::time::timeout(Duration::from_secs(2), async {
+        stream.write_all(&payload).await?;
+        let mut line = String::new();
+        BufReader::new(&mut stream).read_line(&mut line).await?;
+        Ok::<_, std::io::Error>(!line.trim().is_empty())
+    })
+    .await;
+    match answered {
+        Ok(Ok(true)) => bail!("a daemon is already listening on {}", path.display()),
+        _ => bail!(
+            "something is listening on {} but did not answer a ping",
+            path.display()
+        ),
+    }
+}
@@ -1,10 +1,19 @@
+pub mod access;
 pub mod bottle;
+pub mod config;
+pub mod instance;
+pub mod limits;
 pub mod rpc;
 pub mod runtime;
 pub mod recipes;
+pub mod trash;
 
+pub use access::{Access, AccessPolicy};
 pub use bottle::{BottleManager, BottleMetadata, BottleName, BottleSummary};
+pub use config::AlloyConfig;
+pub use limits::{ConnectionSlots, Limits};
 pub use runtime::{RuntimeLocator, RuntimeMetadata};
 pub use rpc::{DaemonCommand, DaemonRequest, DaemonResponse, DaemonStatus};
 pub use recipes::{Recipe, RecipeCatalog, RecipeExecutor, RecipeStep};
+pub use trash::{Retention, TrashedBottle};
 
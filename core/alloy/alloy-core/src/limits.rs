@@ -0,0 +1,160 @@
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// what one client may send before alloy-daemon gives up on it.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_request_bytes: usize,
+    /// how long to wait for the next request
+    pub idle_timeout: Option<Duration>,
+    /// how long a request may take to arrive once it has started
+    pub read_timeout: Option<Duration>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Received {
+    Line(String),
+    Closed,
+    /// the line went past `max_request_bytes`; the rest of it is unread
+    TooLarge,
+    Idle,
+    Stalled,
+}
+
+/// reads one newline-terminated request within `limits`.
+pub async fn read_request<R>(reader: &mut R, limits: &Limits) -> io::Result<Received>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let waiting = async { reader.fill_buf().await.map(|buf| buf.is_empty()) };
+    match within(limits.idle_timeout, waiting).await.transpose()? {
+        None => return Ok(Received::Idle),
+        Some(true) => return Ok(Received::Closed),
+        Some(false) => {}
+    }
+    // reading one byte past the limit tells an oversized line apart from one
+    // that fills it exactly
+    let cap = limits.max_request_bytes as u64 + 1;
+    let mut line = Vec::new();
+    let mut bounded = (&mut *reader).take(cap);
+    let reading = bounded.read_until(b'\n', &mut line);
+    if within(limits.read_timeout, reading)
+        .await
+        .transpose()?
+        .is_none()
+    {
+        return Ok(Received::Stalled);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    } else if line.len() as u64 == cap {
+        return Ok(Received::TooLarge);
+    }
+    String::from_utf8(line)
+        .map(Received::Line)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+async fn within<T>(timeout: Option<Duration>, future: impl Future<Output = T>) -> Option<T> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+/// caps concurrent clients. the accept loop takes a slot before accepting,
+/// so extra clients wait in the listen backlog.
+#[derive(Clone)]
+pub struct ConnectionSlots {
+    semaphore: Arc<Semaphore>,
+}
+
+impl ConnectionSlots {
+    pub fn new(max: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max)),
+        }
+    }
+
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        self.semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("connection slots are never closed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncWriteExt, BufReader};
+    use tokio::net::{UnixListener, UnixStream};
+
+    fn limits() -> Limits {
+        Limits {
+            max_request_bytes: 8,
+            idle_timeout: Some(Duration::from_millis(100)),
+            read_timeout: Some(Duration::from_millis(100)),
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_are_bounded() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let mut reader = BufReader::new(server);
+        client.write_all(b"12345678\n123456789\n").await.unwrap();
+        assert_eq!(
+            read_request(&mut reader, &limits()).await.unwrap(),
+            Received::Line("12345678".to_string())
+        );
+        assert_eq!(
+            read_request(&mut reader, &limits()).await.unwrap(),
+            Received::TooLarge
+        );
+
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let mut reader = BufReader::new(server);
+        assert_eq!(
+            read_request(&mut reader, &limits()).await.unwrap(),
+            Received::Idle
+        );
+        client.write_all(b"{\"id\"").await.unwrap();
+        assert_eq!(
+            read_request(&mut reader, &limits()).await.unwrap(),
+            Received::Stalled
+        );
+        drop(client);
+        assert_eq!(
+            read_request(&mut reader, &limits()).await.unwrap(),
+            Received::Closed
+        );
+    }
+
+    #[tokio::test]
+    async fn slots_hold_back_extra_clients() {
+        let path = std::env::temp_dir().join(format!("alloy-limits-{}.sock", uuid::Uuid::new_v4()));
+        let listener = UnixListener::bind(&path).unwrap();
+        let slots = ConnectionSlots::new(1);
+        let _first = UnixStream::connect(&path).await.unwrap();
+        let _second = UnixStream::connect(&path).await.unwrap();
+
+        let held = slots.acquire().await;
+        listener.accept().await.unwrap();
+        assert_eq!(slots.available(), 0);
+        let waiting = tokio::time::timeout(Duration::from_millis(100), slots.acquire()).await;
+        assert!(waiting.is_err());
+        drop(held);
+        let _slot = slots.acquire().await;
+        listener.accept().await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,11 +1,11 @@
 use crate::bottle::{BottleManager, BottleName};
 use anyhow::{anyhow, Context, Result};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use tokio::fs;
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Recipe {
     pub id: String,
     pub name: String,
@@ -17,7 +17,7 @@ pub struct Recipe {
     pub steps: Vec<RecipeStep>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(untagged)]
 pub enum RecipeStep {
     Run {
@@ -37,14 +37,14 @@ pub enum RecipeStep {
     },
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RecipeRun {
     pub command: String,
     #[serde(default)]
     pub args: Vec<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RecipeWinecfg {
     #[serde(default)]
     pub version: Option<String>,
@@ -100,7 +100,7 @@ impl RecipeCatalog {
             }
         }
 
-        items.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+        items.sort_by_key(|recipe| recipe.name.to_lowercase());
         Ok(items)
     }
 
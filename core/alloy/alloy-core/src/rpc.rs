@@ -13,7 +13,19 @@ pub enum DaemonCommand {
         args: Vec<String>,
         env: Option<HashMap<String, String>>,
     },
-    Destroy { name: String },
+    Destroy {
+        name: String,
+        /// remove the bottle for good instead of moving it to the trash
+        #[serde(default)]
+        permanent: bool,
+    },
+    Restore { name: String },
+    ListTrash,
+    /// one trashed bottle, or the whole trash when `name` is missing
+    PurgeTrash {
+        #[serde(default)]
+        name: Option<String>,
+    },
     Ping,
     ListRecipes,
     ApplyRecipe {
@@ -22,6 +34,28 @@ pub enum DaemonCommand {
     },
 }
 
+impl DaemonCommand {
+    /// the wire name of the command, as used in the `command` tag.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DaemonCommand::Create { .. } => "create",
+            DaemonCommand::List => "list",
+            DaemonCommand::Run { .. } => "run",
+            DaemonCommand::Destroy { .. } => "destroy",
+            DaemonCommand::Restore { .. } => "restore",
+            DaemonCommand::ListTrash => "list_trash",
+            DaemonCommand::PurgeTrash { .. } => "purge_trash",
+            DaemonCommand::Ping => "ping",
+            DaemonCommand::ListRecipes => "list_recipes",
+            DaemonCommand::ApplyRecipe { .. } => "apply_recipe",
+        }
+    }
+
+    /// commands that only read state. peers with read-only access may send
+    /// these and nothing else.
+    pub const READ_ONLY: &'static [&'static str] = &["ping", "list", "list_recipes", "list_trash"];
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DaemonRequest {
     pub id: Uuid,
@@ -1,5 +1,4 @@
 use anyhow::{Context, Result};
-use anyhow::bail;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
@@ -0,0 +1,318 @@
+use crate::bottle::{is_id_prefix, BottleManager, BottleMetadata, BottleName, BottleSummary};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::fs::{create_dir_all, remove_dir_all};
+use uuid::Uuid;
+
+/// written into a bottle as it is trashed, and removed when it comes back.
+const TRASH_META: &str = "trash.json";
+/// a trashed bottle being purged is renamed to this first, so a restore
+/// can't pick up a half-removed tree.
+const PURGING_SUFFIX: &str = ".purging";
+
+/// a bottle in the trash, as `list_trash` shows it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrashedBottle {
+    pub id: Uuid,
+    pub name: String,
+    /// unix seconds
+    pub deleted_at: u64,
+    /// bytes the bottle took up on disk
+    pub size: u64,
+}
+
+/// the part of a `TrashedBottle` that isn't its directory name.
+#[derive(Debug, Serialize, Deserialize)]
+struct Deletion {
+    name: String,
+    deleted_at: u64,
+    size: u64,
+}
+
+/// how much of the trash is kept. `None` keeps everything by that measure.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Retention {
+    pub max_age: Option<Duration>,
+    pub max_bytes: Option<u64>,
+}
+
+impl BottleManager {
+    /// moves the bottle to `trash/<id>` and its log to `trash/<id>.log`, from
+    /// where `restore_bottle` brings both back until they are purged.
+    pub async fn trash_bottle(&self, name: &BottleName) -> Result<TrashedBottle> {
+        let prefix_path = self.bottle_path(name);
+        let metadata = self.read_metadata(name).await.with_context(|| {
+            format!("bottle {} has no readable metadata; destroy it with --permanent", name.as_str())
+        })?;
+        let measured = prefix_path.clone();
+        let size = tokio::task::spawn_blocking(move || dir_size(&measured))
+            .await?
+            .with_context(|| format!("failed to measure bottle {}", name.as_str()))?;
+        let deletion = Deletion {
+            name: name.as_str().to_string(),
+            deleted_at: unix_timestamp(),
+            size,
+        };
+
+        create_dir_all(&self.trash_dir)
+            .await
+            .with_context(|| format!("unable to create trash directory at {}", self.trash_dir.display()))?;
+        let trashed_path = self.trashed_path(metadata.id);
+        if trashed_path.exists() {
+            bail!("bottle {} is already in the trash", metadata.id);
+        }
+        tokio::fs::write(prefix_path.join(TRASH_META), serde_json::to_vec_pretty(&deletion)?).await?;
+        if let Err(err) = tokio::fs::rename(&prefix_path, &trashed_path).await {
+            tokio::fs::remove_file(prefix_path.join(TRASH_META)).await.ok();
+            return Err(err).with_context(|| format!("failed to move bottle {} to the trash", name.as_str()));
+        }
+        let log_path = self.log_path(name);
+        if log_path.exists() {
+            tokio::fs::rename(&log_path, self.trashed_log(metadata.id)).await.ok();
+        }
+
+        Ok(TrashedBottle {
+            id: metadata.id,
+            name: deletion.name,
+            deleted_at: deletion.deleted_at,
+            size: deletion.size,
+        })
+    }
+
+    /// the trashed bottles, longest deleted first.
+    pub async fn list_trash(&self) -> Result<Vec<TrashedBottle>> {
+        let mut trashed = Vec::new();
+        let mut entries = match tokio::fs::read_dir(&self.trash_dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(trashed),
+            Err(err) => return Err(err.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let Ok(id) = entry.file_name().to_string_lossy().parse::<Uuid>() else {
+                continue;
+            };
+            let Ok(bytes) = tokio::fs::read(entry.path().join(TRASH_META)).await else {
+                continue;
+            };
+            if let Ok(deletion) = serde_json::from_slice::<Deletion>(&bytes) {
+                trashed.push(TrashedBottle {
+                    id,
+                    name: deletion.name,
+                    deleted_at: deletion.deleted_at,
+                    size: deletion.size,
+                });
+            }
+        }
+        trashed.sort_by_key(|trashed| (trashed.deleted_at, trashed.id));
+        Ok(trashed)
+    }
+
+    /// the trashed bottle `selector` names: its id, the name it had, or the
+    /// start of its id. a name several trashed bottles had needs the id.
+    pub async fn find_trashed(&self, selector: &str) -> Result<TrashedBottle> {
+        let selector = selector.trim();
+        let trashed = self.list_trash().await?;
+        let mut found: Vec<_> = match selector.parse::<Uuid>() {
+            Ok(id) => trashed.into_iter().filter(|trashed| trashed.id == id).collect(),
+            Err(_) => {
+                let name = BottleName::from_str(selector).ok();
+                let (named, others): (Vec<_>, Vec<_>) = trashed
+                    .into_iter()
+                    .partition(|trashed| name.as_ref().is_some_and(|name| trashed.name == name.as_str()));
+                if named.is_empty() {
+                    others
+                        .into_iter()
+                        .filter(|trashed| is_id_prefix(selector, trashed.id))
+                        .collect()
+                } else {
+                    named
+                }
+            }
+        };
+        match found.len() {
+            0 => bail!("bottle {selector} is not in the trash"),
+            1 => Ok(found.remove(0)),
+            n => bail!("{n} trashed bottles match {selector}; use the id of the one you mean"),
+        }
+    }
+
+    /// moves a trashed bottle and its log back. it can't come back while
+    /// another bottle has taken its name.
+    pub async fn restore_bottle(&self, selector: &str) -> Result<BottleSummary> {
+        let trashed = self.find_trashed(selector).await?;
+        let name = BottleName::from_str(&trashed.name)?;
+        let prefix_path = self.bottle_path(&name);
+        if prefix_path.exists() {
+            bail!("bottle {} already exists", name.as_str());
+        }
+        tokio::fs::rename(self.trashed_path(trashed.id), &prefix_path)
+            .await
+            .with_context(|| format!("failed to restore bottle {}", trashed.id))?;
+        tokio::fs::remove_file(prefix_path.join(TRASH_META)).await.ok();
+        let log_path = self.log_path(&name);
+        if !log_path.exists() {
+            tokio::fs::rename(self.trashed_log(trashed.id), &log_path).await.ok();
+        }
+
+        let metadata = self.read_metadata(&name).await?;
+        Ok(BottleSummary {
+            name: trashed.name,
+            id: trashed.id,
+            path: prefix_path,
+            runtime: metadata.runtime,
+        })
+    }
+
+    /// removes a trashed bottle and its log for good.
+    pub async fn purge_trashed(&self, id: Uuid) -> Result<()> {
+        let doomed = self.trash_dir.join(format!("{id}{PURGING_SUFFIX}"));
+        match tokio::fs::rename(self.trashed_path(id), &doomed).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => bail!("bottle {id} is not in the trash"),
+            Err(err) => return Err(err).with_context(|| format!("failed to purge bottle {id}")),
+        }
+        tokio::fs::remove_file(self.trashed_log(id)).await.ok();
+        remove_dir_all(&doomed)
+            .await
+            .with_context(|| format!("failed to purge bottle {id}"))
+    }
+
+    /// purges every trashed bottle, returning their ids.
+    pub async fn empty_trash(&self) -> Result<Vec<Uuid>> {
+        let mut purged = Vec::new();
+        for trashed in self.list_trash().await? {
+            self.purge_trashed(trashed.id).await?;
+            purged.push(trashed.id);
+        }
+        Ok(purged)
+    }
+
+    /// purges what `retention` no longer keeps: bottles deleted longer ago
+    /// than `max_age`, then the longest deleted until the rest fits in
+    /// `max_bytes`. purges interrupted earlier are finished too.
+    pub async fn enforce_retention(&self, retention: Retention) -> Result<Vec<Uuid>> {
+        self.finish_purges().await?;
+        let trashed = self.list_trash().await?;
+        let now = unix_timestamp();
+        let mut total: u64 = trashed.iter().map(|trashed| trashed.size).sum();
+        let mut purged = Vec::new();
+        for trashed in trashed {
+            let expired = retention
+                .max_age
+                .is_some_and(|max_age| now.saturating_sub(trashed.deleted_at) > max_age.as_secs());
+            let over = retention.max_bytes.is_some_and(|max_bytes| total > max_bytes);
+            if !expired && !over {
+                continue;
+            }
+            self.purge_trashed(trashed.id).await?;
+            total -= trashed.size;
+            purged.push(trashed.id);
+        }
+        Ok(purged)
+    }
+
+    async fn finish_purges(&self) -> Result<()> {
+        let mut entries = match tokio::fs::read_dir(&self.trash_dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_name().to_string_lossy().ends_with(PURGING_SUFFIX) {
+                remove_dir_all(entry.path()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn read_metadata(&self, name: &BottleName) -> Result<BottleMetadata> {
+        let bytes = tokio::fs::read(self.metadata_path(name)).await?;
+        serde_json::from_slice(&bytes).map_err(|err| anyhow!("invalid bottle metadata: {err}"))
+    }
+
+    fn trashed_path(&self, id: Uuid) -> PathBuf {
+        self.trash_dir.join(id.to_string())
+    }
+
+    fn trashed_log(&self, id: Uuid) -> PathBuf {
+        self.trash_dir.join(format!("{id}.log"))
+    }
+}
+
+/// bytes the files under `dir` take up, counting hard linked files once and
+/// never following symlinks; a prefix's `dosdevices` point all over the disk.
+fn dir_size(dir: &Path) -> io::Result<u64> {
+    let mut seen = HashSet::new();
+    let mut total = 0;
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let metadata = std::fs::symlink_metadata(entry.path())?;
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else if metadata.is_file() && seen.insert((metadata.dev(), metadata.ino())) {
+                total += metadata.len();
+            }
+        }
+    }
+    Ok(total)
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bottle::tests::{bottle, manager};
+
+    #[tokio::test]
+    async fn destroyed_bottles_restore_or_expire() {
+        let (manager, root) = manager();
+        let (steam, steam_id) = bottle(&manager, "steam");
+        std::os::unix::fs::symlink("/", manager.bottle_path(&steam).join("z:")).unwrap();
+        manager.destroy_bottle(&steam, false).await.unwrap();
+        assert!(manager.list_bottles().await.unwrap().is_empty());
+        assert!(!manager.log_path(&steam).exists());
+        let trashed = manager.list_trash().await.unwrap();
+        assert_eq!(trashed.len(), 1);
+        assert_eq!((trashed[0].id, trashed[0].name.as_str()), (steam_id, "steam"));
+        assert!(trashed[0].size < 4096);
+
+        // the name is free while the bottle is in the trash, but it can't
+        // come back until it is again
+        bottle(&manager, "steam");
+        assert!(manager.restore_bottle("steam").await.is_err());
+        manager.destroy_bottle(&steam, true).await.unwrap();
+        let restored = manager.restore_bottle(&steam_id.to_string()[..8]).await.unwrap();
+        assert_eq!((restored.id, restored.name.as_str()), (steam_id, "steam"));
+        assert!(manager.log_path(&steam).exists());
+        assert!(!manager.bottle_path(&steam).join(TRASH_META).exists());
+        assert!(manager.list_trash().await.unwrap().is_empty());
+
+        manager.destroy_bottle(&steam, false).await.unwrap();
+        let (office, _) = bottle(&manager, "office");
+        manager.destroy_bottle(&office, false).await.unwrap();
+        let keep_all = Retention::default();
+        assert!(manager.enforce_retention(keep_all).await.unwrap().is_empty());
+        let keep_none = Retention {
+            max_age: None,
+            max_bytes: Some(0),
+        };
+        assert_eq!(manager.enforce_retention(keep_none).await.unwrap().len(), 2);
+        assert!(manager.list_trash().await.unwrap().is_empty());
+        assert!(manager.restore_bottle("steam").await.is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,237 @@
+use alloy_core::access::{bind_socket, socket_dir};
+use alloy_core::instance::{clear_stale_socket, InstanceLock};
+use alloy_core::limits::{read_request, Received};
+use alloy_core::{
+    Access, AccessPolicy, AlloyConfig, BottleManager, BottleName, ConnectionSlots, DaemonCommand, DaemonRequest,
+    DaemonResponse, RecipeCatalog, RecipeExecutor, Retention, RuntimeLocator,
+};
+use anyhow::Result;
+use serde_json::json;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+/// how often `retain_trash` looks for bottles to purge.
+const TRASH_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let config = Arc::new(AlloyConfig::load()?);
+    let policy = AccessPolicy::from_env(DaemonCommand::READ_ONLY)?;
+
+    // before the lock file lands in it, so a missing directory is created
+    // with the socket's mode
+    socket_dir(&config.socket, &policy)?;
+    let _instance = InstanceLock::acquire(&config.socket)?;
+    clear_stale_socket(&config.socket).await?;
+
+    let runtime = match &config.runtime {
+        Some(path) => RuntimeLocator::with_root(path.clone())?,
+        None => RuntimeLocator::detect()?,
+    };
+    let manager = Arc::new(BottleManager::new(runtime)?);
+    tokio::spawn(retain_trash(manager.clone(), config.trash_retention()));
+
+    let listener = bind_socket(&config.socket, &policy)?;
+    eprintln!("[alloy-daemon] listening on {}", config.socket.display());
+
+    let slots = ConnectionSlots::new(config.max_connections);
+    loop {
+        // take a slot first: with all of them in use, new clients wait in
+        // the listen backlog until someone disconnects
+        if slots.available() == 0 {
+            eprintln!("[alloy-daemon] {} clients connected, new ones wait", config.max_connections);
+        }
+        let slot = slots.acquire().await;
+        let (stream, _) = listener.accept().await?;
+        let access = match policy.authorize(&stream) {
+            Ok(Some(access)) => access,
+            Ok(None) => {
+                eprintln!("[alloy-daemon] rejected connection from another user");
+                continue;
+            }
+            Err(err) => {
+                eprintln!("[alloy-daemon] rejected connection: {err:?}");
+                continue;
+            }
+        };
+        let manager = manager.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            let _slot = slot;
+            if let Err(err) = handle_client(stream, manager, config, access).await {
+                eprintln!("[alloy-daemon] client error: {err:?}");
+            }
+        });
+    }
+}
+
+/// purges what the retention no longer keeps, at startup and once an hour.
+async fn retain_trash(manager: Arc<BottleManager>, retention: Retention) {
+    let mut check = tokio::time::interval(TRASH_CHECK_INTERVAL);
+    loop {
+        check.tick().await;
+        match manager.enforce_retention(retention).await {
+            Ok(purged) if !purged.is_empty() => {
+                eprintln!("[alloy-daemon] purged {} bottles past the trash retention", purged.len());
+            }
+            Ok(_) => {}
+            Err(err) => eprintln!("[alloy-daemon] trash retention failed: {err:?}"),
+        }
+    }
+}
+
+fn recipe_catalog(config: &AlloyConfig) -> RecipeCatalog {
+    match &config.recipes {
+        Some(root) => RecipeCatalog::with_root(root),
+        None => RecipeCatalog::discover(),
+    }
+}
+
+async fn handle_client(
+    stream: UnixStream,
+    manager: Arc<BottleManager>,
+    config: Arc<AlloyConfig>,
+    access: Access,
+) -> Result<()> {
+    let limits = config.limits();
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    loop {
+        let line = match read_request(&mut reader, &limits).await? {
+            Received::Line(line) => line,
+            Received::Closed | Received::Idle => break,
+            Received::TooLarge => {
+                let message = format!("request exceeds {} bytes", limits.max_request_bytes);
+                send_response(&mut writer, &DaemonResponse::error(uuid::Uuid::new_v4(), message)).await?;
+                break;
+            }
+            Received::Stalled => {
+                eprintln!("[alloy-daemon] dropping client that stopped halfway through a request");
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let request: DaemonRequest = match serde_json::from_str(&line) {
+            Ok(req) => req,
+            Err(err) => {
+                let response = DaemonResponse::error(uuid::Uuid::new_v4(), err.to_string());
+                send_response(&mut writer, &response).await?;
+                continue;
+            }
+        };
+
+        let response = if access.allows(request.command.name()) {
+            handle_request(manager.clone(), &config, request).await
+        } else {
+            DaemonResponse::error(
+                request.id,
+                format!("{} is not permitted on this connection", request.command.name()),
+            )
+        };
+        send_response(&mut writer, &response).await?;
+    }
+    Ok(())
+}
+
+async fn send_response<W>(writer: &mut W, response: &DaemonResponse) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let payload = serde_json::to_vec(response)?;
+    writer.write_all(&payload).await?;
+    writer.write_all(b"\n").await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+async fn handle_request(
+    manager: Arc<BottleManager>,
+    config: &AlloyConfig,
+    request: DaemonRequest,
+) -> DaemonResponse {
+    match request.command {
+        DaemonCommand::Ping => DaemonResponse::empty(request.id),
+        DaemonCommand::List => match manager.list_bottles().await {
+            Ok(bottles) => DaemonResponse::ok(request.id, json!(bottles)),
+            Err(err) => DaemonResponse::error(request.id, err.to_string()),
+        },
+        DaemonCommand::Create { name } => match BottleName::from_str(&name) {
+            Ok(parsed) => match manager.create_bottle(&parsed).await {
+                Ok(metadata) => DaemonResponse::ok(request.id, json!(metadata)),
+                Err(err) => DaemonResponse::error(request.id, err.to_string()),
+            },
+            Err(err) => DaemonResponse::error(request.id, err.to_string()),
+        },
+        DaemonCommand::Destroy { name, permanent } => match manager.resolve(&name).await {
+            Ok(parsed) => match manager.destroy_bottle(&parsed, permanent).await {
+                Ok(_) => DaemonResponse::empty(request.id),
+                Err(err) => DaemonResponse::error(request.id, err.to_string()),
+            },
+            Err(err) => DaemonResponse::error(request.id, err.to_string()),
+        },
+        DaemonCommand::Restore { name } => match manager.restore_bottle(&name).await {
+            Ok(summary) => DaemonResponse::ok(request.id, json!(summary)),
+            Err(err) => DaemonResponse::error(request.id, err.to_string()),
+        },
+        DaemonCommand::ListTrash => match manager.list_trash().await {
+            Ok(trashed) => DaemonResponse::ok(request.id, json!(trashed)),
+            Err(err) => DaemonResponse::error(request.id, err.to_string()),
+        },
+        DaemonCommand::PurgeTrash { name } => {
+            let purged = match name {
+                Some(name) => match manager.find_trashed(&name).await {
+                    Ok(trashed) => manager.purge_trashed(trashed.id).await.map(|_| vec![trashed.id]),
+                    Err(err) => Err(err),
+                },
+                None => manager.empty_trash().await,
+            };
+            match purged {
+                Ok(purged) => DaemonResponse::ok(request.id, json!({ "purged": purged })),
+                Err(err) => DaemonResponse::error(request.id, err.to_string()),
+            }
+        }
+        DaemonCommand::Run {
+            name,
+            executable,
+            args,
+            env,
+        } => match manager.resolve(&name).await {
+            Ok(parsed) => match manager.run_in_bottle(&parsed, &executable, &args, env).await {
+                Ok(code) => DaemonResponse::ok(request.id, json!({ "exit_code": code })),
+                Err(err) => DaemonResponse::error(request.id, err.to_string()),
+            },
+            Err(err) => DaemonResponse::error(request.id, err.to_string()),
+        },
+        DaemonCommand::ListRecipes => {
+            let catalog = recipe_catalog(config);
+            match catalog.list().await {
+                Ok(recipes) => DaemonResponse::ok(request.id, json!(recipes)),
+                Err(err) => DaemonResponse::error(request.id, err.to_string()),
+            }
+        }
+        DaemonCommand::ApplyRecipe { bottle, recipe } => match manager.resolve(&bottle).await {
+            Ok(parsed) => {
+                let catalog = recipe_catalog(config);
+                match catalog.load(&recipe).await {
+                    Ok(def) => {
+                        let mut executor = RecipeExecutor::new(&manager, parsed.clone());
+                        match executor.apply(&def).await {
+                            Ok(_) => DaemonResponse::ok(request.id, json!({ "applied": def.id })),
+                            Err(err) => DaemonResponse::error(request.id, err.to_string()),
+                        }
+                    }
+                    Err(err) => DaemonResponse::error(request.id, err.to_string()),
+                }
+            }
+            Err(err) => DaemonResponse::error(request.id, err.to_string()),
+        },
+    }
+}
+
@@ -1,22 +1,67 @@
-mod rpc_client;
-
 use std::path::PathBuf;
 use std::process::Stdio;
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use clap::{Parser, Subcommand};
-use rpc_client::RpcClient;
-use serde_json::json;
+use serde::Serialize;
+use silicon_alloy_client::api::{
+    methods, BottleAdoptParams, BottleCleanParams, BottleCloneParams, BottleCreateParams,
+    BottleDeleteParams, BottleExportParams, BottleImportParams, BottleRestoreParams,
+    BottleRunParams, BottleUpdateParams, BottleUsageParams, ChildPolicy, Empty, JobIdParams,
+    JobListParams, JobWaitParams, Method, RecipeApplyParams, ShortcutCreateParams, ShutdownParams,
+    SnapshotCreateParams, SnapshotListParams, SnapshotParams, TrashPurgeParams,
+};
+use silicon_alloy_client::{Client, ClientError};
+use silicon_alloy_daemon::local::LocalService;
+use silicon_alloy_shared::adopt::AdoptMode;
+use silicon_alloy_shared::usage::UsageCategory;
+use silicon_alloy_shared::BottleChanges;
 use tokio::process::Command;
 use uuid::Uuid;
 
 #[derive(Parser)]
-#[command(author, version, about = "manage silicon alloy wine bottles")]
+#[command(
+    author,
+    version,
+    about = "manage silicon alloy wine bottles",
+    after_help = "a BOTTLE is its uuid, a unique prefix of the uuid, or its name"
+)]
 struct Cli {
+    /// run the command in this process instead of asking the daemon
+    #[arg(long, global = true)]
+    no_daemon: bool,
+
+    /// wait for a busy bottle instead of failing
+    #[arg(long, global = true)]
+    wait: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+/// where calls go: the daemon over its socket, or handlers running in this
+/// process. either way the same method produces the same json.
+enum Backend {
+    Daemon(Client),
+    Local(LocalService),
+}
+
+impl Backend {
+    async fn call<M: Method>(&self, method: M, params: M::Params) -> Result<M::Result> {
+        match self {
+            Backend::Daemon(client) => Ok(client.call(method, params).await?),
+            Backend::Local(local) => local.call(method, params).await.map_err(|err| {
+                ClientError::Rpc {
+                    code: err.code.into(),
+                    message: err.message,
+                    data: err.data,
+                }
+                .into()
+            }),
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// run the daemon in the foreground
@@ -25,6 +70,29 @@ enum Commands {
     /// show daemon status information
     Info,
 
+    /// show the daemon protocol version and supported methods
+    Capabilities,
+
+    /// re-read the daemon config file
+    Reload,
+
+    /// show rpc, process and recipe counters
+    Metrics {
+        /// print in the prometheus text format instead of json
+        #[arg(long)]
+        prometheus: bool,
+    },
+
+    /// stop the daemon
+    Shutdown {
+        /// leave running wine processes alive, whatever the daemon's policy
+        #[arg(long, conflicts_with = "kill")]
+        detach: bool,
+        /// terminate running wine processes, whatever the daemon's policy
+        #[arg(long)]
+        kill: bool,
+    },
+
     /// list bottles managed by the daemon
     List,
 
@@ -41,19 +109,127 @@ enum Commands {
         channel: Option<String>,
     },
 
-    /// delete a bottle by id
+    /// move a bottle to the trash
     Delete {
-        id: Uuid,
+        bottle: String,
+        /// remove it for good instead
+        #[arg(long)]
+        permanent: bool,
+    },
+
+    /// bring a bottle back out of the trash
+    Restore {
+        bottle: String,
+        /// restore under a new name, for when the old one has been taken
+        #[arg(long)]
+        name: Option<String>,
+    },
+
+    /// show how much disk each bottle uses, largest first
+    Usage {
+        /// only this bottle
+        bottle: Option<String>,
+        /// read everything again instead of trusting the cache
+        #[arg(long)]
+        refresh: bool,
+    },
+
+    /// free space by removing temp files, installer caches, shader caches or logs
+    Clean {
+        bottle: String,
+        /// temp, installer_cache, shader_cache or logs
+        #[arg(required = true, value_parser = parse_category)]
+        categories: Vec<UsageCategory>,
+    },
+
+    /// remove the daemon logs it has rolled over from, keeping the current one
+    CleanLogs,
+
+    /// list or purge deleted bottles
+    Trash {
+        #[command(subcommand)]
+        command: TrashCommand,
+    },
+
+    /// rename a bottle; no other bottle may have the name
+    Rename {
+        bottle: String,
+        name: String,
+    },
+
+    /// set or remove a bottle's environment variables
+    Env {
+        #[command(subcommand)]
+        command: EnvCommand,
+    },
+
+    /// set a bottle's notes, or clear them when no text is given
+    Notes {
+        bottle: String,
+        text: Option<String>,
+    },
+
+    /// add or remove a bottle's tags
+    Tags {
+        #[command(subcommand)]
+        command: TagCommand,
+    },
+
+    /// copy a bottle into a new one, fixing up paths that name the original
+    Clone {
+        bottle: String,
+        name: String,
+    },
+
+    /// write a bottle to a portable .tar.zst archive
+    Export {
+        bottle: String,
+        path: PathBuf,
+    },
+
+    /// create a bottle from an archive written by export
+    Import {
+        path: PathBuf,
+        /// defaults to the exported bottle's name
+        #[arg(long)]
+        name: Option<String>,
+    },
+
+    /// make a bottle of an existing wine prefix, copying it in by default
+    Adopt {
+        path: PathBuf,
+        name: String,
+        /// wine version to run it with, matched against installed runtimes
+        #[arg(long)]
+        wine_version: String,
+        /// use the prefix where it is instead of copying it
+        #[arg(long)]
+        link: bool,
+        #[arg(long)]
+        wine_label: Option<String>,
+        #[arg(long)]
+        wine_path: Option<PathBuf>,
+        #[arg(long)]
+        channel: Option<String>,
     },
 
     /// run an executable inside a bottle
     Run {
-        id: Uuid,
+        bottle: String,
+        /// return a job id instead of waiting for the process to exit
+        #[arg(long)]
+        background: bool,
         executable: PathBuf,
         #[arg(trailing_var_arg = true)]
         args: Vec<String>,
     },
 
+    /// save and restore copies of a bottle
+    Snapshots {
+        #[command(subcommand)]
+        command: SnapshotCommand,
+    },
+
     /// recipe utilities
     Recipes {
         #[command(subcommand)]
@@ -71,6 +247,12 @@ enum Commands {
         #[command(subcommand)]
         command: ShortcutCommand,
     },
+
+    /// background job helpers
+    Jobs {
+        #[command(subcommand)]
+        command: JobCommand,
+    },
 }
 
 #[derive(Subcommand)]
@@ -80,24 +262,99 @@ enum RecipeCommand {
     /// apply a recipe to a bottle
     Apply {
         #[arg(long)]
-        bottle: Uuid,
+        bottle: String,
         #[arg(long)]
         recipe: String,
+        /// return a job id instead of waiting for the recipe to finish
+        #[arg(long)]
+        background: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum EnvCommand {
+    /// set variables given as KEY=VALUE, replacing any of the same name
+    Set {
+        bottle: String,
+        #[arg(required = true, value_parser = parse_variable)]
+        variables: Vec<(String, String)>,
+    },
+    /// remove variables
+    Unset {
+        bottle: String,
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TagCommand {
+    /// tag a bottle
+    Add {
+        bottle: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// remove tags from a bottle
+    Remove {
+        bottle: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
     },
 }
 
+#[derive(Subcommand)]
+enum SnapshotCommand {
+    /// snapshot a bottle's prefix and settings
+    Create { bottle: String, label: String },
+    /// list a bottle's snapshots, oldest first
+    List { bottle: String },
+    /// put a bottle back the way it was when the snapshot was taken
+    Restore { bottle: String, snapshot: Uuid },
+    /// delete a snapshot
+    Delete { bottle: String, snapshot: Uuid },
+}
+
+#[derive(Subcommand)]
+enum TrashCommand {
+    /// list deleted bottles, longest deleted first
+    List,
+    /// remove a deleted bottle for good, or every one when none is given
+    Purge { bottle: Option<String> },
+}
+
 #[derive(Subcommand)]
 enum RuntimeCommand {
     /// list known wine runtimes
     List,
 }
 
+#[derive(Subcommand)]
+enum JobCommand {
+    /// list known jobs
+    List {
+        /// only jobs for this bottle
+        #[arg(long)]
+        bottle: Option<String>,
+    },
+    /// show a single job
+    Status { id: Uuid },
+    /// block until a job finishes
+    Wait {
+        id: Uuid,
+        #[arg(long)]
+        timeout_ms: Option<u64>,
+    },
+    /// cancel a queued or running job
+    Cancel { id: Uuid },
+}
+
 #[derive(Subcommand)]
 enum ShortcutCommand {
     /// create a mac app bundle that launches a bottle executable
     Create {
         #[arg(long)]
-        bottle: Uuid,
+        bottle: String,
         #[arg(long)]
         name: String,
         #[arg(long)]
@@ -111,18 +368,49 @@ enum ShortcutCommand {
 async fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
     let cli = Cli::parse();
+    if let Commands::Daemon = cli.command {
+        return run_daemon().await;
+    }
+    let client = if cli.no_daemon {
+        if let Some(what) = needs_daemon(&cli.command) {
+            bail!("{what} needs a running daemon");
+        }
+        Backend::Local(LocalService::start().await?)
+    } else {
+        let client = Client::connect_default().await?;
+        if let Commands::Capabilities = cli.command {
+            return print(&client.call(methods::ServiceCapabilities, Empty {}).await?);
+        }
+        client.check_compatibility().await?;
+        Backend::Daemon(client)
+    };
+    let wait = cli.wait;
     match cli.command {
-        Commands::Daemon => run_daemon().await,
-        Commands::Info => {
-            let response = RpcClient::call("service.info", json!({})).await?;
-            println!("{}", serde_json::to_string_pretty(&response)?);
-            Ok(())
+        Commands::Daemon => unreachable!(),
+        Commands::Capabilities => {
+            print(&client.call(methods::ServiceCapabilities, Empty {}).await?)
+        }
+        Commands::Info => print(&client.call(methods::ServiceInfo, Empty {}).await?),
+        Commands::Reload => print(&client.call(methods::ServiceReload, Empty {}).await?),
+        Commands::Metrics { prometheus } => {
+            let metrics = client.call(methods::ServiceMetrics, Empty {}).await?;
+            if prometheus {
+                print!("{}", metrics.to_prometheus());
+                Ok(())
+            } else {
+                print(&metrics)
+            }
         }
-        Commands::List => {
-            let response = RpcClient::call("bottle.list", json!({})).await?;
-            println!("{}", serde_json::to_string_pretty(&response)?);
-            Ok(())
+        Commands::Shutdown { detach, kill } => {
+            let children = match (detach, kill) {
+                (true, _) => Some(ChildPolicy::Detach),
+                (_, true) => Some(ChildPolicy::Kill),
+                _ => None,
+            };
+            let params = ShutdownParams { children };
+            print(&client.call(methods::ServiceShutdown, params).await?)
         }
+        Commands::List => print(&client.call(methods::BottleList, Empty {}).await?),
         Commands::Create {
             name,
             wine_version,
@@ -130,66 +418,240 @@ async fn main() -> Result<()> {
             wine_path,
             channel,
         } => {
-            let response = RpcClient::call(
-                "bottle.create",
-                json!({
-                    "name": name,
-                    "wine_version": wine_version,
-                    "wine_label": wine_label,
-                    "wine_path": wine_path,
-                    "channel": channel,
-                }),
-            )
-            .await?;
-            println!("{}", serde_json::to_string_pretty(&response)?);
-            Ok(())
-        }
-        Commands::Delete { id } => {
-            let response = RpcClient::call("bottle.delete", json!({ "id": id })).await?;
-            println!("{}", serde_json::to_string_pretty(&response)?);
-            Ok(())
+            let params = BottleCreateParams {
+                name,
+                wine_version,
+                wine_label,
+                wine_path,
+                channel,
+            };
+            print(&client.call(methods::BottleCreate, params).await?)
+        }
+        Commands::Delete { bottle, permanent } => {
+            let params = BottleDeleteParams {
+                id: None,
+                bottle: Some(bottle),
+                wait,
+                permanent,
+            };
+            print(&client.call(methods::BottleDelete, params).await?)
+        }
+        Commands::Restore { bottle, name } => {
+            let params = BottleRestoreParams {
+                id: None,
+                bottle: Some(bottle),
+                name,
+            };
+            print(&client.call(methods::BottleRestore, params).await?)
+        }
+        Commands::Usage { bottle, refresh } => {
+            let params = BottleUsageParams {
+                id: None,
+                bottle,
+                refresh,
+            };
+            print(&client.call(methods::BottleUsage, params).await?)
+        }
+        Commands::Clean { bottle, categories } => {
+            let params = BottleCleanParams {
+                id: None,
+                bottle: Some(bottle),
+                categories,
+                wait,
+            };
+            print(&client.call(methods::BottleClean, params).await?)
+        }
+        Commands::CleanLogs => print(&client.call(methods::LogsClean, Empty {}).await?),
+        Commands::Trash { command } => match command {
+            TrashCommand::List => print(&client.call(methods::TrashList, Empty {}).await?),
+            TrashCommand::Purge { bottle } => {
+                let params = TrashPurgeParams { id: None, bottle };
+                print(&client.call(methods::TrashPurge, params).await?)
+            }
+        },
+        Commands::Rename { bottle, name } => {
+            let changes = BottleChanges {
+                name: Some(name),
+                ..Default::default()
+            };
+            update(&client, bottle, changes, wait).await
+        }
+        Commands::Env { command } => match command {
+            EnvCommand::Set { bottle, variables } => {
+                let changes = BottleChanges {
+                    env_set: variables.into_iter().collect(),
+                    ..Default::default()
+                };
+                update(&client, bottle, changes, wait).await
+            }
+            EnvCommand::Unset { bottle, names } => {
+                let changes = BottleChanges {
+                    env_unset: names,
+                    ..Default::default()
+                };
+                update(&client, bottle, changes, wait).await
+            }
+        },
+        Commands::Notes { bottle, text } => {
+            let changes = BottleChanges {
+                notes: Some(text.unwrap_or_default()),
+                ..Default::default()
+            };
+            update(&client, bottle, changes, wait).await
+        }
+        Commands::Tags { command } => match command {
+            TagCommand::Add { bottle, tags } => {
+                let changes = BottleChanges {
+                    add_tags: tags,
+                    ..Default::default()
+                };
+                update(&client, bottle, changes, wait).await
+            }
+            TagCommand::Remove { bottle, tags } => {
+                let changes = BottleChanges {
+                    remove_tags: tags,
+                    ..Default::default()
+                };
+                update(&client, bottle, changes, wait).await
+            }
+        },
+        Commands::Clone { bottle, name } => {
+            let params = BottleCloneParams {
+                id: None,
+                bottle: Some(bottle),
+                name,
+                wait,
+            };
+            print(&client.call(methods::BottleClone, params).await?)
+        }
+        Commands::Export { bottle, path } => {
+            let params = BottleExportParams {
+                id: None,
+                bottle: Some(bottle),
+                path: std::path::absolute(path)?,
+                wait,
+            };
+            print(&client.call(methods::BottleExport, params).await?)
+        }
+        Commands::Import { path, name } => {
+            let params = BottleImportParams {
+                path: std::path::absolute(path)?,
+                name,
+            };
+            print(&client.call(methods::BottleImport, params).await?)
+        }
+        Commands::Adopt {
+            path,
+            name,
+            wine_version,
+            link,
+            wine_label,
+            wine_path,
+            channel,
+        } => {
+            let params = BottleAdoptParams {
+                path: std::path::absolute(path)?,
+                mode: if link { AdoptMode::Link } else { AdoptMode::Copy },
+                create: BottleCreateParams {
+                    name,
+                    wine_version,
+                    wine_label,
+                    wine_path,
+                    channel,
+                },
+            };
+            print(&client.call(methods::BottleAdopt, params).await?)
         }
         Commands::Run {
-            id,
+            bottle,
+            background,
             executable,
             args,
         } => {
-            let response = RpcClient::call(
-                "bottle.run",
-                json!({
-                    "id": id,
-                    "executable": executable,
-                    "args": if args.is_empty() { None } else { Some(args) },
-                }),
-            )
-            .await?;
-            println!("{}", serde_json::to_string_pretty(&response)?);
-            Ok(())
+            let params = BottleRunParams {
+                id: None,
+                bottle: Some(bottle),
+                executable,
+                args: if args.is_empty() { None } else { Some(args) },
+                env: Default::default(),
+                background,
+                wait,
+            };
+            print(&client.call(methods::BottleRun, params).await?)
         }
-        Commands::Recipes { command } => match command {
-            RecipeCommand::List => {
-                let response = RpcClient::call("recipe.list", json!({})).await?;
-                println!("{}", serde_json::to_string_pretty(&response)?);
-                Ok(())
+        Commands::Snapshots { command } => match command {
+            SnapshotCommand::Create { bottle, label } => {
+                let params = SnapshotCreateParams {
+                    id: None,
+                    bottle: Some(bottle),
+                    label,
+                    wait,
+                };
+                print(&client.call(methods::BottleSnapshotCreate, params).await?)
             }
-            RecipeCommand::Apply { bottle, recipe } => {
-                let response = RpcClient::call(
-                    "recipe.apply",
-                    json!({
-                        "bottle_id": bottle,
-                        "recipe_id": recipe,
-                    }),
-                )
-                .await?;
-                println!("{}", serde_json::to_string_pretty(&response)?);
-                Ok(())
+            SnapshotCommand::List { bottle } => {
+                let params = SnapshotListParams {
+                    id: None,
+                    bottle: Some(bottle),
+                };
+                print(&client.call(methods::BottleSnapshotList, params).await?)
+            }
+            SnapshotCommand::Restore { bottle, snapshot } => {
+                let params = SnapshotParams {
+                    id: None,
+                    bottle: Some(bottle),
+                    snapshot,
+                    wait,
+                };
+                print(&client.call(methods::BottleSnapshotRestore, params).await?)
+            }
+            SnapshotCommand::Delete { bottle, snapshot } => {
+                let params = SnapshotParams {
+                    id: None,
+                    bottle: Some(bottle),
+                    snapshot,
+                    wait,
+                };
+                print(&client.call(methods::BottleSnapshotDelete, params).await?)
+            }
+        },
+        Commands::Recipes { command } => match command {
+            RecipeCommand::List => print(&client.call(methods::RecipeList, Empty {}).await?),
+            RecipeCommand::Apply {
+                bottle,
+                recipe,
+                background,
+            } => {
+                let params = RecipeApplyParams {
+                    bottle_id: None,
+                    bottle: Some(bottle),
+                    recipe_id: recipe,
+                    background,
+                    wait,
+                };
+                print(&client.call(methods::RecipeApply, params).await?)
             }
         },
         Commands::Runtime { command } => match command {
-            RuntimeCommand::List => {
-                let response = RpcClient::call("runtime.list", json!({})).await?;
-                println!("{}", serde_json::to_string_pretty(&response)?);
-                Ok(())
+            RuntimeCommand::List => print(&client.call(methods::RuntimeList, Empty {}).await?),
+        },
+        Commands::Jobs { command } => match command {
+            JobCommand::List { bottle } => {
+                let params = JobListParams {
+                    bottle,
+                    ..Default::default()
+                };
+                print(&client.call(methods::JobList, params).await?)
+            }
+            JobCommand::Status { id } => {
+                print(&client.call(methods::JobStatus, JobIdParams { id }).await?)
+            }
+            JobCommand::Wait { id, timeout_ms } => {
+                let params = JobWaitParams { id, timeout_ms };
+                print(&client.call(methods::JobWait, params).await?)
+            }
+            JobCommand::Cancel { id } => {
+                print(&client.call(methods::JobCancel, JobIdParams { id }).await?)
             }
         },
         Commands::Shortcut { command } => match command {
@@ -199,23 +661,70 @@ async fn main() -> Result<()> {
                 executable,
                 destination,
             } => {
-                let response = RpcClient::call(
-                    "shortcut.create",
-                    json!({
-                        "bottle_id": bottle,
-                        "name": name,
-                        "executable": executable,
-                        "destination": destination,
-                    }),
-                )
-                .await?;
-                println!("{}", serde_json::to_string_pretty(&response)?);
-                Ok(())
+                let params = ShortcutCreateParams {
+                    bottle_id: None,
+                    bottle: Some(bottle),
+                    name,
+                    executable,
+                    destination,
+                };
+                print(&client.call(methods::ShortcutCreate, params).await?)
             }
         },
     }
 }
 
+/// commands that only make sense against a daemon that outlives this
+/// process: its jobs, its configuration, its lifetime.
+fn needs_daemon(command: &Commands) -> Option<&'static str> {
+    match command {
+        Commands::Reload => Some("reload"),
+        Commands::Shutdown { .. } => Some("shutdown"),
+        Commands::Jobs { .. } => Some("jobs"),
+        Commands::Run {
+            background: true, ..
+        }
+        | Commands::Recipes {
+            command: RecipeCommand::Apply {
+                background: true, ..
+            },
+        } => Some("--background"),
+        _ => None,
+    }
+}
+
+async fn update(
+    client: &Backend,
+    bottle: String,
+    changes: BottleChanges,
+    wait: bool,
+) -> Result<()> {
+    let params = BottleUpdateParams {
+        id: None,
+        bottle: Some(bottle),
+        changes,
+        wait,
+    };
+    print(&client.call(methods::BottleUpdate, params).await?)
+}
+
+fn parse_variable(text: &str) -> Result<(String, String), String> {
+    match text.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got {text:?}")),
+    }
+}
+
+fn parse_category(text: &str) -> Result<UsageCategory, String> {
+    serde_json::from_value(serde_json::Value::String(text.to_string()))
+        .map_err(|_| format!("unknown category {text:?}"))
+}
+
+fn print<T: Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
 async fn run_daemon() -> Result<()> {
     let mut cmd = Command::new("silicon-alloy-daemon");
     cmd.stdin(Stdio::null())
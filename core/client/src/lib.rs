@@ -0,0 +1,375 @@
+//! typed client for the silicon alloy daemon.
+//!
+//! one [`Client`] holds a single connection to the daemon socket. calls are
+//! pipelined over it: every request gets its own id and responses are routed
+//! back to the caller that sent them, so a client can be shared between tasks.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+use silicon_alloy_shared::api::{
+    methods, Capabilities, DaemonEvent, EventFilter, EventNotification, LaggedNotification, Method,
+    EVENT_NOTIFICATION, LAGGED_NOTIFICATION, PROTOCOL_VERSION,
+};
+use silicon_alloy_shared::daemon_socket_path;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::UnixStream;
+use tokio::sync::{broadcast, oneshot};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+pub use silicon_alloy_shared::api;
+
+/// notifications buffered per listener before the oldest are dropped.
+const NOTIFICATION_BACKLOG: usize = 256;
+
+const METHOD_NOT_FOUND: i64 = -32601;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("unable to connect to daemon at {path:?}: {source}")]
+    Connect { path: PathBuf, source: io::Error },
+    #[error("daemon connection failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("daemon returned error {code}: {message}")]
+    Rpc {
+        code: i64,
+        message: String,
+        data: Option<Value>,
+    },
+    #[error("unable to decode daemon payload: {0}")]
+    Decode(#[from] serde_json::Error),
+    #[error("daemon is incompatible: {0}")]
+    Incompatible(String),
+    #[error("subscription fell behind and missed {0} events")]
+    Lagged(u64),
+    #[error("daemon closed the connection")]
+    Closed,
+}
+
+impl ClientError {
+    /// the json-rpc error code, when the daemon rejected the call.
+    pub fn code(&self) -> Option<i64> {
+        match self {
+            ClientError::Rpc { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+/// a message the daemon pushed without being asked, e.g. an event.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub method: String,
+    pub params: Value,
+}
+
+type Reply = Result<Value, ClientError>;
+
+#[derive(Default)]
+struct Pending {
+    calls: Mutex<HashMap<u64, oneshot::Sender<Reply>>>,
+}
+
+impl Pending {
+    fn insert(&self, id: u64, sender: oneshot::Sender<Reply>) {
+        self.lock().insert(id, sender);
+    }
+
+    fn take(&self, id: u64) -> Option<oneshot::Sender<Reply>> {
+        self.lock().remove(&id)
+    }
+
+    fn close(&self) {
+        for (_, sender) in self.lock().drain() {
+            let _ = sender.send(Err(ClientError::Closed));
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, oneshot::Sender<Reply>>> {
+        self.calls.lock().expect("pending call table poisoned")
+    }
+}
+
+pub struct Client {
+    writer: tokio::sync::Mutex<OwnedWriteHalf>,
+    pending: Arc<Pending>,
+    // only the reader task holds the sender, so listeners see `Closed` once
+    // the connection goes away
+    notifications: broadcast::Receiver<Notification>,
+    next_id: AtomicU64,
+    reader: JoinHandle<()>,
+}
+
+impl Client {
+    /// connects to the daemon listening on `path`.
+    pub async fn connect(path: impl AsRef<Path>) -> Result<Self, ClientError> {
+        let path = path.as_ref();
+        let stream = UnixStream::connect(path)
+            .await
+            .map_err(|source| ClientError::Connect {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        let (reader, writer) = stream.into_split();
+        let pending = Arc::new(Pending::default());
+        let (sender, notifications) = broadcast::channel(NOTIFICATION_BACKLOG);
+        let reader = tokio::spawn(read_loop(reader, pending.clone(), sender));
+        Ok(Self {
+            writer: tokio::sync::Mutex::new(writer),
+            pending,
+            notifications,
+            next_id: AtomicU64::new(1),
+            reader,
+        })
+    }
+
+    /// connects to the daemon at its usual per-user socket path.
+    pub async fn connect_default() -> Result<Self, ClientError> {
+        let path = daemon_socket_path().map_err(|err| io::Error::other(format!("{err:#}")))?;
+        Self::connect(path).await
+    }
+
+    /// calls a method from [`api::methods`] and decodes its result.
+    pub async fn call<M: Method>(
+        &self,
+        _method: M,
+        params: M::Params,
+    ) -> Result<M::Result, ClientError> {
+        let params = serde_json::to_value(params)?;
+        let result = self.call_raw(M::NAME, params).await?;
+        Ok(serde_json::from_value(result)?)
+    }
+
+    /// calls any method by name. useful for methods newer than this crate.
+    pub async fn call_raw(&self, method: &str, params: Value) -> Result<Value, ClientError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.pending.insert(id, sender);
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+        if let Err(err) = self.send(&request).await {
+            self.pending.take(id);
+            return Err(err);
+        }
+        receiver.await.unwrap_or(Err(ClientError::Closed))
+    }
+
+    /// asks the daemon what it supports and fails with
+    /// [`ClientError::Incompatible`] unless it speaks our protocol version.
+    /// daemons that predate `service.capabilities` are treated as incompatible.
+    pub async fn check_compatibility(&self) -> Result<Capabilities, ClientError> {
+        let capabilities = match self.call(methods::ServiceCapabilities, api::Empty {}).await {
+            Ok(capabilities) => capabilities,
+            Err(err) if err.code() == Some(METHOD_NOT_FOUND) => {
+                return Err(ClientError::Incompatible(
+                    "daemon does not report its capabilities".to_string(),
+                ))
+            }
+            Err(err) => return Err(err),
+        };
+        if capabilities.protocol_version != PROTOCOL_VERSION {
+            return Err(ClientError::Incompatible(format!(
+                "daemon speaks protocol {}, this client speaks {}",
+                capabilities.protocol_version, PROTOCOL_VERSION
+            )));
+        }
+        Ok(capabilities)
+    }
+
+    /// every notification the daemon sends on this connection from now on.
+    pub fn notifications(&self) -> broadcast::Receiver<Notification> {
+        self.notifications.resubscribe()
+    }
+
+    /// subscribes to daemon events matching `filter`.
+    pub async fn subscribe(&self, filter: EventFilter) -> Result<Subscription, ClientError> {
+        // listen before asking, events can arrive right behind the reply
+        let receiver = self.notifications();
+        let subscribed = self.call(methods::EventsSubscribe, filter).await?;
+        Ok(Subscription {
+            id: subscribed.subscription,
+            receiver,
+        })
+    }
+
+    async fn send(&self, request: &Value) -> Result<(), ClientError> {
+        let mut encoded = serde_json::to_vec(request)?;
+        encoded.push(b'\n');
+        let mut writer = self.writer.lock().await;
+        writer.write_all(&encoded).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// events delivered to one `events.subscribe` call. cancel it server-side
+/// with `events.unsubscribe`, or just drop the client.
+pub struct Subscription {
+    id: Uuid,
+    receiver: broadcast::Receiver<Notification>,
+}
+
+impl Subscription {
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// waits for the next event. returns [`ClientError::Lagged`] when events
+    /// were missed, after which the stream carries on.
+    pub async fn next(&mut self) -> Result<DaemonEvent, ClientError> {
+        loop {
+            let notification = match self.receiver.recv().await {
+                Ok(notification) => notification,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    return Err(ClientError::Lagged(missed))
+                }
+                Err(broadcast::error::RecvError::Closed) => return Err(ClientError::Closed),
+            };
+            match notification.method.as_str() {
+                EVENT_NOTIFICATION => {
+                    let params: EventNotification = serde_json::from_value(notification.params)?;
+                    if params.subscription == self.id {
+                        return Ok(params.event);
+                    }
+                }
+                LAGGED_NOTIFICATION => {
+                    let params: LaggedNotification = serde_json::from_value(notification.params)?;
+                    if params.subscription == self.id {
+                        return Err(ClientError::Lagged(params.missed));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+async fn read_loop(
+    reader: OwnedReadHalf,
+    pending: Arc<Pending>,
+    notifications: broadcast::Sender<Notification>,
+) {
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Ok(payload) = serde_json::from_str::<Value>(line.trim()) else {
+            continue;
+        };
+        match payload {
+            Value::Array(items) => {
+                for item in items {
+                    route(item, &pending, &notifications);
+                }
+            }
+            item => route(item, &pending, &notifications),
+        }
+    }
+    pending.close();
+}
+
+fn route(message: Value, pending: &Pending, notifications: &broadcast::Sender<Notification>) {
+    let Value::Object(mut message) = message else {
+        return;
+    };
+    if let Some(method) = message.get("method").and_then(Value::as_str) {
+        let notification = Notification {
+            method: method.to_string(),
+            params: message.remove("params").unwrap_or(Value::Null),
+        };
+        let _ = notifications.send(notification);
+        return;
+    }
+    let Some(id) = message.get("id").and_then(Value::as_u64) else {
+        return;
+    };
+    let Some(sender) = pending.take(id) else {
+        return;
+    };
+    let reply = match message.remove("error") {
+        Some(error) => Err(ClientError::Rpc {
+            code: error
+                .get("code")
+                .and_then(Value::as_i64)
+                .unwrap_or_default(),
+            message: error
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            data: error.get("data").cloned(),
+        }),
+        None => Ok(message.remove("result").unwrap_or(Value::Null)),
+    };
+    let _ = sender.send(reply);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::UnixListener;
+
+    #[tokio::test]
+    async fn responses_are_routed_by_id() {
+        let path =
+            std::env::temp_dir().join(format!("silicon-alloy-client-{}.sock", Uuid::new_v4()));
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let first: Value =
+                serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+            let second: Value =
+                serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+            // answer out of order, with a notification in between
+            let replies = [
+                json!({ "jsonrpc": "2.0", "id": second["id"], "error": { "code": -32601, "message": "unknown method" } }),
+                json!({ "jsonrpc": "2.0", "method": "events.event", "params": {} }),
+                json!({ "jsonrpc": "2.0", "id": first["id"], "result": { "status": "ok" } }),
+            ];
+            for reply in replies {
+                writer
+                    .write_all(format!("{reply}\n").as_bytes())
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let client = Client::connect(&path).await.unwrap();
+        let mut notifications = client.notifications();
+        let (ping, missing) =
+            tokio::join!(client.call(methods::ServicePing, api::Empty {}), async {
+                // make sure the ping goes out first
+                tokio::task::yield_now().await;
+                client.call_raw("no.such.method", Value::Null).await
+            });
+        assert_eq!(ping.unwrap().status, "ok");
+        assert_eq!(missing.unwrap_err().code(), Some(-32601));
+        assert_eq!(
+            notifications.recv().await.unwrap().method,
+            EVENT_NOTIFICATION
+        );
+
+        server.await.unwrap();
+        assert!(matches!(
+            client.call(methods::ServicePing, api::Empty {}).await,
+            Err(ClientError::Closed | ClientError::Io(_))
+        ));
+        let _ = std::fs::remove_file(&path);
+    }
+}
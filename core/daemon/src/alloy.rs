@@ -0,0 +1,346 @@
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::{json, Value};
+use silicon_alloy_shared::alloy::{
+    alloy_name, matching_bottles, AlloyBottle, AlloyCommand, AlloyRequest, AlloyResponse,
+    AlloyTrashedBottle,
+};
+use silicon_alloy_shared::api::{
+    methods, BottleCreateParams, BottleDeleteParams, BottleRestoreParams, BottleRunParams,
+    Deferred, Empty, Method, RecipeApplyParams, TrashPurgeParams,
+};
+use silicon_alloy_shared::{select_bottle, BottleRecord};
+use uuid::Uuid;
+
+use crate::rpc::{RpcRequest, Session};
+use crate::service::DaemonService;
+
+/// answers one alloyctl request. each command is carried out by the json-rpc
+/// methods doing the same job, so access rules, events and jobs all behave
+/// as they do for silicon-alloy clients.
+pub async fn handle(service: &DaemonService, session: &Session, value: Value) -> String {
+    // like alloy-daemon, a request too broken to carry an id gets a fresh one
+    let id = value
+        .get("id")
+        .and_then(|id| id.as_str())
+        .and_then(|id| id.parse().ok())
+        .unwrap_or_else(Uuid::new_v4);
+    let response = match serde_json::from_value::<AlloyRequest>(value) {
+        Err(err) => AlloyResponse::error(id, err.to_string()),
+        Ok(request) => {
+            let method = request.command.method();
+            let started = Instant::now();
+            let outcome = if session.allows(method) {
+                run(service, session, request.command).await
+            } else {
+                Err(anyhow!("{method} is not permitted on this connection"))
+            };
+            service
+                .metrics()
+                .record_call(method, started.elapsed(), outcome.is_err());
+            match outcome {
+                Ok(result) => AlloyResponse::ok(id, result),
+                Err(err) => AlloyResponse::error(id, format!("{err:#}")),
+            }
+        }
+    };
+    serde_json::to_string(&response).unwrap_or_default()
+}
+
+async fn run(
+    service: &DaemonService,
+    session: &Session,
+    command: AlloyCommand,
+) -> Result<Option<Value>> {
+    match command {
+        AlloyCommand::Ping => {
+            call(service, session, methods::ServicePing, Empty {}).await?;
+            Ok(None)
+        }
+        AlloyCommand::List => {
+            let root = bottle_root(service, session).await?;
+            let mut listed: Vec<AlloyBottle> = bottles(service, session)
+                .await?
+                .into_iter()
+                .map(|bottle| summary(&root, bottle))
+                .collect();
+            listed.sort_by(|a, b| a.name.cmp(&b.name));
+            Ok(Some(json!(listed)))
+        }
+        AlloyCommand::Create { name } => {
+            let Some(name) = alloy_name(&name) else {
+                bail!(
+                    "please pick a bottle name that uses letters, numbers, dashes, or underscores"
+                );
+            };
+            if !matching_bottles(&bottles(service, session).await?, &name).is_empty() {
+                bail!("bottle {name} already exists");
+            }
+            // alloy-daemon has a single runtime; the default channel's is the
+            // closest thing here
+            let info = call(service, session, methods::ServiceInfo, Empty {}).await?;
+            let channel = &info.config.default_channel.value;
+            let runtime = info
+                .runtimes
+                .iter()
+                .find(|runtime| &runtime.channel == channel)
+                .or(info.runtimes.first())
+                .ok_or_else(|| anyhow!("no wine runtime is installed"))?;
+            let params = BottleCreateParams {
+                name,
+                wine_version: runtime.version.clone(),
+                wine_label: None,
+                wine_path: None,
+                channel: Some(runtime.channel.clone()),
+            };
+            let reply = call(service, session, methods::BottleCreate, params).await?;
+            Ok(Some(json!(summary(&info.bottle_root, reply.bottle))))
+        }
+        AlloyCommand::Destroy { name, permanent } => {
+            let bottle = resolve(service, session, &name).await?;
+            let params = BottleDeleteParams {
+                id: Some(bottle.id),
+                bottle: None,
+                wait: false,
+                permanent,
+            };
+            call(service, session, methods::BottleDelete, params).await?;
+            Ok(None)
+        }
+        AlloyCommand::Restore { name } => {
+            let bottle = resolve_trashed(service, session, &name).await?;
+            let params = BottleRestoreParams {
+                id: Some(bottle.id),
+                bottle: None,
+                name: None,
+            };
+            let reply = call(service, session, methods::BottleRestore, params).await?;
+            let root = bottle_root(service, session).await?;
+            Ok(Some(json!(summary(&root, reply.bottle))))
+        }
+        AlloyCommand::ListTrash => {
+            let trash = call(service, session, methods::TrashList, Empty {}).await?;
+            let listed: Vec<AlloyTrashedBottle> = trash
+                .bottles
+                .into_iter()
+                .map(|trashed| AlloyTrashedBottle {
+                    name: alloy_name(&trashed.bottle.name)
+                        .unwrap_or_else(|| trashed.bottle.id.to_string()),
+                    id: trashed.bottle.id,
+                    deleted_at: trashed.deleted_at,
+                    size: trashed.size,
+                })
+                .collect();
+            Ok(Some(json!(listed)))
+        }
+        AlloyCommand::PurgeTrash { name } => {
+            let params = match name {
+                Some(name) => TrashPurgeParams {
+                    id: Some(resolve_trashed(service, session, &name).await?.id),
+                    bottle: None,
+                },
+                None => TrashPurgeParams::default(),
+            };
+            let purged = call(service, session, methods::TrashPurge, params).await?;
+            Ok(Some(json!({ "purged": purged.purged })))
+        }
+        AlloyCommand::Run {
+            name,
+            executable,
+            args,
+            env,
+        } => {
+            let bottle = resolve(service, session, &name).await?;
+            let params = BottleRunParams {
+                id: Some(bottle.id),
+                bottle: None,
+                executable: PathBuf::from(executable),
+                args: Some(args),
+                env: env.unwrap_or_default().into_iter().collect(),
+                background: false,
+                wait: false,
+            };
+            let Deferred::Done(result) = call(service, session, methods::BottleRun, params).await?
+            else {
+                bail!("bottle.run unexpectedly went to the background");
+            };
+            Ok(Some(
+                json!({ "exit_code": result.exit_status.unwrap_or_default() }),
+            ))
+        }
+        AlloyCommand::ListRecipes => {
+            let recipes = call(service, session, methods::RecipeList, Empty {}).await?;
+            Ok(Some(json!(recipes.recipes)))
+        }
+        AlloyCommand::ApplyRecipe { bottle, recipe } => {
+            let bottle = resolve(service, session, &bottle).await?;
+            let params = RecipeApplyParams {
+                bottle_id: Some(bottle.id),
+                bottle: None,
+                recipe_id: recipe,
+                background: false,
+                wait: false,
+            };
+            let Deferred::Done(applied) =
+                call(service, session, methods::RecipeApply, params).await?
+            else {
+                bail!("recipe.apply unexpectedly went to the background");
+            };
+            Ok(Some(json!({ "applied": applied.applied })))
+        }
+    }
+}
+
+/// alloyctl takes whatever silicon-alloy would: a uuid, an exact name or a
+/// uuid prefix. failing that, it takes the cleaned-up name `list` shows,
+/// which several bottles can share.
+async fn resolve(service: &DaemonService, session: &Session, name: &str) -> Result<BottleRecord> {
+    pick(&bottles(service, session).await?, name, "does not exist")
+}
+
+/// `resolve`, among the bottles in the trash.
+async fn resolve_trashed(
+    service: &DaemonService,
+    session: &Session,
+    name: &str,
+) -> Result<BottleRecord> {
+    let trash = call(service, session, methods::TrashList, Empty {}).await?;
+    let trashed: Vec<BottleRecord> = trash
+        .bottles
+        .into_iter()
+        .map(|trashed| trashed.bottle)
+        .collect();
+    pick(&trashed, name, "is not in the trash")
+}
+
+fn pick(bottles: &[BottleRecord], name: &str, missing: &str) -> Result<BottleRecord> {
+    if let Ok(bottle) = select_bottle(bottles, name) {
+        return Ok(bottle.clone());
+    }
+    let mut found = matching_bottles(bottles, name);
+    match found.len() {
+        0 => bail!("bottle {name} {missing}"),
+        1 => Ok(found.remove(0).clone()),
+        n => bail!("{n} bottles are called {name}; use the uuid of the one you mean"),
+    }
+}
+
+async fn bottles(service: &DaemonService, session: &Session) -> Result<Vec<BottleRecord>> {
+    let listed = call(service, session, methods::BottleList, Empty {}).await?;
+    Ok(listed.bottles.into_iter().map(|listed| listed.bottle).collect())
+}
+
+async fn bottle_root(service: &DaemonService, session: &Session) -> Result<PathBuf> {
+    Ok(call(service, session, methods::ServiceInfo, Empty {})
+        .await?
+        .bottle_root)
+}
+
+/// bottles whose name cleans up to nothing are listed under their uuid,
+/// which `resolve` accepts as well.
+fn summary(root: &Path, bottle: BottleRecord) -> AlloyBottle {
+    AlloyBottle {
+        name: alloy_name(&bottle.name).unwrap_or_else(|| bottle.id.to_string()),
+        id: bottle.id,
+        path: root.join(bottle.id.to_string()),
+        runtime: bottle.wine_runtime,
+    }
+}
+
+async fn call<M: Method>(
+    service: &DaemonService,
+    session: &Session,
+    _method: M,
+    params: M::Params,
+) -> Result<M::Result> {
+    let request = RpcRequest {
+        jsonrpc: None,
+        id: None,
+        method: M::NAME.to_string(),
+        params: serde_json::to_value(params)?,
+    };
+    let result = service.handle(request, session).await?;
+    Ok(serde_json::from_value(result)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::handle_payload;
+    use silicon_alloy_shared::access::Access;
+    use tokio::sync::mpsc;
+
+    async fn send(service: &DaemonService, command: Value) -> Value {
+        let (outbound, _pending) = mpsc::channel(8);
+        let session = Session::new(outbound, Access::Full);
+        let request = json!({ "id": Uuid::new_v4(), "command": command });
+        let line = handle_payload(service, &session, &request.to_string())
+            .await
+            .unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[tokio::test]
+    async fn alloyctl_commands_reach_the_service() {
+        let service = DaemonService::for_tests().await;
+        let ping = send(&service, json!({ "command": "ping" })).await;
+        assert_eq!(ping["status"]["state"], "ok");
+        assert!(ping.get("result").is_none());
+
+        let create = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "bottle.create",
+            "params": { "name": "My Steam", "wine_version": "9.0", "channel": "custom" },
+        });
+        let (outbound, _pending) = mpsc::channel(8);
+        let session = Session::new(outbound, Access::Full);
+        handle_payload(&service, &session, &create.to_string())
+            .await
+            .unwrap();
+
+        let list = send(&service, json!({ "command": "list" })).await;
+        assert_eq!(list["result"][0]["name"], "my-steam");
+
+        let destroy = send(
+            &service,
+            json!({ "command": "destroy", "name": "My Steam" }),
+        )
+        .await;
+        assert_eq!(destroy["status"]["state"], "ok");
+        let missing = send(
+            &service,
+            json!({ "command": "destroy", "name": "my-steam" }),
+        )
+        .await;
+        assert_eq!(
+            missing["status"]["message"],
+            "bottle my-steam does not exist"
+        );
+
+        // destroy only moved it to the trash
+        let trash = send(&service, json!({ "command": "list_trash" })).await;
+        assert_eq!(trash["result"][0]["name"], "my-steam");
+        let restore = send(
+            &service,
+            json!({ "command": "restore", "name": "my-steam" }),
+        )
+        .await;
+        assert_eq!(restore["result"]["name"], "my-steam");
+        let list = send(&service, json!({ "command": "list" })).await;
+        assert_eq!(list["result"][0]["name"], "my-steam");
+
+        let destroy = send(
+            &service,
+            json!({ "command": "destroy", "name": "my-steam", "permanent": true }),
+        )
+        .await;
+        assert_eq!(destroy["status"]["state"], "ok");
+        let trash = send(&service, json!({ "command": "list_trash" })).await;
+        assert_eq!(trash["result"], json!([]));
+        let purged = send(&service, json!({ "command": "purge_trash" })).await;
+        assert_eq!(purged["result"]["purged"], json!([]));
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::Result;
+use serde_json::Value;
+use silicon_alloy_shared::access::Access;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use crate::limits::{self, Limits, Received};
+use crate::rpc::{self, RpcResponse, Session, INVALID_REQUEST};
+use crate::service::DaemonService;
+use crate::shutdown::Shutdown;
+use crate::OUTBOUND_BACKLOG;
+
+/// serves one socket client until it hangs up, breaks a limit or the daemon
+/// shuts down.
+pub async fn handle_connection(
+    service: DaemonService,
+    stream: UnixStream,
+    access: Access,
+    limits: Limits,
+    shutdown: Shutdown,
+) -> Result<()> {
+    let _counted = service.metrics().connection();
+    let (reader, mut writer) = stream.into_split();
+    let (outbound, mut pending) = mpsc::channel::<String>(OUTBOUND_BACKLOG);
+    let writer_task = tokio::spawn(async move {
+        while let Some(line) = pending.recv().await {
+            writer.write_all(line.as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+            writer.flush().await?;
+        }
+        Ok::<_, std::io::Error>(())
+    });
+
+    let session = Session::new(outbound.clone(), access);
+    let mut reader = BufReader::new(reader);
+    loop {
+        let received = tokio::select! {
+            received = limits::read_request(&mut reader, &limits, session.has_subscriptions()) => {
+                received?
+            }
+            _ = shutdown.requested() => break,
+        };
+        let line = match received {
+            Received::Line(line) => line,
+            Received::Closed => break,
+            Received::TooLarge => {
+                // the rest of the line is still on its way, so there is no
+                // telling where the next request would start
+                let message = format!("request exceeds {} bytes", limits.max_request_bytes);
+                let response = RpcResponse::error(Value::Null, INVALID_REQUEST, message);
+                let _ = outbound.send(response.to_json()).await;
+                break;
+            }
+            Received::Idle => {
+                debug!("closing idle connection");
+                break;
+            }
+            Received::Stalled => {
+                warn!("closing connection that stopped halfway through a request");
+                break;
+            }
+        };
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if let Some(response) = rpc::handle_payload(&service, &session, trimmed).await {
+            if outbound.send(response).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    // dropping the session cancels its subscriptions, which releases the
+    // remaining senders and lets the writer drain and finish
+    drop(session);
+    drop(outbound);
+    writer_task.await??;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncBufReadExt;
+
+    #[tokio::test]
+    async fn oversized_requests_get_an_error_and_a_hangup() {
+        let service = DaemonService::for_tests().await;
+        let (client, server) = UnixStream::pair().unwrap();
+        let limits = Limits {
+            max_request_bytes: 64,
+            ..service.limits()
+        };
+        let connection = tokio::spawn(handle_connection(
+            service.clone(),
+            server,
+            Access::Full,
+            limits,
+            service.shutdown(),
+        ));
+
+        let (reader, mut writer) = client.into_split();
+        let request = format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"{}"}}"#,
+            "x".repeat(64)
+        );
+        writer.write_all(request.as_bytes()).await.unwrap();
+        writer.write_all(b"\n").await.unwrap();
+        let mut lines = BufReader::new(reader).lines();
+        let reply = lines.next_line().await.unwrap().unwrap();
+        let reply: Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["error"]["code"], INVALID_REQUEST);
+        assert_eq!(lines.next_line().await.unwrap(), None);
+        connection.await.unwrap().unwrap();
+    }
+}
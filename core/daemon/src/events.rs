@@ -0,0 +1,61 @@
+use silicon_alloy_shared::api::DaemonEvent;
+use tokio::sync::broadcast;
+
+/// how many events a slow subscriber may fall behind before it starts
+/// missing them. lagging subscribers are told how many they skipped.
+const EVENT_BACKLOG: usize = 256;
+
+/// fan-out point for daemon events. every subscriber gets its own receiver,
+/// so any number of connections can listen without coordinating.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<DaemonEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BACKLOG);
+        Self { sender }
+    }
+
+    pub fn emit(&self, event: DaemonEvent) {
+        // no subscribers is the common case and not an error
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DaemonEvent> {
+        self.sender.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use silicon_alloy_shared::api::{EventFilter, EventKind};
+    use uuid::Uuid;
+
+    #[test]
+    fn filter_matches_bottle_and_kind() {
+        let bottle = Uuid::new_v4();
+        let event = DaemonEvent::BottleUpdated { bottle_id: bottle };
+        assert!(EventFilter::default().matches(&event));
+
+        let by_bottle = EventFilter {
+            bottles: vec![Uuid::new_v4()],
+            kinds: vec![],
+        };
+        assert!(!by_bottle.matches(&event));
+
+        let by_kind = EventFilter {
+            bottles: vec![bottle],
+            kinds: vec![EventKind::BottleDeleted],
+        };
+        assert!(!by_kind.matches(&event));
+
+        let both = EventFilter {
+            bottles: vec![bottle],
+            kinds: vec![EventKind::BottleUpdated],
+        };
+        assert!(both.matches(&event));
+    }
+}
@@ -0,0 +1,598 @@
+use std::convert::Infallible;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use futures_util::{SinkExt, StreamExt};
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{self, HeaderValue};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::upgrade::Upgraded;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::{TokioIo, TokioTimer};
+use silicon_alloy_shared::access::Access;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, OwnedSemaphorePermit};
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::{Message, Role, WebSocketConfig};
+use tokio_tungstenite::WebSocketStream;
+use tracing::{debug, info, warn};
+
+use crate::limits;
+use crate::metrics::ConnectionGuard;
+use crate::rpc::{self, Session};
+use crate::service::DaemonService;
+use crate::shutdown::Shutdown;
+use crate::OUTBOUND_BACKLOG;
+
+/// file next to the daemon socket holding the current bearer token.
+pub const TOKEN_FILE: &str = "gateway.token";
+
+/// the daemon api over http and websocket, for clients that can't open a
+/// unix socket. it only listens on loopback and every request has to carry
+/// the bearer token, which changes on each start and is readable only by
+/// the daemon's user. token holders get full access.
+pub struct Gateway {
+    listener: TcpListener,
+    token: Arc<str>,
+    token_path: PathBuf,
+    clients: Arc<AtomicUsize>,
+}
+
+#[derive(Clone)]
+struct Shared {
+    service: DaemonService,
+    shutdown: Shutdown,
+    token: Arc<str>,
+    clients: Arc<AtomicUsize>,
+}
+
+/// counts a connection as active for as long as it is alive, both for the
+/// idle timeout and in the daemon's metrics.
+struct Active {
+    clients: Arc<AtomicUsize>,
+    _counted: ConnectionGuard,
+}
+
+impl Active {
+    fn new(shared: &Shared) -> Self {
+        shared.clients.fetch_add(1, Ordering::Relaxed);
+        Self {
+            clients: shared.clients.clone(),
+            _counted: shared.service.metrics().connection(),
+        }
+    }
+}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        self.clients.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Gateway {
+    /// binds 127.0.0.1:`port` and writes a fresh token to `token_dir`.
+    pub async fn bind(port: u16, token_dir: &Path) -> Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+            .await
+            .with_context(|| format!("unable to bind the gateway to port {port}"))?;
+        let token = new_token()?;
+        let token_path = token_dir.join(TOKEN_FILE);
+        write_token(&token_path, &token)?;
+        Ok(Self {
+            listener,
+            token: token.into(),
+            token_path,
+            clients: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// number of http and websocket clients currently connected.
+    pub fn clients(&self) -> Arc<AtomicUsize> {
+        self.clients.clone()
+    }
+
+    /// accepts clients until shutdown, then removes the token file. open
+    /// connections finish their current request and close.
+    pub async fn serve(self, service: DaemonService, shutdown: Shutdown) {
+        let shared = Shared {
+            service,
+            shutdown: shutdown.clone(),
+            token: self.token.clone(),
+            clients: self.clients.clone(),
+        };
+        // the same slots as the unix socket, so the cap covers both. a
+        // websocket keeps its connection's slot after the http side is done
+        let slots = shared.service.connection_slots().clone();
+        let mut slot = None;
+        loop {
+            let stream = tokio::select! {
+                permit = slots.acquire(), if slot.is_none() => {
+                    slot = Some(permit);
+                    continue;
+                }
+                accepted = self.listener.accept(), if slot.is_some() => match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(err) => {
+                        limits::accept_failed("gateway", err).await;
+                        continue;
+                    }
+                },
+                _ = shutdown.requested() => break,
+            };
+            let slot = Arc::new(slot.take().expect("clients are only accepted with a slot"));
+            let shared = shared.clone();
+            tokio::spawn(async move {
+                let _active = Active::new(&shared);
+                let shutdown = shared.shutdown.clone();
+                let mut builder = http1::Builder::new();
+                builder.timer(TokioTimer::new());
+                if let Some(timeout) = shared.service.limits().read_timeout {
+                    builder.header_read_timeout(timeout);
+                }
+                let connection = builder
+                    .serve_connection(
+                        TokioIo::new(stream),
+                        service_fn(move |request| handle(request, shared.clone(), slot.clone())),
+                    )
+                    .with_upgrades();
+                tokio::pin!(connection);
+                let result = tokio::select! {
+                    result = connection.as_mut() => result,
+                    _ = shutdown.requested() => {
+                        connection.as_mut().graceful_shutdown();
+                        connection.await
+                    }
+                };
+                if let Err(err) = result {
+                    warn!("gateway connection failed: {err}");
+                }
+            });
+        }
+        if let Err(err) = std::fs::remove_file(&self.token_path) {
+            warn!("unable to remove {}: {err}", self.token_path.display());
+        }
+    }
+}
+
+async fn handle(
+    request: Request<Incoming>,
+    shared: Shared,
+    slot: Arc<OwnedSemaphorePermit>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let response = if request.method() == Method::OPTIONS {
+        // cors preflight: the token, not the origin, is what gets checked
+        reply(StatusCode::NO_CONTENT, "")
+    } else if !authorized(&request, &shared.token) {
+        reply(StatusCode::UNAUTHORIZED, "missing or invalid bearer token")
+    } else {
+        match (request.method(), request.uri().path()) {
+            (&Method::POST, "/rpc") => post_rpc(request, &shared.service).await,
+            (&Method::GET, "/ws") => upgrade(request, shared, slot),
+            (&Method::GET, "/metrics") => metrics(&shared.service).await,
+            _ => reply(StatusCode::NOT_FOUND, "not found"),
+        }
+    };
+    Ok(with_cors(response))
+}
+
+/// one json-rpc payload per request, answered in the response body. there
+/// is no way to push notifications here, so subscriptions need `/ws`. the
+/// body has to arrive within the read timeout, like a line on the socket.
+async fn post_rpc(request: Request<Incoming>, service: &DaemonService) -> Response<Full<Bytes>> {
+    let limits = service.limits();
+    let reading = Limited::new(request.into_body(), limits.max_request_bytes).collect();
+    let Some(collected) = limits::within(limits.read_timeout, reading).await else {
+        warn!("closing gateway client that stopped halfway through a request body");
+        return reply(
+            StatusCode::REQUEST_TIMEOUT,
+            "request body not received in time",
+        );
+    };
+    let body = match collected {
+        Ok(body) => body.to_bytes(),
+        Err(err) if err.is::<LengthLimitError>() => {
+            return reply(StatusCode::PAYLOAD_TOO_LARGE, "request body too large")
+        }
+        Err(err) => return reply(StatusCode::BAD_REQUEST, &err.to_string()),
+    };
+    let Ok(payload) = std::str::from_utf8(&body) else {
+        return reply(StatusCode::BAD_REQUEST, "request body is not utf-8");
+    };
+    // nothing reads the outbound side: a subscription made here ends with
+    // the request
+    let (outbound, _) = mpsc::channel(1);
+    let session = Session::new(outbound, Access::Full);
+    match rpc::handle_payload(service, &session, payload).await {
+        Some(response) => {
+            let mut response = Response::new(Full::new(Bytes::from(response)));
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+            response
+        }
+        None => reply(StatusCode::NO_CONTENT, ""),
+    }
+}
+
+/// `service.metrics` in the prometheus text format. prometheus can send the
+/// token itself through `bearer_token_file` pointed at the token file.
+async fn metrics(service: &DaemonService) -> Response<Full<Bytes>> {
+    match service.metrics_snapshot().await {
+        Ok(snapshot) => {
+            let mut response = Response::new(Full::new(Bytes::from(snapshot.to_prometheus())));
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("text/plain; version=0.0.4"),
+            );
+            response
+        }
+        Err(err) => reply(StatusCode::INTERNAL_SERVER_ERROR, &format!("{err:#}")),
+    }
+}
+
+/// switches the connection to websocket. every text message is a json-rpc
+/// payload, and responses and event notifications come back as text
+/// messages, just like lines on the unix socket. the websocket holds on to
+/// the connection's slot until it closes.
+fn upgrade(
+    request: Request<Incoming>,
+    shared: Shared,
+    slot: Arc<OwnedSemaphorePermit>,
+) -> Response<Full<Bytes>> {
+    let wants_websocket = request
+        .headers()
+        .get(header::UPGRADE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+    let accept = match request.headers().get(header::SEC_WEBSOCKET_KEY) {
+        Some(key) if wants_websocket => derive_accept_key(key.as_bytes()),
+        _ => return reply(StatusCode::BAD_REQUEST, "expected a websocket upgrade"),
+    };
+    tokio::spawn(async move {
+        let _slot = slot;
+        match hyper::upgrade::on(request).await {
+            Ok(upgraded) => {
+                let _active = Active::new(&shared);
+                let config = WebSocketConfig {
+                    max_message_size: Some(shared.service.limits().max_request_bytes),
+                    ..Default::default()
+                };
+                let io = TokioIo::new(upgraded);
+                let socket = WebSocketStream::from_raw_socket(io, Role::Server, Some(config)).await;
+                if let Err(err) = serve_websocket(socket, &shared).await {
+                    warn!("websocket client failed: {err:#}");
+                }
+            }
+            Err(err) => warn!("websocket upgrade failed: {err}"),
+        }
+    });
+    let mut response = reply(StatusCode::SWITCHING_PROTOCOLS, "");
+    let headers = response.headers_mut();
+    headers.insert(header::CONNECTION, HeaderValue::from_static("upgrade"));
+    headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+    if let Ok(accept) = HeaderValue::from_str(&accept) {
+        headers.insert(header::SEC_WEBSOCKET_ACCEPT, accept);
+    }
+    response
+}
+
+async fn serve_websocket(
+    socket: WebSocketStream<TokioIo<Upgraded>>,
+    shared: &Shared,
+) -> Result<()> {
+    let (mut sink, mut stream) = socket.split();
+    let (outbound, mut pending) = mpsc::channel::<String>(OUTBOUND_BACKLOG);
+    let writer = tokio::spawn(async move {
+        while let Some(text) = pending.recv().await {
+            sink.send(Message::Text(text)).await?;
+        }
+        sink.close().await
+    });
+
+    // read once, so a reload applies to new clients only
+    let limits = shared.service.limits();
+    let session = Session::new(outbound.clone(), Access::Full);
+    loop {
+        // subscribers are exempt, waiting for events is what they're here for
+        let idle_timeout = limits.idle_timeout.filter(|_| !session.has_subscriptions());
+        let message = tokio::select! {
+            message = limits::within(idle_timeout, stream.next()) => message,
+            _ = shared.shutdown.requested() => break,
+        };
+        let Some(message) = message else {
+            debug!("closing idle websocket");
+            break;
+        };
+        let Some(message) = message else {
+            break;
+        };
+        match message? {
+            Message::Text(text) => {
+                if let Some(response) = rpc::handle_payload(&shared.service, &session, &text).await
+                {
+                    if outbound.send(response).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            Message::Close(_) => break,
+            // pings are answered by tungstenite itself
+            _ => {}
+        }
+    }
+
+    drop(session);
+    drop(outbound);
+    // the peer may already be gone, which is how most sessions end
+    let _ = writer.await?;
+    Ok(())
+}
+
+/// accepts `Authorization: Bearer <token>` and, because browsers can't set
+/// headers on a websocket, an `access_token` query parameter.
+fn authorized(request: &Request<Incoming>, token: &str) -> bool {
+    let header = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let query = request.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("access_token="))
+    });
+    header
+        .or(query)
+        .is_some_and(|given| same_token(given.as_bytes(), token.as_bytes()))
+}
+
+/// compares without stopping at the first mismatch, so response times don't
+/// leak how much of a guess was right.
+fn same_token(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len()
+        && given
+            .iter()
+            .zip(expected)
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn reply(status: StatusCode, message: &str) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from(message.to_string())));
+    *response.status_mut() = status;
+    response
+}
+
+fn with_cors(mut response: Response<Full<Bytes>>) -> Response<Full<Bytes>> {
+    let headers = response.headers_mut();
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_ORIGIN,
+        HeaderValue::from_static("*"),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_HEADERS,
+        HeaderValue::from_static("authorization, content-type"),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_METHODS,
+        HeaderValue::from_static("GET, POST, OPTIONS"),
+    );
+    response
+}
+
+fn new_token() -> Result<String> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes).context("unable to generate a gateway token")?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+fn write_token(path: &Path, token: &str) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("unable to write {}", path.display()))?;
+    // `mode` only applies to new files; a leftover one may be looser
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    file.write_all(token.as_bytes())?;
+    info!("gateway token written to {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    async fn start() -> (SocketAddr, String, DaemonService) {
+        start_with(DaemonService::for_tests().await).await
+    }
+
+    async fn start_with(service: DaemonService) -> (SocketAddr, String, DaemonService) {
+        let dir =
+            std::env::temp_dir().join(format!("silicon-alloy-gateway-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let gateway = Gateway::bind(0, &dir).await.unwrap();
+        let addr = gateway.local_addr().unwrap();
+        let token_path = dir.join(TOKEN_FILE);
+        let mode = std::fs::metadata(&token_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let token = std::fs::read_to_string(token_path).unwrap();
+        tokio::spawn(gateway.serve(service.clone(), service.shutdown()));
+        (addr, token, service)
+    }
+
+    async fn post(addr: SocketAddr, token: Option<&str>, body: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let auth = token
+            .map(|token| format!("Authorization: Bearer {token}\r\n"))
+            .unwrap_or_default();
+        let request = format!(
+            "POST /rpc HTTP/1.1\r\nHost: localhost\r\n{auth}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn http_requests_need_the_token() {
+        let (addr, token, _service) = start().await;
+        let ping = json!({ "jsonrpc": "2.0", "id": 1, "method": "service.ping" }).to_string();
+
+        let denied = post(addr, None, &ping).await;
+        assert!(denied.starts_with("HTTP/1.1 401"), "{denied}");
+        let denied = post(addr, Some("not-the-token"), &ping).await;
+        assert!(denied.starts_with("HTTP/1.1 401"), "{denied}");
+
+        let answered = post(addr, Some(&token), &ping).await;
+        assert!(answered.starts_with("HTTP/1.1 200"), "{answered}");
+        let body = answered.split("\r\n\r\n").nth(1).unwrap();
+        let body: Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["result"]["status"], "ok");
+    }
+
+    #[tokio::test]
+    async fn websocket_clients_receive_notifications() {
+        let (addr, token, _service) = start().await;
+        let url = format!("ws://{addr}/ws?access_token={token}");
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+        let subscribe = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "events.subscribe",
+            "params": { "kinds": ["bottle_created"] },
+        });
+        socket
+            .send(Message::Text(subscribe.to_string()))
+            .await
+            .unwrap();
+        let create = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "bottle.create",
+            "params": { "name": "gateway", "wine_version": "9.0" },
+        });
+        socket
+            .send(Message::Text(create.to_string()))
+            .await
+            .unwrap();
+
+        let mut received = Vec::new();
+        while received.len() < 3 {
+            if let Message::Text(text) = socket.next().await.unwrap().unwrap() {
+                received.push(serde_json::from_str::<Value>(&text).unwrap());
+            }
+        }
+        let pushed = received
+            .iter()
+            .find(|message| message["method"] == "events.event")
+            .unwrap();
+        assert_eq!(pushed["params"]["event"]["name"], "gateway");
+    }
+
+    #[tokio::test]
+    async fn websockets_hold_a_connection_slot() {
+        let (addr, token, service) = start().await;
+        let url = format!("ws://{addr}/ws?access_token={token}");
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        let ping = json!({ "jsonrpc": "2.0", "id": 1, "method": "service.ping" });
+        socket.send(Message::Text(ping.to_string())).await.unwrap();
+        socket.next().await.unwrap().unwrap();
+
+        // the gateway keeps one more slot in hand for its next client
+        let slots = service.connection_slots();
+        let wait = Duration::from_millis(100);
+        let mut held = Vec::new();
+        for _ in 0..slots.max() - 2 {
+            held.push(tokio::time::timeout(wait, slots.acquire()).await.unwrap());
+        }
+        assert!(tokio::time::timeout(wait, slots.acquire()).await.is_err());
+        socket.close(None).await.unwrap();
+        drop(socket);
+        let freed = tokio::time::timeout(Duration::from_secs(5), slots.acquire()).await;
+        assert!(freed.is_ok());
+    }
+
+    #[tokio::test]
+    async fn stalled_request_bodies_time_out() {
+        let service = DaemonService::for_tests_with("[limits]\nread_timeout = 1\n").await;
+        let (addr, token, _service) = start_with(service).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        // the headers promise more body than ever arrives
+        let request = format!(
+            "POST /rpc HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {token}\r\nContent-Length: 64\r\n\r\n{{\"jsonrpc\""
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = vec![0; 1024];
+        let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut response))
+            .await
+            .expect("the stalled body was waited on forever")
+            .unwrap();
+        let response = String::from_utf8_lossy(&response[..read]);
+        assert!(response.starts_with("HTTP/1.1 408"), "{response}");
+    }
+
+    #[tokio::test]
+    async fn idle_websockets_are_closed_unless_subscribed() {
+        let service = DaemonService::for_tests_with("[limits]\nclient_idle_timeout = 1\n").await;
+        let (addr, token, _service) = start_with(service).await;
+        let url = format!("ws://{addr}/ws?access_token={token}");
+
+        let (mut quiet, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let closed = tokio::time::timeout(Duration::from_secs(5), async {
+            while let Some(Ok(message)) = quiet.next().await {
+                if message.is_close() {
+                    break;
+                }
+            }
+        })
+        .await;
+        assert!(closed.is_ok(), "the idle websocket was left open");
+
+        let (mut subscriber, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let subscribe = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "events.subscribe",
+            "params": { "kinds": ["bottle_created"] },
+        });
+        subscriber
+            .send(Message::Text(subscribe.to_string()))
+            .await
+            .unwrap();
+        subscriber.next().await.unwrap().unwrap();
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        let ping = json!({ "jsonrpc": "2.0", "id": 2, "method": "service.ping" });
+        subscriber
+            .send(Message::Text(ping.to_string()))
+            .await
+            .unwrap();
+        let Message::Text(reply) = subscriber.next().await.unwrap().unwrap() else {
+            panic!("expected a reply to the ping");
+        };
+        let reply: Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["result"]["status"], "ok");
+    }
+}
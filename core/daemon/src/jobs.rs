@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::Value;
+use silicon_alloy_shared::api::{DaemonEvent, Job, JobState};
+use silicon_alloy_shared::unix_timestamp;
+use tokio::sync::{watch, Semaphore};
+use uuid::Uuid;
+
+use crate::events::EventBus;
+
+/// jobs allowed to run at once. anything beyond this waits in `queued`.
+const MAX_RUNNING_JOBS: usize = 4;
+
+/// finished jobs kept around for `job.status` / `job.list` before the
+/// oldest ones are forgotten.
+const MAX_FINISHED_JOBS: usize = 200;
+
+struct Entry {
+    job: Job,
+    cancel: watch::Sender<bool>,
+    state: watch::Sender<JobState>,
+}
+
+/// tracks long-running operations that were handed off by their caller.
+/// the work itself runs on its own task, so it outlives the connection that
+/// started it.
+#[derive(Clone)]
+pub struct JobRegistry {
+    entries: Arc<Mutex<HashMap<Uuid, Entry>>>,
+    slots: Arc<Semaphore>,
+    events: EventBus,
+}
+
+impl JobRegistry {
+    pub fn new(events: EventBus) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            slots: Arc::new(Semaphore::new(MAX_RUNNING_JOBS)),
+            events,
+        }
+    }
+
+    /// queues `work` and returns the job as it looks right now. dropping the
+    /// work future is how cancellation happens, so anything it spawns must be
+    /// tied to its lifetime (wine processes use `kill_on_drop`).
+    pub fn spawn<F>(&self, method: &str, bottle_id: Uuid, work: F) -> Job
+    where
+        F: Future<Output = Result<Value>> + Send + 'static,
+    {
+        let job = Job {
+            id: Uuid::new_v4(),
+            method: method.to_string(),
+            bottle_id,
+            state: JobState::Queued,
+            created_at: unix_timestamp(),
+            started_at: None,
+            finished_at: None,
+            result: None,
+            error: None,
+        };
+        let (cancel, mut cancelled) = watch::channel(false);
+        let (state, _) = watch::channel(JobState::Queued);
+        self.lock().insert(
+            job.id,
+            Entry {
+                job: job.clone(),
+                cancel,
+                state,
+            },
+        );
+        self.announce(&job);
+
+        let registry = self.clone();
+        let id = job.id;
+        tokio::spawn(async move {
+            let permit = tokio::select! {
+                permit = registry.slots.clone().acquire_owned() => permit.ok(),
+                _ = cancelled.wait_for(|flag| *flag) => None,
+            };
+            let Some(_permit) = permit else {
+                registry.finish(id, JobState::Cancelled, None, None);
+                return;
+            };
+            registry.update(id, |job| {
+                job.state = JobState::Running;
+                job.started_at = Some(unix_timestamp());
+            });
+            let outcome = tokio::select! {
+                outcome = work => Some(outcome),
+                _ = cancelled.wait_for(|flag| *flag) => None,
+            };
+            match outcome {
+                Some(Ok(value)) => registry.finish(id, JobState::Succeeded, Some(value), None),
+                Some(Err(err)) => {
+                    registry.finish(id, JobState::Failed, None, Some(format!("{err:#}")))
+                }
+                None => registry.finish(id, JobState::Cancelled, None, None),
+            }
+        });
+        job
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<Job> {
+        self.lock().get(&id).map(|entry| entry.job.clone())
+    }
+
+    pub fn list(&self) -> Vec<Job> {
+        let mut jobs: Vec<Job> = self.lock().values().map(|entry| entry.job.clone()).collect();
+        jobs.sort_by_key(|job| job.created_at);
+        jobs
+    }
+
+    /// whether any job is still queued or running.
+    pub fn has_active(&self) -> bool {
+        self.lock()
+            .values()
+            .any(|entry| !entry.job.state.is_terminal())
+    }
+
+    /// requests cancellation. returns `None` for unknown jobs; finished jobs
+    /// are returned unchanged.
+    pub fn cancel(&self, id: Uuid) -> Option<Job> {
+        let entries = self.lock();
+        let entry = entries.get(&id)?;
+        if !entry.job.state.is_terminal() {
+            let _ = entry.cancel.send(true);
+        }
+        Some(entry.job.clone())
+    }
+
+    /// waits until the job reaches a terminal state or `timeout` elapses,
+    /// whichever comes first, and returns the job as it stands.
+    pub async fn wait(&self, id: Uuid, timeout: Option<Duration>) -> Option<Job> {
+        let mut state = self.lock().get(&id)?.state.subscribe();
+        let finished = state.wait_for(|state| state.is_terminal());
+        match timeout {
+            Some(limit) => {
+                let _ = tokio::time::timeout(limit, finished).await;
+            }
+            None => {
+                let _ = finished.await;
+            }
+        }
+        self.get(id)
+    }
+
+    fn update(&self, id: Uuid, apply: impl FnOnce(&mut Job)) {
+        let job = {
+            let mut entries = self.lock();
+            let Some(entry) = entries.get_mut(&id) else {
+                return;
+            };
+            apply(&mut entry.job);
+            // send would drop the value while nobody is waiting
+            entry.state.send_replace(entry.job.state);
+            entry.job.clone()
+        };
+        self.announce(&job);
+    }
+
+    fn finish(&self, id: Uuid, state: JobState, result: Option<Value>, error: Option<String>) {
+        self.update(id, |job| {
+            job.state = state;
+            job.finished_at = Some(unix_timestamp());
+            job.result = result;
+            job.error = error;
+        });
+        self.prune();
+    }
+
+    fn prune(&self) {
+        let mut entries = self.lock();
+        let mut finished: Vec<(u64, Uuid)> = entries
+            .values()
+            .filter(|entry| entry.job.state.is_terminal())
+            .map(|entry| (entry.job.finished_at.unwrap_or_default(), entry.job.id))
+            .collect();
+        if finished.len() <= MAX_FINISHED_JOBS {
+            return;
+        }
+        finished.sort();
+        let excess = finished.len() - MAX_FINISHED_JOBS;
+        for (_, id) in finished.into_iter().take(excess) {
+            entries.remove(&id);
+        }
+    }
+
+    fn announce(&self, job: &Job) {
+        self.events.emit(DaemonEvent::JobUpdated {
+            bottle_id: job.bottle_id,
+            job_id: job.id,
+            method: job.method.clone(),
+            state: job.state,
+        });
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, Entry>> {
+        self.entries.lock().expect("job table poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn jobs_run_to_completion() {
+        let registry = JobRegistry::new(EventBus::new());
+        let job = registry.spawn("test.ok", Uuid::new_v4(), async { Ok(json!({ "done": true })) });
+        let finished = registry.wait(job.id, None).await.unwrap();
+        assert_eq!(finished.state, JobState::Succeeded);
+        assert_eq!(finished.result, Some(json!({ "done": true })));
+        assert!(finished.started_at.is_some() && finished.finished_at.is_some());
+
+        let failed = registry.spawn("test.err", Uuid::new_v4(), async {
+            Err(anyhow::anyhow!("boom"))
+        });
+        let failed = registry.wait(failed.id, None).await.unwrap();
+        assert_eq!(failed.state, JobState::Failed);
+        assert_eq!(failed.error.as_deref(), Some("boom"));
+    }
+
+    #[tokio::test]
+    async fn waits_on_finished_jobs_return_at_once() {
+        let registry = JobRegistry::new(EventBus::new());
+        let job = registry.spawn("test.ok", Uuid::new_v4(), async { Ok(Value::Null) });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let finished = tokio::time::timeout(Duration::from_secs(5), registry.wait(job.id, None))
+            .await
+            .expect("wait on a finished job hung")
+            .unwrap();
+        assert_eq!(finished.state, JobState::Succeeded);
+        let timed = registry
+            .wait(job.id, Some(Duration::from_secs(60)))
+            .await
+            .unwrap();
+        assert_eq!(timed.state, JobState::Succeeded);
+    }
+
+    #[tokio::test]
+    async fn cancel_drops_running_work() {
+        let registry = JobRegistry::new(EventBus::new());
+        let job = registry.spawn("test.slow", Uuid::new_v4(), async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(Value::Null)
+        });
+        let pending = registry
+            .wait(job.id, Some(Duration::from_millis(20)))
+            .await
+            .unwrap();
+        assert!(!pending.state.is_terminal());
+
+        registry.cancel(job.id).unwrap();
+        let cancelled = registry.wait(job.id, None).await.unwrap();
+        assert_eq!(cancelled.state, JobState::Cancelled);
+    }
+}
@@ -0,0 +1,20 @@
+//! the silicon alloy daemon. the `silicon-alloy-daemon` binary serves
+//! [`service::DaemonService`] on a socket; [`local::LocalService`] runs the
+//! same handlers inside another process.
+
+mod alloy;
+pub mod connection;
+mod events;
+pub mod gateway;
+mod jobs;
+pub mod limits;
+pub mod local;
+mod metrics;
+mod processes;
+pub mod rpc;
+pub mod service;
+pub mod shutdown;
+
+/// lines queued for a client before responses and event notifications
+/// start applying back-pressure to the connection.
+pub const OUTBOUND_BACKLOG: usize = 256;
@@ -0,0 +1,223 @@
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use silicon_alloy_shared::config::DaemonConfig;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+
+/// what a connection may send, taken from the config when the client
+/// connects so a reload applies to new clients only.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_request_bytes: usize,
+    pub idle_timeout: Option<Duration>,
+    pub read_timeout: Option<Duration>,
+}
+
+impl Limits {
+    pub fn from_config(config: &DaemonConfig) -> Self {
+        Self {
+            max_request_bytes: config.max_request_bytes.value,
+            idle_timeout: config.client_idle_timeout(),
+            read_timeout: config.read_timeout(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Received {
+    /// one request, without its newline
+    Line(String),
+    /// the client closed its end
+    Closed,
+    /// the line went past `max_request_bytes`; the rest of it is unread
+    TooLarge,
+    /// nothing arrived within the idle timeout
+    Idle,
+    /// a request was started but not finished within the read timeout
+    Stalled,
+}
+
+/// reads the next request line. the idle timeout covers the wait for its
+/// first byte and only applies when `may_idle` is false; clients waiting on
+/// event notifications have every reason to stay quiet.
+pub async fn read_request<R>(
+    reader: &mut R,
+    limits: &Limits,
+    may_idle: bool,
+) -> io::Result<Received>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let idle_timeout = limits.idle_timeout.filter(|_| !may_idle);
+    let waiting = async { reader.fill_buf().await.map(|buf| buf.is_empty()) };
+    match within(idle_timeout, waiting).await.transpose()? {
+        None => return Ok(Received::Idle),
+        Some(true) => return Ok(Received::Closed),
+        Some(false) => {}
+    }
+    // one byte past the limit is how an oversized line is told apart from
+    // one that fills it exactly
+    let cap = limits.max_request_bytes as u64 + 1;
+    let mut line = Vec::new();
+    let mut bounded = (&mut *reader).take(cap);
+    let reading = bounded.read_until(b'\n', &mut line);
+    if within(limits.read_timeout, reading)
+        .await
+        .transpose()?
+        .is_none()
+    {
+        return Ok(Received::Stalled);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    } else if line.len() as u64 == cap {
+        return Ok(Received::TooLarge);
+    }
+    String::from_utf8(line)
+        .map(Received::Line)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// runs `future`, giving up after `timeout` if there is one.
+pub(crate) async fn within<T>(
+    timeout: Option<Duration>,
+    future: impl Future<Output = T>,
+) -> Option<T> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+/// how long to stop accepting after running out of file descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// logs a failed accept so the listener can carry on. running out of file
+/// descriptors fails every accept until a client hangs up, so that also
+/// pauses briefly instead of spinning.
+pub async fn accept_failed(listener: &str, err: io::Error) {
+    warn!("{listener} accept failed: {err}");
+    if matches!(err.raw_os_error(), Some(libc::EMFILE | libc::ENFILE)) {
+        tokio::time::sleep(ACCEPT_BACKOFF).await;
+    }
+}
+
+/// caps how many clients are served at once, over the socket and the
+/// gateway together. while every slot is taken nobody is accepted, so new
+/// clients wait in the listen backlog instead of costing us anything.
+#[derive(Clone)]
+pub struct ConnectionSlots {
+    semaphore: Arc<Semaphore>,
+    max: usize,
+    /// set while clients are waiting, so the warning isn't repeated
+    saturated: Arc<AtomicBool>,
+}
+
+impl ConnectionSlots {
+    pub fn new(max: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max)),
+            max,
+            saturated: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// waits for a free slot, which is given back when the permit drops.
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        let permit = match self.semaphore.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                if !self.saturated.swap(true, Ordering::Relaxed) {
+                    warn!("all {} connection slots in use, new clients wait", self.max);
+                }
+                self.semaphore
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("connection slots are never closed")
+            }
+        };
+        self.saturated.store(false, Ordering::Relaxed);
+        permit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncWriteExt, BufReader};
+    use tokio::net::{UnixListener, UnixStream};
+
+    fn limits() -> Limits {
+        Limits {
+            max_request_bytes: 16,
+            idle_timeout: Some(Duration::from_millis(100)),
+            read_timeout: Some(Duration::from_millis(100)),
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_are_bounded_in_size_and_time() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let mut reader = BufReader::new(server);
+
+        // exactly at the limit is fine, one byte more is not
+        client.write_all(b"0123456789abcdef\n").await.unwrap();
+        let line = read_request(&mut reader, &limits(), false).await.unwrap();
+        assert_eq!(line, Received::Line("0123456789abcdef".to_string()));
+        client.write_all(b"0123456789abcdefg\n").await.unwrap();
+        let line = read_request(&mut reader, &limits(), false).await.unwrap();
+        assert_eq!(line, Received::TooLarge);
+
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let mut reader = BufReader::new(server);
+        assert_eq!(
+            read_request(&mut reader, &limits(), false).await.unwrap(),
+            Received::Idle
+        );
+        // subscribers are left alone; the write unblocks the read
+        let quiet = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            client.write_all(b"{\"id\"").await.unwrap();
+            client
+        });
+        let started = read_request(&mut reader, &limits(), true).await.unwrap();
+        assert_eq!(started, Received::Stalled);
+        drop(quiet.await.unwrap());
+        assert_eq!(
+            read_request(&mut reader, &limits(), false).await.unwrap(),
+            Received::Closed
+        );
+    }
+
+    #[tokio::test]
+    async fn clients_wait_for_a_free_slot() {
+        let path = std::env::temp_dir().join(format!(
+            "silicon-alloy-limits-{}.sock",
+            uuid::Uuid::new_v4()
+        ));
+        let listener = UnixListener::bind(&path).unwrap();
+        let slots = ConnectionSlots::new(1);
+
+        let _first = UnixStream::connect(&path).await.unwrap();
+        let _second = UnixStream::connect(&path).await.unwrap();
+        let held = slots.acquire().await;
+        listener.accept().await.unwrap();
+
+        let waiting = tokio::time::timeout(Duration::from_millis(100), slots.acquire()).await;
+        assert!(waiting.is_err(), "a second slot was handed out");
+        drop(held);
+        let _slot = slots.acquire().await;
+        listener.accept().await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::Result;
+use serde_json::Value;
+use silicon_alloy_shared::access::Access;
+use silicon_alloy_shared::api::Method;
+use silicon_alloy_shared::config::DaemonConfig;
+use tokio::sync::mpsc;
+
+use crate::rpc::{RpcError, RpcRequest, Session, INTERNAL_ERROR};
+use crate::service::DaemonService;
+
+/// a [`DaemonService`] owned by the calling process, for tools that have to
+/// work without a daemon running. it reads the same config file and bottle
+/// root as the daemon would, and bottle locks keep the two apart when both
+/// are around.
+pub struct LocalService {
+    service: DaemonService,
+    session: Session,
+}
+
+impl LocalService {
+    pub async fn start() -> Result<Self> {
+        let config = DaemonConfig::load()?;
+        Ok(Self::with_service(DaemonService::new(config, None).await?))
+    }
+
+    fn with_service(service: DaemonService) -> Self {
+        // nobody reads notifications in-process, so subscriptions end as
+        // soon as they have something to say
+        let (outbound, _) = mpsc::channel(1);
+        Self {
+            service,
+            session: Session::new(outbound, Access::Full),
+        }
+    }
+
+    /// calls a method from `api::methods` and decodes its result.
+    pub async fn call<M: Method>(
+        &self,
+        _method: M,
+        params: M::Params,
+    ) -> Result<M::Result, RpcError> {
+        let params = serde_json::to_value(params).map_err(decode_error)?;
+        let result = self.call_raw(M::NAME, params).await?;
+        serde_json::from_value(result).map_err(decode_error)
+    }
+
+    /// calls any method by name, failing the way the daemon would answer.
+    pub async fn call_raw(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let request = RpcRequest {
+            jsonrpc: None,
+            id: None,
+            method: method.to_string(),
+            params,
+        };
+        self.service
+            .handle(request, &self.session)
+            .await
+            .map_err(|err| RpcError::from_failure(&err))
+    }
+}
+
+fn decode_error(err: serde_json::Error) -> RpcError {
+    RpcError {
+        code: INTERNAL_ERROR,
+        message: format!("serialization failed: {err}"),
+        data: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::{BOTTLE_BUSY, INVALID_PARAMS};
+    use silicon_alloy_shared::api::{
+        methods, BottleCreateParams, BottleDeleteParams, BottleRestoreParams, Empty,
+    };
+    use silicon_alloy_shared::lock::LockMode;
+
+    #[tokio::test]
+    async fn calls_go_straight_to_the_handlers() {
+        let local = LocalService::with_service(DaemonService::for_tests().await);
+        let params = BottleCreateParams {
+            name: "local".to_string(),
+            wine_version: "9.0".to_string(),
+            wine_label: None,
+            wine_path: None,
+            channel: Some("custom".to_string()),
+        };
+        let created = local.call(methods::BottleCreate, params).await.unwrap();
+        let listed = local.call(methods::BottleList, Empty {}).await.unwrap();
+        assert_eq!(listed.bottles[0].bottle.id, created.bottle.id);
+
+        let err = local
+            .call_raw("bottle.delete", serde_json::json!({ "id": 3 }))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, INVALID_PARAMS);
+
+        // a bottle another process holds can't be deleted from under it
+        let root = local
+            .call(methods::ServiceInfo, Empty {})
+            .await
+            .unwrap()
+            .bottle_root;
+        let store = silicon_alloy_shared::BottleStore::with_root(root).unwrap();
+        let held = store
+            .lock(created.bottle.id, LockMode::Shared, "bottle.run")
+            .unwrap();
+        let listed = local.call(methods::BottleList, Empty {}).await.unwrap();
+        assert!(listed.bottles[0].busy);
+        // named by its name rather than its uuid
+        let delete = BottleDeleteParams {
+            id: None,
+            bottle: Some("local".to_string()),
+            wait: false,
+            permanent: false,
+        };
+        let err = local
+            .call(methods::BottleDelete, delete.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, BOTTLE_BUSY);
+        assert_eq!(err.data.unwrap()["holders"][0]["holder"], "bottle.run");
+        drop(held);
+        let deleted = local.call(methods::BottleDelete, delete).await.unwrap();
+        assert_eq!(deleted.deleted, created.bottle.id);
+        assert!(deleted.trashed);
+
+        // and brought back out of the trash by the same name
+        let trash = local.call(methods::TrashList, Empty {}).await.unwrap();
+        assert_eq!(trash.bottles[0].bottle.id, created.bottle.id);
+        let restore = BottleRestoreParams {
+            id: None,
+            bottle: Some("local".to_string()),
+            name: None,
+        };
+        let restored = local.call(methods::BottleRestore, restore).await.unwrap();
+        assert_eq!(restored.bottle.id, created.bottle.id);
+        assert!(local
+            .call(methods::TrashList, Empty {})
+            .await
+            .unwrap()
+            .bottles
+            .is_empty());
+    }
+}
@@ -1,53 +1,204 @@
-mod rpc;
-mod service;
+use std::sync::atomic::Ordering;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use once_cell::sync::OnceLock;
-use rpc::{RpcRequest, RpcResponse};
-use service::DaemonService;
-use silicon_alloy_shared::{daemon_socket_path, project_dirs};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::UnixListener;
-use tracing::{error, info};
+use silicon_alloy_daemon::connection::handle_connection;
+use silicon_alloy_daemon::gateway::Gateway;
+use silicon_alloy_daemon::limits;
+use silicon_alloy_daemon::service::{DaemonService, LogFilter};
+use silicon_alloy_daemon::shutdown::{IdleTimer, Signal, Signals};
+use silicon_alloy_shared::access::{self, Access, AccessPolicy};
+use silicon_alloy_shared::activation;
+use silicon_alloy_shared::api::ChildPolicy;
+use silicon_alloy_shared::config::DaemonConfig;
+use silicon_alloy_shared::instance::{self, InstanceLock};
+use silicon_alloy_shared::{log_dir, DAEMON_LOG};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::task::JoinSet;
+use tracing::{error, info, warn};
 use tracing_appender::non_blocking::WorkerGuard;
-use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+use tracing_subscriber::{fmt, prelude::*, reload, EnvFilter};
 
 static LOG_GUARD: OnceLock<WorkerGuard> = OnceLock::new();
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    setup_tracing()?;
-    let socket_path = socket_path()?;
+/// how long requests already being handled get to finish once shutdown
+/// starts. connections still busy after that are cut off.
+const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(10);
+
+/// how often an idle timeout, if configured, checks for activity.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+fn main() -> Result<()> {
+    // a socket handed over by the service manager belongs to it: we neither
+    // replace it on startup nor remove it on the way out. the activation
+    // variables are cleared here, while this is still the only thread
+    let inherited = unsafe { activation::inherited_listener()? };
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(serve(inherited))
+}
+
+async fn serve(inherited: Option<std::os::unix::net::UnixListener>) -> Result<()> {
+    let config = DaemonConfig::load()?;
+    let log_filter = setup_tracing(&config.log_filter.value)?;
+    if config.file_found {
+        info!("using configuration from {}", config.file.display());
+    }
+    let socket_path = config.socket_path.value.clone();
+    let policy = AccessPolicy::from_config(&config);
     info!("starting daemon on {}", socket_path.display());
-    if socket_path.exists() {
-        std::fs::remove_file(&socket_path)?;
+    if inherited.is_none() {
+        // before the lock file lands in it, so a missing directory is
+        // created with the socket's mode
+        access::socket_dir(&socket_path, &policy)?;
     }
-    let listener = UnixListener::bind(&socket_path)?;
-    let service = DaemonService::new().await?;
-    loop {
-        let (stream, _) = listener.accept().await?;
-        let svc = service.clone();
-        tokio::spawn(async move {
-            if let Err(err) = handle_connection(svc, stream).await {
-                error!("connection failed: {err:?}");
+    // held until the process exits; the kernel releases it even on a crash
+    let _instance = InstanceLock::acquire(&socket_path)?;
+    let (listener, activated) = match inherited {
+        Some(listener) => {
+            info!("using the socket passed in by the service manager");
+            (UnixListener::from_std(listener)?, true)
+        }
+        None => {
+            instance::clear_socket(&socket_path).await?;
+            (access::bind_socket(&socket_path, &policy)?, false)
+        }
+    };
+    let mut idle = IdleTimer::default();
+    let mut idle_check = tokio::time::interval(IDLE_CHECK_INTERVAL);
+    let mut signals = Signals::new()?;
+    let gateway_port = config.gateway_port.value;
+    let service = DaemonService::new(config, Some(log_filter)).await?;
+    let shutdown = service.shutdown();
+    // a purge cut short by the exit is finished on the next start
+    tokio::spawn(service.clone().retain_trash(shutdown.clone()));
+    let (gateway, gateway_clients) = match gateway_port {
+        Some(port) => {
+            // the token sits next to the socket, in the directory only we can read
+            let token_dir = socket_path.parent().unwrap_or(std::path::Path::new("."));
+            let gateway = Gateway::bind(port, token_dir).await?;
+            info!("gateway listening on http://{}", gateway.local_addr()?);
+            let clients = gateway.clients();
+            let task = tokio::spawn(gateway.serve(service.clone(), shutdown.clone()));
+            (Some(task), Some(clients))
+        }
+        None => (None, None),
+    };
+
+    let mut connections = JoinSet::new();
+    let slots = service.connection_slots().clone();
+    // a client is only accepted once there is a slot for it
+    let mut slot = None;
+    let children = loop {
+        tokio::select! {
+            permit = slots.acquire(), if slot.is_none() => slot = Some(permit),
+            accepted = listener.accept(), if slot.is_some() => {
+                let stream = match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(err) => {
+                        limits::accept_failed("socket", err).await;
+                        continue;
+                    }
+                };
+                let Some(access) = authorize(&service, &stream) else {
+                    continue;
+                };
+                let slot = slot.take();
+                let svc = service.clone();
+                let limits = service.limits();
+                let shutdown = shutdown.clone();
+                connections.spawn(async move {
+                    let _slot = slot;
+                    let result = handle_connection(svc, stream, access, limits, shutdown).await;
+                    if let Err(err) = result {
+                        error!("connection failed: {err:?}");
+                    }
+                });
             }
-        });
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            _ = idle_check.tick() => {
+                // read on every tick, a reload may have changed it
+                let Some(timeout) = service.idle_timeout() else {
+                    continue;
+                };
+                let quiet = connections.is_empty()
+                    && !service.is_busy()
+                    && gateway_clients
+                        .as_ref()
+                        .is_none_or(|clients| clients.load(Ordering::Relaxed) == 0);
+                if idle.observe(quiet, Instant::now(), timeout) {
+                    info!("idle for too long, exiting");
+                    shutdown.trigger(service.child_policy());
+                }
+            }
+            signal = signals.recv() => match signal {
+                Signal::Stop(name) => {
+                    info!("received {name}, shutting down");
+                    shutdown.trigger(service.child_policy());
+                }
+                Signal::Reload => {
+                    info!("received SIGHUP, reloading configuration");
+                    if let Err(err) = service.reload().await {
+                        error!("reload failed, keeping the current configuration: {err:#}");
+                    }
+                }
+            },
+            children = shutdown.requested() => break children,
+        }
+    };
+
+    // no new clients from here on; the ones already connected stop reading
+    // and get until the deadline to finish what they are doing
+    drop(listener);
+    if !activated {
+        if let Err(err) = std::fs::remove_file(&socket_path) {
+            warn!("unable to remove {}: {err}", socket_path.display());
+        }
+    }
+    let drained = tokio::time::timeout(SHUTDOWN_DEADLINE, async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_err() {
+        warn!(
+            "{} connections still busy after {:?}",
+            connections.len(),
+            SHUTDOWN_DEADLINE
+        );
+    }
+    if let Some(gateway) = gateway {
+        // stops at the same signal; this waits for the token file to go
+        let _ = gateway.await;
     }
+    service.release_children(children).await;
+    if children == ChildPolicy::Detach {
+        /*
+         * wine processes are spawned with kill_on_drop so cancelled jobs
+         * don't leak them. returning from main would drop every task and
+         * with them the children we promised to leave alone, so leave
+         * without running destructors. the lock and socket need no cleanup
+         * beyond what the kernel does.
+         */
+        info!("daemon stopped, wine processes left running");
+        std::process::exit(0);
+    }
+    connections.abort_all();
+    info!("daemon stopped");
+    Ok(())
 }
 
-fn setup_tracing() -> Result<()> {
-    let dirs = project_dirs()?;
-    let log_dir = dirs.data_dir().join("logs");
+fn setup_tracing(filter: &str) -> Result<LogFilter> {
+    let log_dir = log_dir()?;
     std::fs::create_dir_all(&log_dir)?;
 
-    let file_appender = tracing_appender::rolling::daily(&log_dir, "daemon.log");
+    let file_appender = tracing_appender::rolling::daily(&log_dir, DAEMON_LOG);
     let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
     let _ = LOG_GUARD.set(guard);
 
-    let env_filter = std::env::var("SILICON_ALLOY_LOG")
-        .ok()
-        .and_then(|value| EnvFilter::try_new(value).ok())
-        .unwrap_or_else(|| EnvFilter::new("info"));
+    let env_filter = EnvFilter::try_new(filter).unwrap_or_else(|_| EnvFilter::new("info"));
+    let (env_filter, handle) = reload::Layer::new(env_filter);
 
     let registry = tracing_subscriber::registry()
         .with(env_filter)
@@ -55,44 +206,22 @@ fn setup_tracing() -> Result<()> {
         .with(fmt::layer().with_ansi(false).with_writer(non_blocking));
 
     let _ = registry.try_init();
-    Ok(())
+    Ok(handle)
 }
 
-fn socket_path() -> Result<std::path::PathBuf> {
-    daemon_socket_path()
-}
-
-async fn handle_connection(service: DaemonService, stream: tokio::net::UnixStream) -> Result<()> {
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
-    let mut line = String::new();
-    loop {
-        line.clear();
-        let bytes = reader.read_line(&mut line).await?;
-        if bytes == 0 {
-            break;
-        }
-        let trimmed = line.trim();
-        if trimmed.is_empty() {
-            continue;
+/// looks up what the connecting user may do. peers that aren't covered by
+/// the policy are dropped before a single byte is read from them.
+fn authorize(service: &DaemonService, stream: &UnixStream) -> Option<Access> {
+    let uid = match stream.peer_cred() {
+        Ok(cred) => cred.uid(),
+        Err(err) => {
+            warn!("rejecting connection with unreadable credentials: {err}");
+            return None;
         }
-        let request: RpcRequest = match serde_json::from_str(trimmed) {
-            Ok(req) => req,
-            Err(err) => {
-                let response = RpcResponse::error(None, -32700, format!("invalid json: {err}"));
-                writer.write_all(response.to_json().as_bytes()).await?;
-                writer.write_all(b"\n").await?;
-                continue;
-            }
-        };
-        let response = match service.handle(request.clone()).await {
-            Ok(value) => RpcResponse::result(request.id.clone(), value),
-            Err(err) => RpcResponse::error(Some(request.id.clone()), -32000, format!("{err:#}")),
-        };
-        writer.write_all(response.to_json().as_bytes()).await?;
-        writer.write_all(b"\n").await?;
-        writer.flush().await?;
+    };
+    let access = service.access_for(uid);
+    if access.is_none() {
+        warn!("rejecting connection from uid {uid}");
     }
-    Ok(())
+    access
 }
-
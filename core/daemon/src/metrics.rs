@@ -0,0 +1,258 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use silicon_alloy_shared::api::methods;
+use silicon_alloy_shared::metrics::{self as snapshot, Bucket, MethodMetrics, StepMetrics};
+
+/// upper bounds, in seconds, shared by every histogram. rpc calls are mostly
+/// sub-millisecond, but `bottle.run` and recipe steps wait on wine installers.
+const BUCKETS: [f64; 12] = [
+    0.001, 0.005, 0.025, 0.1, 0.25, 1.0, 5.0, 15.0, 60.0, 300.0, 900.0, 3600.0,
+];
+
+/// where calls to methods the daemon doesn't know end up, so a misbehaving
+/// client can't grow the table.
+const OTHER_METHOD: &str = "other";
+
+/// counters kept in memory for `service.metrics`. recording a call touches
+/// a few atomics and nothing else; only the rarer process and recipe events
+/// take a lock.
+#[derive(Clone)]
+pub struct Metrics {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    /// filled once with every known method, never modified afterwards
+    methods: HashMap<&'static str, MethodStats>,
+    connections: AtomicU64,
+    processes_started: AtomicU64,
+    process_exits: Mutex<BTreeMap<String, u64>>,
+    recipes_succeeded: AtomicU64,
+    recipes_failed: AtomicU64,
+    steps: Mutex<BTreeMap<&'static str, Histogram>>,
+}
+
+#[derive(Default)]
+struct MethodStats {
+    calls: AtomicU64,
+    errors: AtomicU64,
+    latency: Histogram,
+}
+
+struct Histogram {
+    /// per bucket, not cumulative; the last slot is for values past them all
+    counts: [AtomicU64; BUCKETS.len() + 1],
+    sum_micros: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: std::array::from_fn(|_| AtomicU64::new(0)),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let slot = BUCKETS
+            .iter()
+            .position(|le| seconds <= *le)
+            .unwrap_or(BUCKETS.len());
+        self.counts[slot].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> snapshot::Histogram {
+        let mut total = 0;
+        let buckets = BUCKETS
+            .iter()
+            .zip(&self.counts)
+            .map(|(le, count)| {
+                total += count.load(Ordering::Relaxed);
+                Bucket {
+                    le: *le,
+                    count: total,
+                }
+            })
+            .collect();
+        snapshot::Histogram {
+            buckets,
+            count: total + self.counts[BUCKETS.len()].load(Ordering::Relaxed),
+            sum_seconds: self.sum_micros.load(Ordering::Relaxed) as f64 / 1e6,
+        }
+    }
+}
+
+/// keeps a connection counted for as long as it is alive.
+pub struct ConnectionGuard {
+    metrics: Metrics,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.metrics
+            .inner
+            .connections
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let methods = methods::NAMES
+            .iter()
+            .copied()
+            .chain([OTHER_METHOD])
+            .map(|name| (name, MethodStats::default()))
+            .collect();
+        Self {
+            inner: Arc::new(Inner {
+                methods,
+                connections: AtomicU64::new(0),
+                processes_started: AtomicU64::new(0),
+                process_exits: Mutex::new(BTreeMap::new()),
+                recipes_succeeded: AtomicU64::new(0),
+                recipes_failed: AtomicU64::new(0),
+                steps: Mutex::new(BTreeMap::new()),
+            }),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn record_call(&self, method: &str, elapsed: Duration, failed: bool) {
+        let methods = &self.inner.methods;
+        let Some(stats) = methods.get(method).or_else(|| methods.get(OTHER_METHOD)) else {
+            return;
+        };
+        stats.calls.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            stats.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        stats.latency.observe(elapsed);
+    }
+
+    pub fn connection(&self) -> ConnectionGuard {
+        self.inner.connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard {
+            metrics: self.clone(),
+        }
+    }
+
+    pub fn process_started(&self) {
+        self.inner.processes_started.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// `None` means a signal ended the process.
+    pub fn process_exited(&self, code: Option<i32>) {
+        let key = code.map_or_else(|| "signal".to_string(), |code| code.to_string());
+        *self.lock_exits().entry(key).or_default() += 1;
+    }
+
+    pub fn recipe_applied(&self, succeeded: bool) {
+        let counter = if succeeded {
+            &self.inner.recipes_succeeded
+        } else {
+            &self.inner.recipes_failed
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn recipe_step(&self, action: &'static str, elapsed: Duration) {
+        self.lock_steps()
+            .entry(action)
+            .or_default()
+            .observe(elapsed);
+    }
+
+    /// everything but the figures that have to be read off disk, which the
+    /// service fills in.
+    pub fn snapshot(&self) -> snapshot::MetricsSnapshot {
+        let inner = &self.inner;
+        let mut methods: Vec<MethodMetrics> = inner
+            .methods
+            .iter()
+            .map(|(name, stats)| MethodMetrics {
+                method: name.to_string(),
+                calls: stats.calls.load(Ordering::Relaxed),
+                errors: stats.errors.load(Ordering::Relaxed),
+                latency: stats.latency.snapshot(),
+            })
+            .collect();
+        methods.sort_by(|a, b| a.method.cmp(&b.method));
+        snapshot::MetricsSnapshot {
+            active_connections: inner.connections.load(Ordering::Relaxed),
+            methods,
+            processes_started: inner.processes_started.load(Ordering::Relaxed),
+            process_exits: self.lock_exits().clone(),
+            recipes_succeeded: inner.recipes_succeeded.load(Ordering::Relaxed),
+            recipes_failed: inner.recipes_failed.load(Ordering::Relaxed),
+            recipe_steps: self
+                .lock_steps()
+                .iter()
+                .map(|(action, duration)| StepMetrics {
+                    action: action.to_string(),
+                    duration: duration.snapshot(),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn lock_exits(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, u64>> {
+        self.inner.process_exits.lock().expect("metrics poisoned")
+    }
+
+    fn lock_steps(&self) -> std::sync::MutexGuard<'_, BTreeMap<&'static str, Histogram>> {
+        self.inner.steps.lock().expect("metrics poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histograms_are_cumulative() {
+        let metrics = Metrics::default();
+        metrics.record_call("bottle.list", Duration::from_micros(500), false);
+        metrics.record_call("bottle.list", Duration::from_millis(50), true);
+        metrics.record_call("bottle.list", Duration::from_secs(7200), false);
+        metrics.record_call("no.such.method", Duration::ZERO, true);
+
+        let snapshot = metrics.snapshot();
+        let list = snapshot
+            .methods
+            .iter()
+            .find(|m| m.method == "bottle.list")
+            .unwrap();
+        assert_eq!((list.calls, list.errors), (3, 1));
+        let counts: Vec<u64> = list.latency.buckets.iter().map(|b| b.count).collect();
+        assert_eq!(counts[..4], [1, 1, 1, 2]);
+        assert_eq!(*counts.last().unwrap(), 2);
+        // the two-hour call only shows up in the total
+        assert_eq!(list.latency.count, 3);
+        assert!(!snapshot
+            .methods
+            .iter()
+            .any(|m| m.method == "no.such.method"));
+        let other = snapshot
+            .methods
+            .iter()
+            .find(|m| m.method == OTHER_METHOD)
+            .unwrap();
+        assert_eq!(other.calls, 1);
+
+        let guard = metrics.connection();
+        assert_eq!(metrics.snapshot().active_connections, 1);
+        drop(guard);
+        assert_eq!(metrics.snapshot().active_connections, 0);
+    }
+}
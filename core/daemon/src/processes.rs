@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::process::Command;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// how long `wineserver -k` gets per bottle before we stop waiting on it.
+const WINESERVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+struct Tracked {
+    bottle_id: Uuid,
+    prefix: PathBuf,
+    wineserver: PathBuf,
+}
+
+/// wine processes started by this daemon, keyed by pid. entries live exactly
+/// as long as the `ProcessGuard` returned by `track`.
+#[derive(Clone, Default)]
+pub struct ProcessTable {
+    entries: Arc<Mutex<HashMap<u32, Tracked>>>,
+}
+
+pub struct ProcessGuard {
+    table: ProcessTable,
+    pid: u32,
+}
+
+impl Drop for ProcessGuard {
+    fn drop(&mut self) {
+        self.table.lock().remove(&self.pid);
+    }
+}
+
+impl ProcessTable {
+    pub fn track(
+        &self,
+        pid: u32,
+        bottle_id: Uuid,
+        prefix: PathBuf,
+        wineserver: PathBuf,
+    ) -> ProcessGuard {
+        self.lock().insert(
+            pid,
+            Tracked {
+                bottle_id,
+                prefix,
+                wineserver,
+            },
+        );
+        ProcessGuard {
+            table: self.clone(),
+            pid,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    pub fn runs_in(&self, bottle_id: Uuid) -> bool {
+        self.lock()
+            .values()
+            .any(|tracked| tracked.bottle_id == bottle_id)
+    }
+
+    /// asks every tracked process to exit, then shuts down the wineserver of
+    /// each bottle involved so the windows programs it hosts go too.
+    pub async fn terminate_all(&self) {
+        let tracked: Vec<(u32, Tracked)> = self
+            .lock()
+            .iter()
+            .map(|(pid, tracked)| (*pid, tracked.clone()))
+            .collect();
+        for (pid, process) in &tracked {
+            info!("stopping wine process {pid} in bottle {}", process.bottle_id);
+            // the pid is still ours: the entry is dropped only after `wait`
+            // has reaped the child
+            unsafe {
+                libc::kill(*pid as libc::pid_t, libc::SIGTERM);
+            }
+        }
+        let mut servers: Vec<(PathBuf, PathBuf)> = tracked
+            .into_iter()
+            .map(|(_, tracked)| (tracked.prefix, tracked.wineserver))
+            .collect();
+        servers.sort();
+        servers.dedup();
+        for (prefix, wineserver) in servers {
+            let mut cmd = Command::new(&wineserver);
+            cmd.arg("-k").env("WINEPREFIX", &prefix).kill_on_drop(true);
+            match tokio::time::timeout(WINESERVER_TIMEOUT, cmd.status()).await {
+                Ok(Ok(_)) => info!("stopped wineserver for {}", prefix.display()),
+                Ok(Err(err)) => warn!("unable to run {}: {err}", wineserver.display()),
+                Err(_) => warn!("wineserver for {} did not stop in time", prefix.display()),
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u32, Tracked>> {
+        self.entries.lock().expect("process table poisoned")
+    }
+}
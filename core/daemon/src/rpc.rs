@@ -1,18 +1,56 @@
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Value};
+use silicon_alloy_shared::access::Access;
+use silicon_alloy_shared::alloy::is_alloy_request;
+use silicon_alloy_shared::lock::BottleBusy;
+use tokio::sync::mpsc;
+use tokio::task::AbortHandle;
+use uuid::Uuid;
+
+use crate::alloy;
+use crate::service::DaemonService;
+
+pub const JSONRPC_VERSION: &str = "2.0";
+
+pub const PARSE_ERROR: i32 = -32700;
+pub const INVALID_REQUEST: i32 = -32600;
+pub const METHOD_NOT_FOUND: i32 = -32601;
+pub const INVALID_PARAMS: i32 = -32602;
+pub const INTERNAL_ERROR: i32 = -32603;
+/// server-defined: the peer's access level does not cover the method.
+pub const FORBIDDEN: i32 = -32001;
+/// server-defined: the bottle is locked by someone else and the caller did
+/// not ask to wait. `data` names who holds it.
+pub const BOTTLE_BUSY: i32 = -32002;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct RpcRequest {
+    #[serde(default)]
+    pub jsonrpc: Option<String>,
+    /// `None` when the member is absent (a notification), `Some(Value::Null)`
+    /// when the client explicitly sent `"id": null`.
+    #[serde(default, deserialize_with = "present")]
     pub id: Option<Value>,
     pub method: String,
     #[serde(default)]
     pub params: Value,
 }
 
+fn present<'de, D>(deserializer: D) -> Result<Option<Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Value::deserialize(deserializer).map(Some)
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct RpcResponse {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub id: Option<Value>,
+    pub jsonrpc: &'static str,
+    pub id: Value,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub result: Option<Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -23,31 +61,93 @@ pub struct RpcResponse {
 pub struct RpcError {
     pub code: i32,
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+/// errors raised by `DaemonService` that map onto a specific json-rpc code.
+/// anything else surfacing from a handler is reported as an internal error.
+#[derive(Debug, thiserror::Error)]
+pub enum RpcFault {
+    #[error("unknown method {0}")]
+    MethodNotFound(String),
+    #[error("{0}")]
+    InvalidParams(String),
+    #[error("{0} is not permitted on this connection")]
+    Forbidden(String),
+}
+
+impl RpcError {
+    /// the error object a failed handler is reported with.
+    pub fn from_failure(err: &anyhow::Error) -> Self {
+        if let Some(busy) = err.downcast_ref::<BottleBusy>() {
+            return Self {
+                code: BOTTLE_BUSY,
+                message: format!("{err:#}"),
+                data: serde_json::to_value(busy).ok(),
+            };
+        }
+        let code = err
+            .downcast_ref::<RpcFault>()
+            .map(RpcFault::code)
+            .unwrap_or(INTERNAL_ERROR);
+        Self {
+            code,
+            message: format!("{err:#}"),
+            data: None,
+        }
+    }
+}
+
+impl RpcFault {
+    pub fn code(&self) -> i32 {
+        match self {
+            RpcFault::MethodNotFound(_) => METHOD_NOT_FOUND,
+            RpcFault::InvalidParams(_) => INVALID_PARAMS,
+            RpcFault::Forbidden(_) => FORBIDDEN,
+        }
+    }
 }
 
 impl RpcResponse {
-    pub fn result(id: Option<Value>, value: Value) -> Self {
+    pub fn result(id: Value, value: Value) -> Self {
         Self {
+            jsonrpc: JSONRPC_VERSION,
             id,
             result: Some(value),
             error: None,
         }
     }
 
-    pub fn error(id: Option<Value>, code: i32, message: String) -> Self {
+    pub fn error(id: Value, code: i32, message: String) -> Self {
         Self {
+            jsonrpc: JSONRPC_VERSION,
             id,
             result: None,
-            error: Some(RpcError { code, message }),
+            error: Some(RpcError {
+                code,
+                message,
+                data: None,
+            }),
+        }
+    }
+
+    pub fn from_failure(id: Value, err: &anyhow::Error) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION,
+            id,
+            result: None,
+            error: Some(RpcError::from_failure(err)),
         }
     }
 
     pub fn to_json(&self) -> String {
         serde_json::to_string(self).unwrap_or_else(|err| {
             serde_json::json!({
+                "jsonrpc": JSONRPC_VERSION,
                 "id": self.id,
                 "error": {
-                    "code": -32603,
+                    "code": INTERNAL_ERROR,
                     "message": format!("serialization failed: {err}")
                 }
             })
@@ -56,3 +156,492 @@ impl RpcResponse {
     }
 }
 
+/// per-connection state. `outbound` carries every line written back to the
+/// client, so responses and server-pushed notifications share one writer.
+pub struct Session {
+    outbound: mpsc::Sender<String>,
+    access: Access,
+    subscriptions: Mutex<HashMap<Uuid, AbortHandle>>,
+}
+
+impl Session {
+    pub fn new(outbound: mpsc::Sender<String>, access: Access) -> Self {
+        Self {
+            outbound,
+            access,
+            subscriptions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn allows(&self, method: &str) -> bool {
+        self.access.allows(method)
+    }
+
+    pub fn outbound(&self) -> mpsc::Sender<String> {
+        self.outbound.clone()
+    }
+
+    pub fn add_subscription(&self, id: Uuid, task: AbortHandle) {
+        self.subscriptions
+            .lock()
+            .expect("subscription table poisoned")
+            .insert(id, task);
+    }
+
+    pub fn has_subscriptions(&self) -> bool {
+        !self
+            .subscriptions
+            .lock()
+            .expect("subscription table poisoned")
+            .is_empty()
+    }
+
+    pub fn remove_subscription(&self, id: Uuid) -> bool {
+        let removed = self
+            .subscriptions
+            .lock()
+            .expect("subscription table poisoned")
+            .remove(&id);
+        match removed {
+            Some(task) => {
+                task.abort();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Ok(subscriptions) = self.subscriptions.get_mut() {
+            for (_, task) in subscriptions.drain() {
+                task.abort();
+            }
+        }
+    }
+}
+
+/// a server-initiated message. notifications carry no id and expect no reply.
+pub fn notification(method: &str, params: Value) -> String {
+    json!({
+        "jsonrpc": JSONRPC_VERSION,
+        "method": method,
+        "params": params,
+    })
+    .to_string()
+}
+
+/// handles one line from the socket, which may hold a single request or a
+/// batch. returns `None` when nothing should be written back, i.e. the line
+/// only carried notifications.
+pub async fn handle_payload(
+    service: &DaemonService,
+    session: &Session,
+    payload: &str,
+) -> Option<String> {
+    let value: Value = match serde_json::from_str(payload) {
+        Ok(value) => value,
+        Err(err) => {
+            let response =
+                RpcResponse::error(Value::Null, PARSE_ERROR, format!("invalid json: {err}"));
+            return Some(response.to_json());
+        }
+    };
+    match value {
+        Value::Array(items) if items.is_empty() => Some(
+            RpcResponse::error(Value::Null, INVALID_REQUEST, "empty batch".to_string()).to_json(),
+        ),
+        Value::Array(items) => {
+            let mut responses = Vec::with_capacity(items.len());
+            for item in items {
+                if let Some(response) = handle_value(service, session, item).await {
+                    responses.push(response);
+                }
+            }
+            if responses.is_empty() {
+                None
+            } else {
+                Some(serde_json::to_string(&responses).unwrap_or_else(|err| {
+                    RpcResponse::error(
+                        Value::Null,
+                        INTERNAL_ERROR,
+                        format!("serialization failed: {err}"),
+                    )
+                    .to_json()
+                }))
+            }
+        }
+        // alloyctl requests share the socket; they never come batched
+        other if is_alloy_request(&other) => Some(alloy::handle(service, session, other).await),
+        other => handle_value(service, session, other)
+            .await
+            .map(|response| response.to_json()),
+    }
+}
+
+async fn handle_value(
+    service: &DaemonService,
+    session: &Session,
+    value: Value,
+) -> Option<RpcResponse> {
+    let request = match parse_request(value) {
+        Ok(request) => request,
+        Err(response) => return Some(*response),
+    };
+    let id = request.id.clone();
+    let method = request.method.clone();
+    let started = Instant::now();
+    let outcome = if session.allows(&request.method) {
+        service.handle(request, session).await
+    } else {
+        Err(RpcFault::Forbidden(request.method).into())
+    };
+    service
+        .metrics()
+        .record_call(&method, started.elapsed(), outcome.is_err());
+    // notifications never get a reply, not even when they fail
+    let id = id?;
+    Some(match outcome {
+        Ok(value) => RpcResponse::result(id, value),
+        Err(err) => RpcResponse::from_failure(id, &err),
+    })
+}
+
+fn parse_request(value: Value) -> Result<RpcRequest, Box<RpcResponse>> {
+    /*
+     * the bundled gui predates the "jsonrpc" member, so a missing version is
+     * tolerated. anything that does name a version has to name 2.0.
+     */
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+    if !value.is_object() {
+        return Err(Box::new(RpcResponse::error(
+            Value::Null,
+            INVALID_REQUEST,
+            "request must be an object".to_string(),
+        )));
+    }
+    let request: RpcRequest = serde_json::from_value(value).map_err(|err| {
+        Box::new(RpcResponse::error(
+            id.clone(),
+            INVALID_REQUEST,
+            format!("invalid request: {err}"),
+        ))
+    })?;
+    if let Some(version) = &request.jsonrpc {
+        if version != JSONRPC_VERSION {
+            return Err(Box::new(RpcResponse::error(
+                id,
+                INVALID_REQUEST,
+                format!("unsupported jsonrpc version {version}"),
+            )));
+        }
+    }
+    if !matches!(
+        request.params,
+        Value::Null | Value::Object(_) | Value::Array(_)
+    ) {
+        return Err(Box::new(RpcResponse::error(
+            id,
+            INVALID_REQUEST,
+            "params must be an object or array".to_string(),
+        )));
+    }
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::DaemonService;
+    use silicon_alloy_shared::api::{methods, ChildPolicy};
+
+    async fn call(service: &DaemonService, payload: Value) -> Option<Value> {
+        let (outbound, _pending) = mpsc::channel(8);
+        let session = Session::new(outbound, Access::Full);
+        handle_payload(service, &session, &payload.to_string())
+            .await
+            .map(|line| serde_json::from_str(&line).unwrap())
+    }
+
+    #[tokio::test]
+    async fn responses_carry_version_and_id() {
+        let service = DaemonService::for_tests().await;
+        let response = call(
+            &service,
+            json!({ "jsonrpc": "2.0", "id": 7, "method": "service.ping" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response["jsonrpc"], "2.0");
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["result"]["status"], "ok");
+    }
+
+    #[tokio::test]
+    async fn every_catalog_method_dispatches() {
+        let service = DaemonService::for_tests().await;
+        for (index, method) in methods::NAMES.iter().enumerate() {
+            // a non-uuid id keeps anything destructive from going through
+            let response = call(
+                &service,
+                json!({ "jsonrpc": "2.0", "id": index, "method": method, "params": { "id": 3 } }),
+            )
+            .await
+            .unwrap();
+            assert_ne!(response["error"]["code"], METHOD_NOT_FOUND, "{method}");
+        }
+
+        let capabilities = call(
+            &service,
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "service.capabilities" }),
+        )
+        .await
+        .unwrap();
+        let listed: Vec<&str> = capabilities["result"]["methods"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(listed, methods::NAMES);
+        let delete = listed.iter().position(|name| *name == "bottle.delete").unwrap();
+        let delete = &capabilities["result"]["methods"][delete];
+        let properties = delete["params"]["properties"].as_object().unwrap();
+        assert!(properties.contains_key("id") && properties.contains_key("bottle"));
+    }
+
+    #[tokio::test]
+    async fn read_only_sessions_only_reach_read_only_methods() {
+        let service = DaemonService::for_tests().await;
+        let (outbound, _pending) = mpsc::channel(8);
+        let methods = ["bottle.list".to_string()].into_iter().collect();
+        let session = Session::new(outbound, Access::ReadOnly(std::sync::Arc::new(methods)));
+        let list = json!({ "jsonrpc": "2.0", "id": 1, "method": "bottle.list" });
+        let list: Value = serde_json::from_str(
+            &handle_payload(&service, &session, &list.to_string()).await.unwrap(),
+        )
+        .unwrap();
+        assert!(list["result"]["bottles"].is_array());
+
+        let delete = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "bottle.delete",
+            "params": { "id": Uuid::new_v4() },
+        });
+        let delete: Value = serde_json::from_str(
+            &handle_payload(&service, &session, &delete.to_string()).await.unwrap(),
+        )
+        .unwrap();
+        assert_eq!(delete["error"]["code"], FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn calls_show_up_in_metrics() {
+        let service = DaemonService::for_tests().await;
+        for (id, method) in ["service.ping", "service.ping", "bottle.nope"].iter().enumerate() {
+            call(&service, json!({ "jsonrpc": "2.0", "id": id, "method": method })).await;
+        }
+        call(
+            &service,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 3,
+                "method": "bottle.create",
+                "params": { "name": "counted", "wine_version": "9.0", "channel": "custom" },
+            }),
+        )
+        .await;
+
+        let metrics = call(
+            &service,
+            json!({ "jsonrpc": "2.0", "id": 4, "method": "service.metrics" }),
+        )
+        .await
+        .unwrap();
+        let methods = metrics["result"]["methods"].as_array().unwrap();
+        let method = |name: &str| methods.iter().find(|m| m["method"] == name).unwrap();
+        assert_eq!(method("service.ping")["calls"], 2);
+        assert_eq!(method("service.ping")["latency"]["count"], 2);
+        assert_eq!(method("other")["errors"], 1);
+        assert_eq!(metrics["result"]["bottles_by_channel"]["custom"], 1);
+    }
+
+    #[tokio::test]
+    async fn shutdown_request_reaches_the_accept_loop() {
+        let service = DaemonService::for_tests().await;
+        let waiter = tokio::spawn({
+            let shutdown = service.shutdown();
+            async move { shutdown.requested().await }
+        });
+        let reply = call(
+            &service,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "service.shutdown",
+                "params": { "children": "detach" },
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(reply["result"]["children"], "detach");
+        assert_eq!(waiter.await.unwrap(), ChildPolicy::Detach);
+
+        // later requests don't change the outcome
+        service.shutdown().trigger(ChildPolicy::Kill);
+        assert_eq!(service.shutdown().requested().await, ChildPolicy::Detach);
+    }
+
+    #[tokio::test]
+    async fn reload_applies_the_config_file() {
+        let service = DaemonService::for_tests().await;
+        let info = call(&service, json!({ "jsonrpc": "2.0", "id": 1, "method": "service.info" }))
+            .await
+            .unwrap();
+        let config = &info["result"]["config"];
+        assert_eq!(config["default_channel"]["source"], "default");
+        assert_eq!(config["recipe_paths"]["source"], "file");
+
+        let file = std::path::PathBuf::from(config["file"].as_str().unwrap());
+        let mut contents = std::fs::read_to_string(&file).unwrap();
+        contents.push_str("default_channel = \"native-arm64\"\n");
+        contents.push_str("[access]\nread_only_uids = [4242]\n");
+        std::fs::write(&file, &contents).unwrap();
+        let reply = call(&service, json!({ "jsonrpc": "2.0", "id": 2, "method": "service.reload" }))
+            .await
+            .unwrap();
+        assert_eq!(reply["result"]["config"]["default_channel"]["value"], "native-arm64");
+        // the socket was bound private, sharing it needs a restart
+        assert_eq!(reply["result"]["restart_required"], json!(["access"]));
+        assert!(service.access_for(4242).is_some());
+
+        // a broken file leaves the running configuration alone
+        std::fs::write(&file, "default_channel = [").unwrap();
+        let reply = call(&service, json!({ "jsonrpc": "2.0", "id": 3, "method": "service.reload" }))
+            .await
+            .unwrap();
+        assert!(reply["error"].is_object());
+        assert!(service.access_for(4242).is_some());
+    }
+
+    #[tokio::test]
+    async fn notifications_get_no_reply() {
+        let service = DaemonService::for_tests().await;
+        assert!(call(
+            &service,
+            json!({ "jsonrpc": "2.0", "method": "service.ping" })
+        )
+        .await
+        .is_none());
+        assert!(
+            call(&service, json!({ "jsonrpc": "2.0", "method": "nope" }))
+                .await
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn error_codes_follow_the_spec() {
+        let service = DaemonService::for_tests().await;
+        let unknown = call(
+            &service,
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "nope" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(unknown["error"]["code"], METHOD_NOT_FOUND);
+
+        let bad_params = call(
+            &service,
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "bottle.delete", "params": { "id": 3 } }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(bad_params["error"]["code"], INVALID_PARAMS);
+
+        let (outbound, _pending) = mpsc::channel(8);
+        let session = Session::new(outbound, Access::Full);
+        let parse = handle_payload(&service, &session, "{not json")
+            .await
+            .unwrap();
+        let parse: Value = serde_json::from_str(&parse).unwrap();
+        assert_eq!(parse["error"]["code"], PARSE_ERROR);
+        assert_eq!(parse["id"], Value::Null);
+
+        let invalid = call(
+            &service,
+            json!({ "jsonrpc": "1.0", "id": 3, "method": "service.ping" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(invalid["error"]["code"], INVALID_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn batches_skip_notifications() {
+        let service = DaemonService::for_tests().await;
+        let response = call(
+            &service,
+            json!([
+                { "jsonrpc": "2.0", "id": "a", "method": "service.ping" },
+                { "jsonrpc": "2.0", "method": "service.ping" },
+                { "jsonrpc": "2.0", "id": "b", "method": "nope" },
+                42
+            ]),
+        )
+        .await
+        .unwrap();
+        let items = response.as_array().unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0]["id"], "a");
+        assert_eq!(items[1]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(items[2]["error"]["code"], INVALID_REQUEST);
+
+        let empty = call(&service, json!([])).await.unwrap();
+        assert_eq!(empty["error"]["code"], INVALID_REQUEST);
+
+        let quiet = call(
+            &service,
+            json!([{ "jsonrpc": "2.0", "method": "service.ping" }]),
+        )
+        .await;
+        assert!(quiet.is_none());
+    }
+
+    #[tokio::test]
+    async fn subscribers_receive_matching_events() {
+        let service = DaemonService::for_tests().await;
+        let (outbound, mut pending) = mpsc::channel(8);
+        let session = Session::new(outbound, Access::Full);
+        let subscribe = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "events.subscribe",
+            "params": { "kinds": ["bottle_created"] },
+        });
+        let reply = handle_payload(&service, &session, &subscribe.to_string())
+            .await
+            .unwrap();
+        let reply: Value = serde_json::from_str(&reply).unwrap();
+        let subscription = reply["result"]["subscription"].clone();
+
+        let create = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "bottle.create",
+            "params": { "name": "events", "wine_version": "9.0" },
+        });
+        handle_payload(&service, &session, &create.to_string())
+            .await
+            .unwrap();
+
+        let pushed: Value = serde_json::from_str(&pending.recv().await.unwrap()).unwrap();
+        assert_eq!(pushed["method"], "events.event");
+        assert!(pushed.get("id").is_none());
+        assert_eq!(pushed["params"]["subscription"], subscription);
+        assert_eq!(pushed["params"]["event"]["kind"], "bottle_created");
+        assert_eq!(pushed["params"]["event"]["name"], "events");
+    }
+}
@@ -1,121 +1,588 @@
+use std::future::Future;
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, Result};
 use directories::UserDirs;
-use serde::Deserialize;
-use serde_json::{json, Value};
-use silicon_alloy_shared::recipes::{default_recipe_root, find_recipe, load_all, Recipe, RecipeStep};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use silicon_alloy_shared::api::{
+    methods, BottleAdoptParams, BottleAdopted, BottleCleanParams, BottleCleaned, BottleCloneParams,
+    BottleCreateParams, BottleDeleteParams, BottleDeleted, BottleExportParams, BottleExported,
+    BottleImportParams, BottleImported, BottleReply, BottleRestoreParams, BottleRunParams,
+    BottleUpdateParams, BottleUsageParams, Capabilities, ChildPolicy, DaemonEvent, Deferred,
+    EventFilter, EventNotification, JobIdParams, JobList, JobListParams, JobReply, JobWaitParams,
+    JobWaitReply, LaggedNotification, LogsCleaned, Method, PingReply, RecipeApplied,
+    RecipeApplyParams, RecipeList, RecipeSummary, Reloaded, RunResult, RuntimeList, ServiceInfo,
+    ShortcutCreateParams, ShortcutCreated, ShutdownParams, ShuttingDown, SnapshotCreateParams,
+    SnapshotDeleted, SnapshotList, SnapshotListParams, SnapshotParams, SnapshotReply, Subscribed,
+    TrashList, TrashPurgeParams, TrashPurged, UnsubscribeParams, Unsubscribed, UsageReport,
+    EVENT_NOTIFICATION, LAGGED_NOTIFICATION, PROTOCOL_VERSION,
+};
+use silicon_alloy_shared::access::{Access, AccessPolicy};
+use silicon_alloy_shared::config::{DaemonConfig, Source};
+use silicon_alloy_shared::lock::{BottleBusy, BottleLock, LockMode};
+use silicon_alloy_shared::metrics::MetricsSnapshot;
+use silicon_alloy_shared::recipes::{find_in_search_path, load_search_path, Recipe, RecipeStep};
+use silicon_alloy_shared::usage::{clean_logs, log_usage};
 use silicon_alloy_shared::{
-    discover_runtimes, runtime_root, BottleRecord, BottleStore, RuntimeDescriptor, WineRuntime,
+    discover_runtimes, log_dir, select_bottle, unix_timestamp, BottleList, BottleRecord,
+    BottleStore, ListedBottle, RuntimeDescriptor, WineRuntime,
 };
 use tokio::fs;
 use tokio::process::Command;
+use tokio::sync::broadcast::error::RecvError;
 use tracing::{info, warn};
+use tracing_subscriber::{reload, EnvFilter, Registry};
 use uuid::Uuid;
 
-use crate::rpc::RpcRequest;
+use crate::events::EventBus;
+use crate::jobs::JobRegistry;
+use crate::limits::{ConnectionSlots, Limits};
+use crate::metrics::Metrics;
+use crate::processes::ProcessTable;
+use crate::shutdown::Shutdown;
+use crate::rpc::{self, RpcFault, RpcRequest, Session};
 
 #[derive(Clone)]
 pub struct DaemonService {
     state: Arc<State>,
 }
 
+/// how often `retain_trash` looks for bottles to purge.
+const TRASH_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// lets `service.reload` swap the log filter installed by `main`.
+pub type LogFilter = reload::Handle<EnvFilter, Registry>;
+
 struct State {
     bottles: BottleStore,
-    runtime_dir: PathBuf,
-    recipe_dir: PathBuf,
+    /// where `main` has the daemon log to
+    log_dir: PathBuf,
+    settings: RwLock<Arc<Settings>>,
+    /// the socket as bound at startup; reloads can't move or reshare it
+    socket_path: PathBuf,
+    shared_socket: bool,
+    /// sized once at startup, like the socket
+    slots: ConnectionSlots,
+    log_filter: Option<LogFilter>,
+    events: EventBus,
+    jobs: JobRegistry,
+    processes: ProcessTable,
+    metrics: Metrics,
+    shutdown: Shutdown,
+    started_at: u64,
+}
+
+/// everything a config reload replaces while the daemon keeps running.
+struct Settings {
+    config: DaemonConfig,
     runtimes: Vec<RuntimeDescriptor>,
+    access: AccessPolicy,
 }
 
 impl DaemonService {
-    pub async fn new() -> Result<Self> {
-        let bottles = BottleStore::new()?;
-        let runtime_dir = runtime_root()?;
-        let recipe_dir = recipe_dir()?;
-        let mut runtimes = discover_runtimes(&runtime_dir)?;
-        if let Ok(extra) = std::env::var("SILICON_ALLOY_ARM64_WINE64") {
-            let path = PathBuf::from(&extra);
-            if path.exists() {
-                runtimes.push(RuntimeDescriptor {
-                    channel: "native-arm64".to_string(),
-                    label: "wine arm64 (external)".to_string(),
-                    version: "experimental".to_string(),
-                    wine64_path: path,
-                    notes: Some("provided via SILICON_ALLOY_ARM64_WINE64".to_string()),
-                });
+    pub async fn new(config: DaemonConfig, log_filter: Option<LogFilter>) -> Result<Self> {
+        let bottles = BottleStore::with_root(&config.bottle_root.value)?;
+        // the default locations are where users are told to drop runtimes
+        // and recipes, so they should exist even while empty
+        for (dirs, source) in [
+            (&config.runtime_roots.value, &config.runtime_roots.source),
+            (&config.recipe_paths.value, &config.recipe_paths.source),
+        ] {
+            if *source == Source::Default {
+                for dir in dirs {
+                    std::fs::create_dir_all(dir)?;
+                }
             }
         }
-        if runtimes.is_empty() {
-            tracing::warn!("no wine runtimes discovered under {}", runtime_dir.display());
-        }
-        Ok(Self {
+        Ok(Self::from_parts(bottles, log_dir()?, config, log_filter))
+    }
+
+    fn from_parts(
+        bottles: BottleStore,
+        log_dir: PathBuf,
+        config: DaemonConfig,
+        log_filter: Option<LogFilter>,
+    ) -> Self {
+        let events = EventBus::new();
+        let settings = Settings::new(config);
+        Self {
             state: Arc::new(State {
                 bottles,
-                runtime_dir,
-                recipe_dir,
-                runtimes,
+                log_dir,
+                socket_path: settings.config.socket_path.value.clone(),
+                shared_socket: settings.access.is_shared(),
+                slots: ConnectionSlots::new(settings.config.max_connections.value),
+                settings: RwLock::new(Arc::new(settings)),
+                log_filter,
+                jobs: JobRegistry::new(events.clone()),
+                events,
+                processes: ProcessTable::default(),
+                metrics: Metrics::default(),
+                shutdown: Shutdown::new(),
+                started_at: unix_timestamp(),
             }),
-        })
+        }
+    }
+
+    /// a service rooted in a throwaway directory, so tests never touch the
+    /// user's real bottles.
+    #[cfg(test)]
+    pub async fn for_tests() -> Self {
+        Self::for_tests_with("").await
+    }
+
+    /// like `for_tests`, with `extra` appended to the config file.
+    pub async fn for_tests_with(extra: &str) -> Self {
+        let root = std::env::temp_dir().join(format!("silicon-alloy-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&root).expect("test root");
+        let file = root.join("daemon.toml");
+        std::fs::write(
+            &file,
+            format!(
+                "bottle_root = {:?}\nruntime_roots = [{:?}]\nrecipe_paths = [{:?}]\n{extra}",
+                root.join("bottles"),
+                root.join("runtime"),
+                root.join("recipes"),
+            ),
+        )
+        .expect("test config");
+        let config = DaemonConfig::load_from(&file, &|_| None).expect("test config");
+        let bottles = BottleStore::with_root(&config.bottle_root.value).expect("test bottle root");
+        Self::from_parts(bottles, root.join("logs"), config, None)
     }
 
-    pub async fn handle(&self, request: RpcRequest) -> Result<Value> {
+    pub async fn handle(&self, request: RpcRequest, session: &Session) -> Result<Value> {
+        let params = request.params;
         match request.method.as_str() {
-            "service.ping" => Ok(json!({ "status": "ok" })),
-            "service.info" => self.service_info().await,
-            "runtime.list" => self.runtime_list().await,
-            "bottle.list" => self.bottle_list().await,
-            "bottle.create" => self.bottle_create(request.params).await,
-            "bottle.delete" => self.bottle_delete(request.params).await,
-            "bottle.run" => self.bottle_run(request.params).await,
-            "recipe.list" => self.recipe_list().await,
-            "recipe.apply" => self.recipe_apply(request.params).await,
-            "shortcut.create" => self.shortcut_create(request.params).await,
-            _ => Err(anyhow!("unknown method {}", request.method)),
+            methods::ServicePing::NAME => dispatch(methods::ServicePing, params, |_| async {
+                Ok(PingReply {
+                    status: "ok".to_string(),
+                })
+            })
+            .await,
+            methods::ServiceInfo::NAME => {
+                dispatch(methods::ServiceInfo, params, |_| self.service_info()).await
+            }
+            methods::ServiceCapabilities::NAME => {
+                dispatch(methods::ServiceCapabilities, params, |_| {
+                    self.service_capabilities()
+                })
+                .await
+            }
+            methods::ServiceShutdown::NAME => {
+                dispatch(methods::ServiceShutdown, params, |input| {
+                    self.service_shutdown(input)
+                })
+                .await
+            }
+            methods::ServiceReload::NAME => {
+                dispatch(methods::ServiceReload, params, |_| self.reload()).await
+            }
+            methods::ServiceMetrics::NAME => {
+                dispatch(methods::ServiceMetrics, params, |_| self.metrics_snapshot()).await
+            }
+            methods::RuntimeList::NAME => {
+                dispatch(methods::RuntimeList, params, |_| self.runtime_list()).await
+            }
+            methods::BottleList::NAME => {
+                dispatch(methods::BottleList, params, |_| self.bottle_list()).await
+            }
+            methods::BottleCreate::NAME => {
+                dispatch(methods::BottleCreate, params, |input| self.bottle_create(input)).await
+            }
+            methods::BottleDelete::NAME => {
+                dispatch(methods::BottleDelete, params, |input| self.bottle_delete(input)).await
+            }
+            methods::BottleUpdate::NAME => {
+                dispatch(methods::BottleUpdate, params, |input| self.bottle_update(input)).await
+            }
+            methods::BottleClone::NAME => {
+                dispatch(methods::BottleClone, params, |input| self.bottle_clone(input)).await
+            }
+            methods::BottleExport::NAME => {
+                dispatch(methods::BottleExport, params, |input| self.bottle_export(input)).await
+            }
+            methods::BottleImport::NAME => {
+                dispatch(methods::BottleImport, params, |input| self.bottle_import(input)).await
+            }
+            methods::BottleRun::NAME => {
+                dispatch(methods::BottleRun, params, |input| self.bottle_run(input)).await
+            }
+            methods::BottleSnapshotCreate::NAME => {
+                dispatch(methods::BottleSnapshotCreate, params, |input| {
+                    self.snapshot_create(input)
+                })
+                .await
+            }
+            methods::BottleSnapshotList::NAME => {
+                dispatch(methods::BottleSnapshotList, params, |input| {
+                    self.snapshot_list(input)
+                })
+                .await
+            }
+            methods::BottleSnapshotRestore::NAME => {
+                dispatch(methods::BottleSnapshotRestore, params, |input| {
+                    self.snapshot_restore(input)
+                })
+                .await
+            }
+            methods::BottleSnapshotDelete::NAME => {
+                dispatch(methods::BottleSnapshotDelete, params, |input| {
+                    self.snapshot_delete(input)
+                })
+                .await
+            }
+            methods::BottleRestore::NAME => {
+                dispatch(methods::BottleRestore, params, |input| self.bottle_restore(input)).await
+            }
+            methods::TrashList::NAME => {
+                dispatch(methods::TrashList, params, |_| self.trash_list()).await
+            }
+            methods::TrashPurge::NAME => {
+                dispatch(methods::TrashPurge, params, |input| self.trash_purge(input)).await
+            }
+            methods::BottleUsage::NAME => {
+                dispatch(methods::BottleUsage, params, |input| self.bottle_usage(input)).await
+            }
+            methods::BottleClean::NAME => {
+                dispatch(methods::BottleClean, params, |input| self.bottle_clean(input)).await
+            }
+            methods::LogsClean::NAME => {
+                dispatch(methods::LogsClean, params, |_| self.logs_clean()).await
+            }
+            methods::BottleAdopt::NAME => {
+                dispatch(methods::BottleAdopt, params, |input| self.bottle_adopt(input)).await
+            }
+            methods::RecipeList::NAME => {
+                dispatch(methods::RecipeList, params, |_| self.recipe_list()).await
+            }
+            methods::RecipeApply::NAME => {
+                dispatch(methods::RecipeApply, params, |input| self.recipe_apply(input)).await
+            }
+            methods::ShortcutCreate::NAME => {
+                dispatch(methods::ShortcutCreate, params, |input| {
+                    self.shortcut_create(input)
+                })
+                .await
+            }
+            methods::EventsSubscribe::NAME => {
+                dispatch(methods::EventsSubscribe, params, |input| {
+                    self.events_subscribe(input, session)
+                })
+                .await
+            }
+            methods::EventsUnsubscribe::NAME => {
+                dispatch(methods::EventsUnsubscribe, params, |input| {
+                    self.events_unsubscribe(input, session)
+                })
+                .await
+            }
+            methods::JobStatus::NAME => {
+                dispatch(methods::JobStatus, params, |input| self.job_status(input)).await
+            }
+            methods::JobList::NAME => {
+                dispatch(methods::JobList, params, |input| self.job_list(input)).await
+            }
+            methods::JobWait::NAME => {
+                dispatch(methods::JobWait, params, |input| self.job_wait(input)).await
+            }
+            methods::JobCancel::NAME => {
+                dispatch(methods::JobCancel, params, |input| self.job_cancel(input)).await
+            }
+            _ => Err(RpcFault::MethodNotFound(request.method).into()),
         }
     }
 
-    async fn service_info(&self) -> Result<Value> {
-        Ok(json!({
-            "version": env!("CARGO_PKG_VERSION"),
-            "runtime_dir": self.state.runtime_dir,
-            "bottle_root": self.state.bottles.root(),
-            "runtimes": self.state.runtimes,
-        }))
+    pub fn shutdown(&self) -> Shutdown {
+        self.state.shutdown.clone()
     }
 
-    async fn runtime_list(&self) -> Result<Value> {
-        Ok(json!({ "runtimes": self.state.runtimes }))
+    /// true while wine processes or jobs are running, which keeps an idle
+    /// daemon from exiting underneath them.
+    pub fn is_busy(&self) -> bool {
+        !self.state.processes.is_empty() || self.state.jobs.has_active()
     }
 
-    async fn recipe_list(&self) -> Result<Value> {
-        let recipes = load_all(&self.state.recipe_dir)?;
-        let summaries: Vec<Value> = recipes
+    pub fn metrics(&self) -> &Metrics {
+        &self.state.metrics
+    }
+
+    /// the in-memory counters plus the bottle census, which is read off disk
+    /// on every call rather than tracked.
+    pub async fn metrics_snapshot(&self) -> Result<MetricsSnapshot> {
+        let mut snapshot = self.state.metrics.snapshot();
+        snapshot.uptime_seconds = unix_timestamp().saturating_sub(self.state.started_at);
+        for bottle in self.state.bottles.list().await? {
+            let channel = bottle.wine_runtime.channel.unwrap_or_else(|| "unknown".to_string());
+            *snapshot.bottles_by_channel.entry(channel).or_default() += 1;
+        }
+        Ok(snapshot)
+    }
+
+    pub fn child_policy(&self) -> ChildPolicy {
+        self.settings().config.child_policy.value
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.settings().config.idle_timeout()
+    }
+
+    /// limits for a client connecting now.
+    pub fn limits(&self) -> Limits {
+        Limits::from_config(&self.settings().config)
+    }
+
+    pub fn connection_slots(&self) -> &ConnectionSlots {
+        &self.state.slots
+    }
+
+    /// `None` means the peer must be disconnected.
+    pub fn access_for(&self, uid: u32) -> Option<Access> {
+        self.settings().access.access_for(uid)
+    }
+
+    /// re-reads the config file and applies it. settings that were fixed
+    /// when the daemon started keep their old value and are reported back.
+    /// nothing changes when the file doesn't parse.
+    pub async fn reload(&self) -> Result<Reloaded> {
+        let current = self.settings();
+        let config = DaemonConfig::load_from(&current.config.file, &|var| std::env::var(var).ok())?;
+        let filter = EnvFilter::try_new(&config.log_filter.value)
+            .map_err(|err| anyhow!("invalid log filter {:?}: {err}", config.log_filter.value))?;
+        let mut restart_required = Vec::new();
+        if config.bottle_root.value != self.state.bottles.root() {
+            restart_required.push("bottle_root".to_string());
+        }
+        if config.socket_path.value != self.state.socket_path {
+            restart_required.push("socket_path".to_string());
+        }
+        if config.gateway_port.value != current.config.gateway_port.value {
+            restart_required.push("gateway_port".to_string());
+        }
+        if config.max_connections.value != self.state.slots.max() {
+            restart_required.push("max_connections".to_string());
+        }
+        let settings = Settings::new(config.clone());
+        // socket permissions are set once, when it is bound
+        if settings.access.is_shared() != self.state.shared_socket {
+            restart_required.push("access".to_string());
+        }
+        if let Some(handle) = &self.state.log_filter {
+            handle.reload(filter)?;
+        }
+        *self.state.settings.write().expect("settings poisoned") = Arc::new(settings);
+        info!("reloaded configuration from {}", config.file.display());
+        if !restart_required.is_empty() {
+            warn!("restart the daemon to apply: {}", restart_required.join(", "));
+        }
+        Ok(Reloaded {
+            config,
+            restart_required,
+        })
+    }
+
+    fn settings(&self) -> Arc<Settings> {
+        self.state.settings.read().expect("settings poisoned").clone()
+    }
+
+    /// applies `policy` to the wine processes this daemon started. detached
+    /// processes are simply left alone.
+    pub async fn release_children(&self, policy: ChildPolicy) {
+        let processes = &self.state.processes;
+        match policy {
+            ChildPolicy::Kill => processes.terminate_all().await,
+            ChildPolicy::Detach if !processes.is_empty() => {
+                info!("leaving wine processes running")
+            }
+            ChildPolicy::Detach => {}
+        }
+    }
+
+    async fn events_subscribe(&self, filter: EventFilter, session: &Session) -> Result<Subscribed> {
+        let subscription = Uuid::new_v4();
+        let mut receiver = self.state.events.subscribe();
+        let outbound = session.outbound();
+        let task = tokio::spawn(async move {
+            loop {
+                let message = match receiver.recv().await {
+                    Ok(event) if filter.matches(&event) => rpc::notification(
+                        EVENT_NOTIFICATION,
+                        serde_json::to_value(EventNotification {
+                            subscription,
+                            event,
+                        })
+                        .unwrap_or_default(),
+                    ),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => rpc::notification(
+                        LAGGED_NOTIFICATION,
+                        serde_json::to_value(LaggedNotification {
+                            subscription,
+                            missed,
+                        })
+                        .unwrap_or_default(),
+                    ),
+                    Err(RecvError::Closed) => break,
+                };
+                if outbound.send(message).await.is_err() {
+                    break;
+                }
+            }
+        });
+        session.add_subscription(subscription, task.abort_handle());
+        Ok(Subscribed { subscription })
+    }
+
+    async fn events_unsubscribe(
+        &self,
+        input: UnsubscribeParams,
+        session: &Session,
+    ) -> Result<Unsubscribed> {
+        if !session.remove_subscription(input.subscription) {
+            return Err(RpcFault::InvalidParams(format!(
+                "no subscription {} on this connection",
+                input.subscription
+            ))
+            .into());
+        }
+        Ok(Unsubscribed {
+            unsubscribed: input.subscription,
+        })
+    }
+
+    async fn job_status(&self, input: JobIdParams) -> Result<JobReply> {
+        let job = self.state.jobs.get(input.id).ok_or_else(|| unknown_job(input.id))?;
+        Ok(JobReply { job })
+    }
+
+    async fn job_list(&self, input: JobListParams) -> Result<JobList> {
+        let bottle_id = match input.bottle.as_deref() {
+            Some(selector) => Some(self.select_bottle(input.bottle_id, Some(selector)).await?),
+            None => input.bottle_id,
+        };
+        let jobs = self
+            .state
+            .jobs
+            .list()
             .into_iter()
-            .map(|recipe| {
-                json!({
-                    "id": recipe.manifest.id,
-                    "name": recipe.manifest.name,
-                    "description": recipe.manifest.description,
-                })
+            .filter(|job| bottle_id.is_none_or(|id| job.bottle_id == id))
+            .filter(|job| input.state.is_none_or(|state| job.state == state))
+            .collect();
+        Ok(JobList { jobs })
+    }
+
+    async fn job_wait(&self, input: JobWaitParams) -> Result<JobWaitReply> {
+        let timeout = input.timeout_ms.map(Duration::from_millis);
+        let job = self
+            .state
+            .jobs
+            .wait(input.id, timeout)
+            .await
+            .ok_or_else(|| unknown_job(input.id))?;
+        Ok(JobWaitReply {
+            timed_out: !job.state.is_terminal(),
+            job,
+        })
+    }
+
+    async fn job_cancel(&self, input: JobIdParams) -> Result<JobReply> {
+        let job = self
+            .state
+            .jobs
+            .cancel(input.id)
+            .ok_or_else(|| unknown_job(input.id))?;
+        Ok(JobReply { job })
+    }
+
+    async fn service_info(&self) -> Result<ServiceInfo> {
+        let settings = self.settings();
+        Ok(ServiceInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            pid: std::process::id(),
+            started_at: self.state.started_at,
+            runtime_dir: settings.runtime_dir().to_path_buf(),
+            bottle_root: self.state.bottles.root().to_path_buf(),
+            runtimes: settings.runtimes.clone(),
+            config: settings.config.clone(),
+        })
+    }
+
+    async fn service_capabilities(&self) -> Result<Capabilities> {
+        Ok(Capabilities {
+            protocol_version: PROTOCOL_VERSION,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            methods: methods::catalog(),
+        })
+    }
+
+    async fn service_shutdown(&self, input: ShutdownParams) -> Result<ShuttingDown> {
+        let children = input.children.unwrap_or_else(|| self.child_policy());
+        info!("shutdown requested over rpc");
+        self.state.shutdown.trigger(children);
+        Ok(ShuttingDown { children })
+    }
+
+    async fn runtime_list(&self) -> Result<RuntimeList> {
+        Ok(RuntimeList {
+            runtimes: self.settings().runtimes.clone(),
+        })
+    }
+
+    async fn recipe_list(&self) -> Result<RecipeList> {
+        let recipes = load_search_path(&self.settings().config.recipe_paths.value)?;
+        let recipes = recipes
+            .into_iter()
+            .map(|recipe| RecipeSummary {
+                id: recipe.manifest.id,
+                name: recipe.manifest.name,
+                description: recipe.manifest.description,
             })
             .collect();
-        Ok(json!({ "recipes": summaries }))
+        Ok(RecipeList { recipes })
     }
 
-    async fn recipe_apply(&self, params: Value) -> Result<Value> {
-        let input: RecipeApplyParams =
-            serde_json::from_value(params).context("expected recipe.apply params { bottle_id, recipe_id }")?;
-        let recipe = find_recipe(&self.state.recipe_dir, &input.recipe_id)?;
-        self.apply_recipe(input.bottle_id, recipe).await
+    async fn recipe_apply(&self, input: RecipeApplyParams) -> Result<Deferred<RecipeApplied>> {
+        let recipe =
+            find_in_search_path(&self.settings().config.recipe_paths.value, &input.recipe_id)?;
+        let id = self
+            .select_bottle(input.bottle_id, input.bottle.as_deref())
+            .await?;
+        self.state.bottles.record(id).await?;
+        let method = methods::RecipeApply::NAME;
+        if input.background {
+            // a busy bottle fails the call, not a job later on, unless the
+            // caller waits for it; then the job does the waiting
+            let lock = match input.wait {
+                false => Some(self.lock_bottle(id, LockMode::Exclusive, method, false).await?),
+                true => None,
+            };
+            let service = self.clone();
+            let job = self.state.jobs.spawn(method, id, async move {
+                let _lock = match lock {
+                    Some(lock) => lock,
+                    None => service.lock_bottle(id, LockMode::Exclusive, method, true).await?,
+                };
+                let applied = service.apply_recipe(id, recipe).await?;
+                Ok(serde_json::to_value(applied)?)
+            });
+            return Ok(Deferred::Job { job });
+        }
+        let _lock = self
+            .lock_bottle(id, LockMode::Exclusive, method, input.wait)
+            .await?;
+        Ok(Deferred::Done(self.apply_recipe(id, recipe).await?))
     }
 
-    async fn shortcut_create(&self, params: Value) -> Result<Value> {
-        let input: ShortcutCreateParams = serde_json::from_value(params)
-            .context("expected shortcut.create params { bottle_id, name, executable, destination? }")?;
-        let record = self.state.bottles.record(input.bottle_id).await?;
-        let prefix = self.state.bottles.bottle_prefix(input.bottle_id);
+    async fn shortcut_create(&self, input: ShortcutCreateParams) -> Result<ShortcutCreated> {
+        let id = self
+            .select_bottle(input.bottle_id, input.bottle.as_deref())
+            .await?;
+        let record = self.state.bottles.record(id).await?;
+        let prefix = self.state.bottles.bottle_prefix(id);
         let destination = match input.destination.clone() {
             Some(path) => path,
             None => default_shortcut_dir()?,
@@ -129,120 +596,575 @@ impl DaemonService {
             record.name,
             record.id
         );
-        Ok(json!({ "shortcut": shortcut_path }))
+        Ok(ShortcutCreated {
+            shortcut: shortcut_path,
+        })
     }
 
-    async fn bottle_list(&self) -> Result<Value> {
-        let bottles = self.state.bottles.list().await?;
-        Ok(json!({ "bottles": bottles }))
+    async fn bottle_list(&self) -> Result<BottleList> {
+        let bottles = self
+            .state
+            .bottles
+            .list()
+            .await?
+            .into_iter()
+            .map(|bottle| {
+                let holders = self.state.bottles.holders(bottle.id);
+                ListedBottle {
+                    bottle,
+                    busy: !holders.is_empty(),
+                    holders,
+                }
+            })
+            .collect();
+        Ok(BottleList { bottles })
     }
 
-    async fn bottle_create(&self, params: Value) -> Result<Value> {
-        let input: BottleCreateParams = serde_json::from_value(params)
-            .context("expected bottle.create params { name, wine_path, wine_version, wine_label }")?;
+    async fn bottle_create(&self, input: BottleCreateParams) -> Result<BottleReply> {
         let runtime = self.select_runtime(&input)?;
         let record = self.state.bottles.create(&input.name, runtime).await?;
         info!("created bottle {} ({})", record.name, record.id);
-        Ok(json!({ "bottle": record }))
+        self.state.events.emit(DaemonEvent::BottleCreated {
+            bottle_id: record.id,
+            name: record.name.clone(),
+        });
+        Ok(BottleReply { bottle: record })
+    }
+
+    async fn bottle_delete(&self, input: BottleDeleteParams) -> Result<BottleDeleted> {
+        let id = self.select_bottle(input.id, input.bottle.as_deref()).await?;
+        let method = methods::BottleDelete::NAME;
+        let lock = self
+            .lock_bottle(id, LockMode::Exclusive, method, input.wait)
+            .await?;
+        if input.permanent {
+            self.state.bottles.remove(&lock).await?;
+            info!("deleted bottle {id}");
+        } else {
+            let trashed = self.state.bottles.trash(&lock).await?;
+            info!("moved bottle {} ({}) to the trash", trashed.bottle.name, id);
+        }
+        self.state
+            .events
+            .emit(DaemonEvent::BottleDeleted { bottle_id: id });
+        Ok(BottleDeleted {
+            deleted: id,
+            trashed: !input.permanent,
+        })
+    }
+
+    async fn bottle_restore(&self, input: BottleRestoreParams) -> Result<BottleReply> {
+        let id = self.select_trashed(input.id, input.bottle.as_deref()).await?;
+        let record = self
+            .state
+            .bottles
+            .restore_trashed(id, input.name.as_deref())
+            .await?;
+        info!("restored bottle {} ({}) from the trash", record.name, record.id);
+        self.state.events.emit(DaemonEvent::BottleCreated {
+            bottle_id: record.id,
+            name: record.name.clone(),
+        });
+        Ok(BottleReply { bottle: record })
+    }
+
+    async fn trash_list(&self) -> Result<TrashList> {
+        let bottles = self.state.bottles.list_trash().await?;
+        Ok(TrashList { bottles })
+    }
+
+    async fn trash_purge(&self, input: TrashPurgeParams) -> Result<TrashPurged> {
+        let purged = if input.id.is_none() && input.bottle.is_none() {
+            self.state.bottles.empty_trash().await?
+        } else {
+            let id = self.select_trashed(input.id, input.bottle.as_deref()).await?;
+            self.state.bottles.purge_trashed(id).await?;
+            vec![id]
+        };
+        info!("purged {} bottles from the trash", purged.len());
+        Ok(TrashPurged { purged })
+    }
+
+    async fn bottle_usage(&self, input: BottleUsageParams) -> Result<UsageReport> {
+        if input.id.is_some() || input.bottle.is_some() {
+            let id = self.select_bottle(input.id, input.bottle.as_deref()).await?;
+            let usage = self.state.bottles.usage(id, input.refresh).await?;
+            return Ok(UsageReport {
+                bottles: vec![usage],
+                logs: None,
+            });
+        }
+        let mut bottles = Vec::new();
+        for bottle in self.state.bottles.list().await? {
+            match self.state.bottles.usage(bottle.id, input.refresh).await {
+                Ok(usage) => bottles.push(usage),
+                // most likely deleted while the others were measured
+                Err(err) => warn!("unable to measure bottle {}: {err:#}", bottle.id),
+            }
+        }
+        bottles.sort_by_key(|usage| std::cmp::Reverse(usage.total));
+        let logs = match log_usage(&self.state.log_dir).await {
+            Ok(size) => Some(size),
+            Err(err) => {
+                warn!("unable to measure the daemon logs: {err:#}");
+                None
+            }
+        };
+        Ok(UsageReport { bottles, logs })
+    }
+
+    async fn bottle_clean(&self, input: BottleCleanParams) -> Result<BottleCleaned> {
+        if input.categories.is_empty() {
+            return Err(RpcFault::InvalidParams("no categories given".to_string()).into());
+        }
+        if let Some(category) = input.categories.iter().find(|category| !category.cleanable()) {
+            return Err(RpcFault::InvalidParams(format!("{category} can't be cleaned")).into());
+        }
+        let id = self.select_bottle(input.id, input.bottle.as_deref()).await?;
+        let method = methods::BottleClean::NAME;
+        let lock = self
+            .lock_bottle(id, LockMode::Exclusive, method, input.wait)
+            .await?;
+        let categories = self
+            .state
+            .bottles
+            .clean_bottle(&lock, &input.categories)
+            .await?;
+        let reclaimed = categories.values().sum();
+        info!("cleaned bottle {id}, reclaiming {reclaimed} bytes");
+        self.state
+            .events
+            .emit(DaemonEvent::BottleUpdated { bottle_id: id });
+        Ok(BottleCleaned {
+            bottle_id: id,
+            reclaimed,
+            categories,
+        })
     }
 
-    async fn bottle_delete(&self, params: Value) -> Result<Value> {
-        let input: BottleDeleteParams =
-            serde_json::from_value(params).context("expected bottle.delete params { id }")?;
-        self.state.bottles.remove(input.id).await?;
-        Ok(json!({ "deleted": input.id }))
+    async fn logs_clean(&self) -> Result<LogsCleaned> {
+        let reclaimed = clean_logs(&self.state.log_dir).await?;
+        info!("removed old daemon logs, reclaiming {reclaimed} bytes");
+        Ok(LogsCleaned { reclaimed })
     }
 
-    async fn bottle_run(&self, params: Value) -> Result<Value> {
-        let input: BottleRunParams =
-            serde_json::from_value(params).context("expected bottle.run params { id, executable, args? }")?;
-        let record = self.state.bottles.record(input.id).await?;
-        let prefix = self.state.bottles.bottle_prefix(input.id);
+    async fn bottle_adopt(&self, input: BottleAdoptParams) -> Result<BottleAdopted> {
+        require_absolute(&input.path)?;
+        let runtime = self.select_runtime(&input.create)?;
+        let adopted = self
+            .state
+            .bottles
+            .adopt_prefix(&input.path, &input.create.name, runtime, input.mode)
+            .await?;
+        let mut warnings = adopted.warnings;
+        let runtime = &adopted.bottle.wine_runtime;
+        if runtime.version != input.create.wine_version {
+            warnings.push(format!(
+                "no wine {} is installed, so the bottle uses {} {}",
+                input.create.wine_version, runtime.label, runtime.version
+            ));
+        }
+        if !runtime.wine64_path.exists() {
+            warnings.push(format!("{} does not exist", runtime.wine64_path.display()));
+        }
+        let record = adopted.bottle;
+        info!(
+            "adopted {} as bottle {} ({})",
+            input.path.display(),
+            record.name,
+            record.id
+        );
+        self.state.events.emit(DaemonEvent::BottleCreated {
+            bottle_id: record.id,
+            name: record.name.clone(),
+        });
+        Ok(BottleAdopted {
+            bottle: record,
+            prefix: adopted.prefix,
+            warnings,
+        })
+    }
+
+    /// purges what the trash retention settings no longer keep, every
+    /// `TRASH_CHECK_INTERVAL` until the daemon shuts down.
+    pub async fn retain_trash(self, shutdown: Shutdown) {
+        let mut check = tokio::time::interval(TRASH_CHECK_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = check.tick() => {}
+                _ = shutdown.requested() => return,
+            }
+            // read on every tick, a reload may have changed it
+            let retention = self.settings().config.trash_retention();
+            match self.state.bottles.enforce_retention(retention).await {
+                Ok(purged) if !purged.is_empty() => {
+                    info!("purged {} bottles past the trash retention", purged.len());
+                }
+                Ok(_) => {}
+                Err(err) => warn!("unable to enforce trash retention: {err:#}"),
+            }
+        }
+    }
+
+    async fn bottle_update(&self, input: BottleUpdateParams) -> Result<BottleReply> {
+        let id = self.select_bottle(input.id, input.bottle.as_deref()).await?;
+        let method = methods::BottleUpdate::NAME;
+        let lock = self
+            .lock_bottle(id, LockMode::Exclusive, method, input.wait)
+            .await?;
+        let record = self
+            .state
+            .bottles
+            .update_bottle(&lock, &input.changes)
+            .await?;
+        info!("updated bottle {} ({})", record.name, record.id);
+        self.state
+            .events
+            .emit(DaemonEvent::BottleUpdated { bottle_id: record.id });
+        Ok(BottleReply { bottle: record })
+    }
+
+    async fn bottle_clone(&self, input: BottleCloneParams) -> Result<BottleReply> {
+        let id = self.select_bottle(input.id, input.bottle.as_deref()).await?;
+        let method = methods::BottleClone::NAME;
+        let lock = self
+            .lock_bottle(id, LockMode::Exclusive, method, input.wait)
+            .await?;
+        let record = self.state.bottles.clone_bottle(&lock, &input.name).await?;
+        info!("cloned bottle {} into {} ({})", id, record.name, record.id);
+        self.state.events.emit(DaemonEvent::BottleCreated {
+            bottle_id: record.id,
+            name: record.name.clone(),
+        });
+        Ok(BottleReply { bottle: record })
+    }
+
+    async fn bottle_export(&self, input: BottleExportParams) -> Result<BottleExported> {
+        require_absolute(&input.path)?;
+        let id = self.select_bottle(input.id, input.bottle.as_deref()).await?;
+        let method = methods::BottleExport::NAME;
+        let lock = self
+            .lock_bottle(id, LockMode::Exclusive, method, input.wait)
+            .await?;
+        let manifest = self.state.bottles.export_bottle(&lock, &input.path).await?;
+        let size = fs::metadata(&input.path).await?.len();
+        info!("exported bottle {} to {}", id, input.path.display());
+        Ok(BottleExported {
+            path: input.path,
+            files: manifest.files.len(),
+            size,
+        })
+    }
+
+    async fn bottle_import(&self, input: BottleImportParams) -> Result<BottleImported> {
+        require_absolute(&input.path)?;
+        let runtimes = self.settings().runtimes.clone();
+        let imported = self
+            .state
+            .bottles
+            .import_bottle(&input.path, input.name.as_deref(), &runtimes)
+            .await?;
+        let record = imported.bottle;
+        info!("imported bottle {} ({}) from {}", record.name, record.id, input.path.display());
+        self.state.events.emit(DaemonEvent::BottleCreated {
+            bottle_id: record.id,
+            name: record.name.clone(),
+        });
+        Ok(BottleImported {
+            bottle: record,
+            warnings: imported.warnings,
+        })
+    }
+
+    async fn bottle_run(&self, input: BottleRunParams) -> Result<Deferred<RunResult>> {
+        let id = self.select_bottle(input.id, input.bottle.as_deref()).await?;
+        let record = self.state.bottles.record(id).await?;
+        let method = methods::BottleRun::NAME;
+        if input.background {
+            let lock = match input.wait {
+                false => Some(self.lock_bottle(id, LockMode::Shared, method, false).await?),
+                true => None,
+            };
+            let service = self.clone();
+            let job = self.state.jobs.spawn(method, id, async move {
+                let _lock = match lock {
+                    Some(lock) => lock,
+                    None => service.lock_bottle(id, LockMode::Shared, method, true).await?,
+                };
+                let result = service.run_executable(record, input).await?;
+                Ok(serde_json::to_value(result)?)
+            });
+            return Ok(Deferred::Job { job });
+        }
+        let _lock = self
+            .lock_bottle(id, LockMode::Shared, method, input.wait)
+            .await?;
+        Ok(Deferred::Done(self.run_executable(record, input).await?))
+    }
+
+    async fn snapshot_create(&self, input: SnapshotCreateParams) -> Result<SnapshotReply> {
+        let id = self.select_bottle(input.id, input.bottle.as_deref()).await?;
+        let method = methods::BottleSnapshotCreate::NAME;
+        let lock = self
+            .lock_bottle(id, LockMode::Exclusive, method, input.wait)
+            .await?;
+        let snapshot = self
+            .state
+            .bottles
+            .create_snapshot(&lock, &input.label)
+            .await?;
+        info!("snapshotted bottle {} as {:?} ({})", id, snapshot.label, snapshot.id);
+        Ok(SnapshotReply { snapshot })
+    }
+
+    async fn snapshot_list(&self, input: SnapshotListParams) -> Result<SnapshotList> {
+        let id = self.select_bottle(input.id, input.bottle.as_deref()).await?;
+        let snapshots = self.state.bottles.list_snapshots(id).await?;
+        Ok(SnapshotList { snapshots })
+    }
+
+    async fn snapshot_restore(&self, input: SnapshotParams) -> Result<BottleReply> {
+        let id = self.select_bottle(input.id, input.bottle.as_deref()).await?;
+        // the lock and the wineserver check cover processes started
+        // elsewhere; this gives the clearer message for our own
+        if !input.wait && self.state.processes.runs_in(id) {
+            let busy = BottleBusy {
+                bottle_id: id,
+                holders: Vec::new(),
+            };
+            return Err(anyhow::Error::new(busy)
+                .context("the bottle has running wine processes, stop them before restoring"));
+        }
+        let method = methods::BottleSnapshotRestore::NAME;
+        let lock = self
+            .lock_bottle(id, LockMode::Exclusive, method, input.wait)
+            .await?;
+        let bottle = self
+            .state
+            .bottles
+            .restore_snapshot(&lock, input.snapshot)
+            .await?;
+        info!("restored bottle {} from snapshot {}", id, input.snapshot);
+        self.state
+            .events
+            .emit(DaemonEvent::BottleUpdated { bottle_id: id });
+        Ok(BottleReply { bottle })
+    }
+
+    async fn snapshot_delete(&self, input: SnapshotParams) -> Result<SnapshotDeleted> {
+        let id = self.select_bottle(input.id, input.bottle.as_deref()).await?;
+        let method = methods::BottleSnapshotDelete::NAME;
+        let lock = self
+            .lock_bottle(id, LockMode::Shared, method, input.wait)
+            .await?;
+        self.state
+            .bottles
+            .remove_snapshot(&lock, input.snapshot)
+            .await?;
+        Ok(SnapshotDeleted {
+            deleted: input.snapshot,
+        })
+    }
+
+    /// the bottle a call is about: `id`, or whatever bottle the `bottle`
+    /// selector resolves to by uuid, uuid prefix or name.
+    async fn select_bottle(&self, id: Option<Uuid>, bottle: Option<&str>) -> Result<Uuid> {
+        match (id, bottle) {
+            (Some(id), None) => Ok(id),
+            (None, Some(selector)) => Ok(self.state.bottles.resolve(selector).await?.id),
+            (Some(_), Some(_)) => Err(RpcFault::InvalidParams(
+                "name the bottle by id or by bottle, not both".to_string(),
+            )
+            .into()),
+            (None, None) => {
+                Err(RpcFault::InvalidParams("no bottle given; set id or bottle".to_string()).into())
+            }
+        }
+    }
+
+    /// like `select_bottle`, but the `bottle` selector is resolved among the
+    /// bottles in the trash.
+    async fn select_trashed(&self, id: Option<Uuid>, bottle: Option<&str>) -> Result<Uuid> {
+        let (None, Some(selector)) = (id, bottle) else {
+            return self.select_bottle(id, bottle).await;
+        };
+        let trashed: Vec<_> = self
+            .state
+            .bottles
+            .list_trash()
+            .await?
+            .into_iter()
+            .map(|trashed| trashed.bottle)
+            .collect();
+        Ok(select_bottle(&trashed, selector)?.id)
+    }
+
+    /// takes a bottle's lock on behalf of `method`. a busy bottle fails the
+    /// call with `BottleBusy` unless the caller asked to wait for it.
+    async fn lock_bottle(
+        &self,
+        id: Uuid,
+        mode: LockMode,
+        method: &str,
+        wait: bool,
+    ) -> Result<BottleLock> {
+        if wait {
+            self.state.bottles.lock_waiting(id, mode, method).await
+        } else {
+            self.state.bottles.lock(id, mode, method)
+        }
+    }
+
+    async fn run_executable(&self, record: BottleRecord, input: BottleRunParams) -> Result<RunResult> {
+        let prefix = self.state.bottles.bottle_prefix(record.id);
         let mut args = vec![input.executable.to_string_lossy().to_string()];
         if let Some(rest) = input.args {
             args.extend(rest);
         }
+        let env: Vec<(String, String)> = input.env.into_iter().collect();
         let status = run_wine_command(
+            &self.state,
             &record,
             &prefix,
             record.wine_runtime.wine64_path.clone(),
             args,
-            &[],
+            &env,
         )
         .await?;
-        Ok(json!({
-            "exit_status": status.code(),
-            "success": status.success(),
-        }))
+        Ok(RunResult {
+            exit_status: status.code(),
+            success: status.success(),
+        })
+    }
+
+    async fn apply_recipe(&self, bottle_id: Uuid, recipe: Recipe) -> Result<RecipeApplied> {
+        let outcome = self.apply_recipe_steps(bottle_id, recipe).await;
+        self.state.metrics.recipe_applied(outcome.is_ok());
+        outcome
     }
 
-    async fn apply_recipe(&self, bottle_id: Uuid, recipe: Recipe) -> Result<Value> {
+    async fn apply_recipe_steps(&self, bottle_id: Uuid, recipe: Recipe) -> Result<RecipeApplied> {
         let mut record = self.state.bottles.record(bottle_id).await?;
         let prefix = self.state.bottles.bottle_prefix(bottle_id);
-        for step in recipe.manifest.steps.iter() {
-            match step {
-                RecipeStep::Run { path, args } => {
-                    let resolved = recipe.resource(path);
-                    run_wine_command(
-                        &record,
-                        &prefix,
-                        resolved,
-                        args.clone(),
-                        &[],
-                    )
-                    .await?;
+        let recipe_id = recipe.manifest.id.clone();
+        for (index, step) in recipe.manifest.steps.iter().enumerate() {
+            let kind = step_action(step);
+            let action = kind.to_string();
+            self.state.events.emit(DaemonEvent::RecipeStepStarted {
+                bottle_id,
+                recipe_id: recipe_id.clone(),
+                step: index,
+                action: action.clone(),
+            });
+            let started = Instant::now();
+            let outcome = self.apply_step(&recipe, step, &mut record, &prefix).await;
+            self.state.metrics.recipe_step(kind, started.elapsed());
+            match outcome {
+                Ok(()) => self.state.events.emit(DaemonEvent::RecipeStepFinished {
+                    bottle_id,
+                    recipe_id: recipe_id.clone(),
+                    step: index,
+                    action,
+                }),
+                Err(err) => {
+                    self.state.events.emit(DaemonEvent::RecipeStepFailed {
+                        bottle_id,
+                        recipe_id: recipe_id.clone(),
+                        step: index,
+                        action,
+                        error: format!("{err:#}"),
+                    });
+                    return Err(err);
                 }
-                RecipeStep::WaitForExit => {
-                    tracing::info!("wait step implicitly satisfied (processes run synchronously)");
+            }
+        }
+        self.state.bottles.update_record(bottle_id, &record).await?;
+        self.state
+            .events
+            .emit(DaemonEvent::BottleUpdated { bottle_id });
+        Ok(RecipeApplied { applied: recipe_id })
+    }
+
+    async fn apply_step(
+        &self,
+        recipe: &Recipe,
+        step: &RecipeStep,
+        record: &mut BottleRecord,
+        prefix: &PathBuf,
+    ) -> Result<()> {
+        match step {
+            RecipeStep::Run { path, args } => {
+                let resolved = recipe.resource(path);
+                run_wine_command(
+                    &self.state,
+                    record,
+                    prefix,
+                    resolved,
+                    args.clone(),
+                    &[],
+                )
+                .await?;
+            }
+            RecipeStep::WaitForExit => {
+                tracing::info!("wait step implicitly satisfied (processes run synchronously)");
+            }
+            RecipeStep::WineCfg { version } => {
+                if let Some(version) = version {
+                    record.environment.push((
+                        "WINE_DEFAULT_VERSION".to_string(),
+                        version.clone(),
+                    ));
                 }
-                RecipeStep::WineCfg { version } => {
-                    if let Some(version) = version {
-                        record.environment.push((
-                            "WINE_DEFAULT_VERSION".to_string(),
-                            version.clone(),
-                        ));
-                    }
-                    let winecfg_path = record
-                        .wine_runtime
-                        .wine64_path
-                        .parent()
-                        .map(|p| p.join("winecfg"))
-                        .ok_or_else(|| anyhow!("wine runtime missing winecfg companion"))?;
-                    run_wine_command(
-                        &record,
-                        &prefix,
-                        winecfg_path,
-                        vec![],
-                        &[],
-                    )
-                    .await?;
+                let winecfg_path = record
+                    .wine_runtime
+                    .wine64_path
+                    .parent()
+                    .map(|p| p.join("winecfg"))
+                    .ok_or_else(|| anyhow!("wine runtime missing winecfg companion"))?;
+                run_wine_command(
+                    &self.state,
+                    record,
+                    prefix,
+                    winecfg_path,
+                    vec![],
+                    &[],
+                )
+                .await?;
+            }
+            RecipeStep::Env { variables } => {
+                for (key, value) in variables {
+                    record
+                        .environment
+                        .retain(|(existing, _)| existing != key);
+                    record.environment.push((key.clone(), value.clone()));
                 }
-                RecipeStep::Env { variables } => {
-                    for (key, value) in variables {
-                        record
-                            .environment
-                            .retain(|(existing, _)| existing != key);
-                        record.environment.push((key.clone(), value.clone()));
-                    }
+            }
+            RecipeStep::Copy { from, to } => {
+                let source = recipe.resource(from);
+                if !source.exists() {
+                    return Err(anyhow!(
+                        "recipe resource {:?} is missing",
+                        source
+                    ));
                 }
-                RecipeStep::Copy { from, to } => {
-                    let source = recipe.resource(from);
-                    if !source.exists() {
-                        return Err(anyhow!(
-                            "recipe resource {:?} is missing",
-                            source
-                        ));
-                    }
-                    let destination = prefix.join(to);
-                    if let Some(parent) = destination.parent() {
-                        fs::create_dir_all(parent).await?;
-                    }
-                    fs::copy(&source, &destination).await?;
+                let destination = prefix.join(to);
+                if let Some(parent) = destination.parent() {
+                    fs::create_dir_all(parent).await?;
                 }
+                fs::copy(&source, &destination).await?;
             }
         }
-        self.state.bottles.update_record(bottle_id, &record).await?;
-        Ok(json!({ "applied": recipe.manifest.id }))
+        Ok(())
+    }
+}
+
+fn step_action(step: &RecipeStep) -> &'static str {
+    match step {
+        RecipeStep::Run { .. } => "run",
+        RecipeStep::WaitForExit => "wait_for_exit",
+        RecipeStep::WineCfg { .. } => "winecfg",
+        RecipeStep::Env { .. } => "env",
+        RecipeStep::Copy { .. } => "copy",
     }
 }
 
@@ -360,13 +1282,55 @@ fn default_shortcut_dir() -> Result<PathBuf> {
     Ok(home.join("Applications").join("Silicon Alloy"))
 }
 
-fn default_wine_path(runtime_dir: &PathBuf, version: &str) -> PathBuf {
+fn default_wine_path(runtime_dir: &Path, version: &str) -> PathBuf {
     runtime_dir
         .join(format!("wine-x86_64-{version}"))
         .join("bin")
         .join("wine64")
 }
 
+impl Settings {
+    fn new(config: DaemonConfig) -> Self {
+        let mut runtimes = Vec::new();
+        for root in &config.runtime_roots.value {
+            match discover_runtimes(root) {
+                Ok(found) => runtimes.extend(found),
+                Err(err) => warn!("unable to scan runtimes in {}: {err}", root.display()),
+            }
+        }
+        for extra in &config.extra_runtimes.value {
+            if extra.wine64_path.exists() {
+                runtimes.push(extra.clone());
+            } else {
+                warn!(
+                    "skipping runtime {}: {} does not exist",
+                    extra.label,
+                    extra.wine64_path.display()
+                );
+            }
+        }
+        if runtimes.is_empty() {
+            warn!("no wine runtimes discovered under {:?}", config.runtime_roots.value);
+        }
+        let access = AccessPolicy::from_config(&config);
+        Self {
+            config,
+            runtimes,
+            access,
+        }
+    }
+
+    /// where runtimes are expected when none matches, the first root.
+    fn runtime_dir(&self) -> &Path {
+        self.config
+            .runtime_roots
+            .value
+            .first()
+            .map(PathBuf::as_path)
+            .unwrap_or(Path::new(""))
+    }
+}
+
 impl DaemonService {
     fn select_runtime(&self, input: &BottleCreateParams) -> Result<WineRuntime> {
         if let Some(path) = &input.wine_path {
@@ -380,17 +1344,19 @@ impl DaemonService {
                 channel: input.channel.clone().or_else(|| Some("custom".to_string())),
             });
         }
-        let channel = input.channel.clone().unwrap_or_else(|| "rossetta".to_string());
-        if let Some(descriptor) = self
-            .state
+        let settings = self.settings();
+        let channel = input
+            .channel
+            .clone()
+            .unwrap_or_else(|| settings.config.default_channel.value.clone());
+        if let Some(descriptor) = settings
             .runtimes
             .iter()
             .find(|rt| rt.channel == channel && rt.version == input.wine_version)
         {
             return Ok(Self::descriptor_to_runtime(descriptor.clone(), input.wine_label.clone()));
         }
-        if let Some(descriptor) = self
-            .state
+        if let Some(descriptor) = settings
             .runtimes
             .iter()
             .find(|rt| rt.channel == channel)
@@ -402,7 +1368,7 @@ impl DaemonService {
                 .wine_label
                 .clone()
                 .unwrap_or_else(|| format!("wine {}", input.wine_version)),
-            wine64_path: default_wine_path(&self.state.runtime_dir, &input.wine_version),
+            wine64_path: default_wine_path(settings.runtime_dir(), &input.wine_version),
             version: input.wine_version.clone(),
             channel: Some(channel),
         };
@@ -418,54 +1384,47 @@ impl DaemonService {
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct BottleCreateParams {
-    name: String,
-    wine_version: String,
-    #[serde(default)]
-    wine_label: Option<String>,
-    #[serde(default)]
-    wine_path: Option<PathBuf>,
-    #[serde(default)]
-    channel: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
-struct BottleDeleteParams {
-    id: Uuid,
-}
-
-#[derive(Debug, Deserialize)]
-struct BottleRunParams {
-    id: Uuid,
-    executable: PathBuf,
-    #[serde(default)]
-    args: Option<Vec<String>>,
+/// the daemon's working directory means nothing to the client, so paths it
+/// reads or writes for one must be absolute.
+fn require_absolute(path: &Path) -> Result<()> {
+    if path.is_absolute() {
+        Ok(())
+    } else {
+        Err(RpcFault::InvalidParams(format!("{} is not an absolute path", path.display())).into())
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct RecipeApplyParams {
-    bottle_id: Uuid,
-    recipe_id: String,
+fn unknown_job(id: Uuid) -> anyhow::Error {
+    RpcFault::InvalidParams(format!("unknown job {id}")).into()
 }
 
-#[derive(Debug, Deserialize)]
-struct ShortcutCreateParams {
-    bottle_id: Uuid,
-    name: String,
-    executable: String,
-    #[serde(default)]
-    destination: Option<PathBuf>,
+/// decodes the params of `M`, runs `handler` and encodes its result. decode
+/// failures are tagged so they surface as invalid params rather than
+/// internal errors.
+async fn dispatch<M, F, Fut>(_method: M, params: Value, handler: F) -> Result<Value>
+where
+    M: Method,
+    F: FnOnce(M::Params) -> Fut,
+    Fut: Future<Output = Result<M::Result>>,
+{
+    let input = parse_params::<M::Params>(params, M::NAME)?;
+    let result = handler(input).await?;
+    Ok(serde_json::to_value(result)?)
 }
 
-fn recipe_dir() -> Result<PathBuf> {
-    if let Ok(custom) = std::env::var("SILICON_ALLOY_RECIPES") {
-        return Ok(PathBuf::from(custom));
-    }
-    default_recipe_root()
+fn parse_params<T: DeserializeOwned>(params: Value, method: &str) -> Result<T> {
+    // absent params mean "no params", which decode like an empty object
+    let params = if params.is_null() {
+        Value::Object(Default::default())
+    } else {
+        params
+    };
+    serde_json::from_value(params)
+        .map_err(|err| RpcFault::InvalidParams(format!("invalid {method} params: {err}")).into())
 }
 
 async fn run_wine_command(
+    state: &State,
     record: &BottleRecord,
     prefix: &PathBuf,
     command: PathBuf,
@@ -477,7 +1436,8 @@ async fn run_wine_command(
      * so rosetta reliably fronts every wine invocation. apple's
      * translator actually kicks in, doing it here means the env we curate for the bottle is exactly what wine sees,
      * and the exit status we bubble up is authoritative. the synchronous wait keeps
-     * state updates deterministic for the caller
+     * state updates deterministic for the caller; callers that cannot block run
+     * this inside a job instead
     */
     let mut cmd = Command::new("arch");
     cmd.arg("-x86_64")
@@ -491,7 +1451,36 @@ async fn run_wine_command(
         cmd.env(k, v);
     }
     cmd.current_dir(prefix);
-    let status = cmd.status().await?;
+    // background jobs are cancelled by dropping their future; take wine down
+    // with it instead of leaving an orphan behind
+    cmd.kill_on_drop(true);
+    // keep wine out of the daemon's process group, so a ctrl-c aimed at the
+    // daemon is handled by its shutdown policy instead of hitting wine too
+    cmd.process_group(0);
+    let mut child = cmd.spawn()?;
+    state.metrics.process_started();
+    let pid = child.id();
+    let _tracked = pid.map(|pid| {
+        state.processes.track(
+            pid,
+            record.id,
+            prefix.clone(),
+            record.wine_runtime.wine64_path.with_file_name("wineserver"),
+        )
+    });
+    state.events.emit(DaemonEvent::ProcessStarted {
+        bottle_id: record.id,
+        pid,
+        command: command.clone(),
+    });
+    let status = child.wait().await?;
+    state.metrics.process_exited(status.code());
+    state.events.emit(DaemonEvent::ProcessExited {
+        bottle_id: record.id,
+        pid,
+        exit_status: status.code(),
+        success: status.success(),
+    });
     if !status.success() {
         warn!(
             "wine command {:?} exited with {:?}",
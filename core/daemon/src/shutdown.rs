@@ -0,0 +1,116 @@
+use std::time::{Duration, Instant};
+
+use silicon_alloy_shared::api::ChildPolicy;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+
+/// broadcast point for "stop the daemon". the first request wins and
+/// decides what happens to running wine processes.
+#[derive(Clone)]
+pub struct Shutdown {
+    state: watch::Sender<Option<ChildPolicy>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (state, _) = watch::channel(None);
+        Self { state }
+    }
+
+    pub fn trigger(&self, children: ChildPolicy) {
+        self.state.send_if_modified(|state| {
+            if state.is_some() {
+                return false;
+            }
+            *state = Some(children);
+            true
+        });
+    }
+
+    /// resolves once shutdown was requested, with the child policy to apply.
+    pub async fn requested(&self) -> ChildPolicy {
+        let mut state = self.state.subscribe();
+        let requested = state.wait_for(Option::is_some).await;
+        // the sender lives in `self`, so the channel cannot close under us
+        requested
+            .ok()
+            .and_then(|state| *state)
+            .unwrap_or_default()
+    }
+}
+
+/// what a signal sent to the daemon asks for.
+pub enum Signal {
+    /// SIGTERM or SIGINT
+    Stop(&'static str),
+    /// SIGHUP: re-read the config file
+    Reload,
+}
+
+pub struct Signals {
+    terminate: tokio::signal::unix::Signal,
+    interrupt: tokio::signal::unix::Signal,
+    hangup: tokio::signal::unix::Signal,
+}
+
+impl Signals {
+    pub fn new() -> std::io::Result<Self> {
+        Ok(Self {
+            terminate: signal(SignalKind::terminate())?,
+            interrupt: signal(SignalKind::interrupt())?,
+            hangup: signal(SignalKind::hangup())?,
+        })
+    }
+
+    pub async fn recv(&mut self) -> Signal {
+        tokio::select! {
+            _ = self.terminate.recv() => Signal::Stop("SIGTERM"),
+            _ = self.interrupt.recv() => Signal::Stop("SIGINT"),
+            _ = self.hangup.recv() => Signal::Reload,
+        }
+    }
+}
+
+/// measures how long the daemon has had nothing to do. it is idle while no
+/// client is connected and nothing runs on its behalf.
+#[derive(Default)]
+pub struct IdleTimer {
+    idle_since: Option<Instant>,
+}
+
+impl IdleTimer {
+    /// records the current state and returns true once the daemon has been
+    /// idle for the whole timeout. the timeout is passed in on every call
+    /// because a config reload may change it.
+    pub fn observe(&mut self, idle: bool, now: Instant, timeout: Duration) -> bool {
+        if !idle {
+            self.idle_since = None;
+            return false;
+        }
+        let since = *self.idle_since.get_or_insert(now);
+        now.duration_since(since) >= timeout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_timer_resets_on_activity() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(10);
+        let mut timer = IdleTimer::default();
+        assert!(!timer.observe(true, start, timeout));
+        assert!(!timer.observe(true, start + Duration::from_secs(9), timeout));
+        assert!(!timer.observe(false, start + Duration::from_secs(9), timeout));
+        assert!(!timer.observe(true, start + Duration::from_secs(12), timeout));
+        assert!(timer.observe(true, start + Duration::from_secs(22), timeout));
+    }
+}
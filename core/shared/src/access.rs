@@ -0,0 +1,193 @@
+use std::collections::BTreeSet;
+use std::fs::DirBuilder;
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use tokio::net::UnixListener;
+
+use crate::config::DaemonConfig;
+
+/// what a connected peer may do.
+#[derive(Debug, Clone)]
+pub enum Access {
+    Full,
+    ReadOnly(Arc<BTreeSet<String>>),
+}
+
+impl Access {
+    pub fn allows(&self, method: &str) -> bool {
+        match self {
+            Access::Full => true,
+            Access::ReadOnly(methods) => methods.contains(method),
+        }
+    }
+}
+
+/// decides which local users may talk to a daemon. the user running the
+/// daemon always has full access; everyone else is turned away unless
+/// listed.
+#[derive(Debug, Clone)]
+pub struct AccessPolicy {
+    owner: u32,
+    allowed: BTreeSet<u32>,
+    read_only: BTreeSet<u32>,
+    read_only_methods: Arc<BTreeSet<String>>,
+}
+
+impl AccessPolicy {
+    /// a policy that only admits the current user.
+    pub fn owner_only(read_only_methods: &[&str]) -> Self {
+        Self {
+            owner: current_uid(),
+            allowed: BTreeSet::new(),
+            read_only: BTreeSet::new(),
+            read_only_methods: Arc::new(read_only_methods.iter().map(|m| m.to_string()).collect()),
+        }
+    }
+
+    /// the uids and read-only method list from the `[access]` section of
+    /// the config and its environment overrides.
+    pub fn from_config(config: &DaemonConfig) -> Self {
+        Self {
+            owner: current_uid(),
+            allowed: config.allowed_uids.value.clone(),
+            read_only: config.read_only_uids.value.clone(),
+            read_only_methods: Arc::new(config.read_only_methods.value.clone()),
+        }
+    }
+
+    /// `None` means the peer must be disconnected.
+    pub fn access_for(&self, uid: u32) -> Option<Access> {
+        if uid == self.owner || self.allowed.contains(&uid) {
+            Some(Access::Full)
+        } else if self.read_only.contains(&uid) {
+            Some(Access::ReadOnly(self.read_only_methods.clone()))
+        } else {
+            None
+        }
+    }
+
+    /// whether anyone besides the owner may connect.
+    pub fn is_shared(&self) -> bool {
+        !self.allowed.is_empty() || !self.read_only.is_empty()
+    }
+}
+
+/// binds the daemon socket so that only the owner can reach it: the socket
+/// is 0600 inside a 0700 directory. when the policy admits other users the
+/// socket becomes 0666 inside a 0711 directory, and the peer credential
+/// check is what keeps everyone else out.
+pub fn bind_socket(path: &Path, policy: &AccessPolicy) -> Result<UnixListener> {
+    socket_dir(path, policy)?;
+    let listener =
+        UnixListener::bind(path).with_context(|| format!("failed to bind {}", path.display()))?;
+    let socket_mode = if policy.is_shared() { 0o666 } else { 0o600 };
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(socket_mode))?;
+    Ok(listener)
+}
+
+/// makes sure the directory the socket goes in is ours and closed to other
+/// users, so nobody else can reach the socket in the moment before its own
+/// mode is set. a missing directory is created with the right mode. an
+/// existing one is left as it is, since it may hold more than our files:
+/// if its owner or mode is wrong the daemon refuses to start instead.
+pub fn socket_dir<'a>(path: &'a Path, policy: &AccessPolicy) -> Result<&'a Path> {
+    let parent = path
+        .parent()
+        .ok_or_else(|| anyhow!("socket path {} has no parent", path.display()))?;
+    let dir_mode = if policy.is_shared() { 0o711 } else { 0o700 };
+    if let Some(above) = parent.parent() {
+        std::fs::create_dir_all(above)
+            .with_context(|| format!("unable to create {}", above.display()))?;
+    }
+    match DirBuilder::new().mode(dir_mode).create(parent) {
+        // the umask may have taken bits we need
+        Ok(()) => std::fs::set_permissions(parent, std::fs::Permissions::from_mode(dir_mode))?,
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("unable to create socket directory {}", parent.display()))
+        }
+    }
+    let metadata = std::fs::metadata(parent)?;
+    if metadata.uid() != current_uid() {
+        bail!(
+            "socket directory {} belongs to uid {}, refusing to use it",
+            parent.display(),
+            metadata.uid()
+        );
+    }
+    let mode = metadata.permissions().mode() & 0o777;
+    if mode & 0o066 != 0 {
+        bail!(
+            "socket directory {} has mode {mode:04o}, which lets other users replace the socket; \
+             expected {dir_mode:04o}",
+            parent.display()
+        );
+    }
+    if policy.is_shared() && mode & 0o001 == 0 {
+        bail!(
+            "socket directory {} has mode {mode:04o}, which keeps the allowed uids from reaching \
+             the socket; expected {dir_mode:04o}",
+            parent.display()
+        );
+    }
+    Ok(parent)
+}
+
+pub fn current_uid() -> u32 {
+    // geteuid cannot fail
+    unsafe { libc::geteuid() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peers_get_the_access_they_were_granted() {
+        let mut policy = AccessPolicy::owner_only(&["service.ping"]);
+        assert!(matches!(
+            policy.access_for(current_uid()),
+            Some(Access::Full)
+        ));
+        assert!(policy.access_for(current_uid() + 1).is_none());
+        assert!(!policy.is_shared());
+
+        policy.read_only.insert(current_uid() + 1);
+        let access = policy.access_for(current_uid() + 1).unwrap();
+        assert!(access.allows("service.ping"));
+        assert!(!access.allows("bottle.delete"));
+        assert!(policy.is_shared());
+    }
+
+    #[tokio::test]
+    async fn socket_is_private_to_the_owner() {
+        let dir =
+            std::env::temp_dir().join(format!("silicon-alloy-access-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("daemon.sock");
+        let _listener = bind_socket(&path, &AccessPolicy::owner_only(&[])).unwrap();
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dir), 0o700);
+        assert_eq!(mode(&path), 0o600);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // a directory we didn't create is checked, not changed
+        let mut shared = AccessPolicy::owner_only(&[]);
+        shared.allowed.insert(current_uid() + 1);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(bind_socket(&path, &AccessPolicy::owner_only(&[])).is_err());
+        assert!(bind_socket(&path, &shared).is_err());
+        assert_eq!(mode(&dir), 0o755);
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700)).unwrap();
+        assert!(bind_socket(&path, &shared).is_err());
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o711)).unwrap();
+        let _shared = bind_socket(&path, &shared).unwrap();
+        assert_eq!(mode(&path), 0o666);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
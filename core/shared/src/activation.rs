@@ -0,0 +1,90 @@
+use std::os::fd::{FromRawFd, RawFd};
+use std::os::unix::net::UnixListener;
+
+use anyhow::{bail, Context, Result};
+
+/// first fd handed over by the service manager (`SD_LISTEN_FDS_START`).
+const LISTEN_FDS_START: RawFd = 3;
+
+/// picks up a listening socket passed in by systemd (or launchd mimicking
+/// its protocol through `LISTEN_FDS` / `LISTEN_PID`). returns `None` when
+/// the daemon was started normally and has to bind its own socket. the
+/// listener is non-blocking, ready for `tokio::net::UnixListener::from_std`.
+///
+/// # Safety
+///
+/// clears the activation variables from the environment, which races with
+/// any other thread reading or writing it. call this from `main` before the
+/// async runtime or anything else has started a thread.
+pub unsafe fn inherited_listener() -> Result<Option<UnixListener>> {
+    let pid = std::env::var("LISTEN_PID").ok();
+    let fds = std::env::var("LISTEN_FDS").ok();
+    // wine and anything else we spawn must not think the fds are theirs
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+    take_listener(pid.as_deref(), fds.as_deref(), LISTEN_FDS_START)
+}
+
+fn take_listener(pid: Option<&str>, fds: Option<&str>, fd: RawFd) -> Result<Option<UnixListener>> {
+    let (Some(pid), Some(fds)) = (pid, fds) else {
+        return Ok(None);
+    };
+    let pid: u32 = pid.trim().parse().context("invalid LISTEN_PID")?;
+    if pid != std::process::id() {
+        // meant for a process further up the chain, not us
+        return Ok(None);
+    }
+    let fds: u32 = fds.trim().parse().context("invalid LISTEN_FDS")?;
+    match fds {
+        0 => return Ok(None),
+        1 => {}
+        n => bail!("expected one socket from the service manager, got {n}"),
+    }
+    // also fails on a closed fd, before we claim ownership of it
+    set_cloexec(fd)?;
+    // the service manager guarantees the fd is open and ours from here on
+    let listener = unsafe { UnixListener::from_raw_fd(fd) };
+    listener
+        .set_nonblocking(true)
+        .context("inherited fd is not a unix socket")?;
+    Ok(Some(listener))
+}
+
+fn set_cloexec(fd: RawFd) -> Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) } < 0 {
+        return Err(std::io::Error::last_os_error())
+            .context("unable to mark listener close-on-exec");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::fd::IntoRawFd;
+
+    #[tokio::test]
+    async fn takes_over_a_listener_meant_for_us() {
+        let path = std::env::temp_dir().join(format!(
+            "silicon-alloy-activation-{}.sock",
+            uuid::Uuid::new_v4()
+        ));
+        let bound = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let pid = std::process::id().to_string();
+
+        assert!(take_listener(None, Some("1"), -1).unwrap().is_none());
+        assert!(take_listener(Some("1"), Some("1"), -1).unwrap().is_none());
+
+        let listener = take_listener(Some(&pid), Some("1"), bound.into_raw_fd())
+            .unwrap()
+            .unwrap();
+        let listener = tokio::net::UnixListener::from_std(listener).unwrap();
+        let (client, accepted) =
+            tokio::join!(tokio::net::UnixStream::connect(&path), listener.accept());
+        client.unwrap();
+        accepted.unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
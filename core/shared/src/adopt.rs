@@ -0,0 +1,398 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use uuid::Uuid;
+
+use crate::copy::Copier;
+use crate::{
+    rewrite_hives, unix_timestamp, BottleRecord, BottleStore, WineRuntime, SCHEMA_VERSION,
+};
+
+/// the registry key wine keeps the windows version it pretends to be under.
+const CURRENT_VERSION_KEY: &str = r"software\\microsoft\\windows nt\\currentversion";
+
+/// how `bottle.adopt` takes over a prefix.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AdoptMode {
+    /// copy it into the bottle root, leaving the original alone
+    #[default]
+    Copy,
+    /// use it where it is, through a symlink from the bottle's directory
+    Link,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PrefixArch {
+    Win32,
+    Win64,
+}
+
+/// what an existing prefix's `system.reg` says about it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct PrefixInfo {
+    /// from the `#arch=` header; prefixes from before wine 1.3 have none
+    pub arch: Option<PrefixArch>,
+    /// the version as winecfg names it, like `win10` or `win7`
+    pub windows_version: Option<String>,
+    pub product_name: Option<String>,
+    pub build: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AdoptedBottle {
+    pub bottle: BottleRecord,
+    pub prefix: PrefixInfo,
+    /// things the bottle may need fixing by hand, like a 32-bit prefix
+    pub warnings: Vec<String>,
+}
+
+impl BottleStore {
+    /// registers the wine prefix at `source` as a new bottle called `name`.
+    /// a copy gets the same fix-ups as a clone: symlinks and registry paths
+    /// naming `source` are pointed at the copy. nothing may be running in
+    /// the prefix meanwhile.
+    pub async fn adopt_prefix(
+        &self,
+        source: &Path,
+        name: &str,
+        runtime: WineRuntime,
+        mode: AdoptMode,
+    ) -> Result<AdoptedBottle> {
+        let source = fs::canonicalize(source)
+            .await
+            .with_context(|| format!("unable to open {}", source.display()))?;
+        let root = fs::canonicalize(&self.root).await?;
+        if source.starts_with(&root) {
+            bail!("{} is already in the bottle root", source.display());
+        }
+        let prefix = inspect_prefix(&source).await?;
+        let id = Uuid::new_v4();
+        self.check_name(name, id).await?;
+        let bottle_dir = self.root.join(id.to_string());
+        fs::create_dir_all(&bottle_dir)
+            .await
+            .context("failed to create bottle directory")?;
+        let adopted = self.take_prefix(&source, id, mode).await;
+        if let Err(err) = adopted {
+            // never follows the link, so a linked prefix stays as it was
+            let _ = fs::remove_dir_all(&bottle_dir).await;
+            return Err(err).with_context(|| format!("failed to adopt {}", source.display()));
+        }
+        let record = BottleRecord {
+            schema_version: SCHEMA_VERSION,
+            id,
+            name: name.trim().to_string(),
+            created_at: unix_timestamp(),
+            wine_runtime: runtime,
+            environment: Vec::new(),
+            notes: None,
+            tags: Vec::new(),
+        };
+        // written last, so an adoption that failed halfway is never listed
+        if let Err(err) = self.write_named_record(&bottle_dir, &record).await {
+            let _ = fs::remove_dir_all(&bottle_dir).await;
+            return Err(err);
+        }
+        let mut warnings = Vec::new();
+        match prefix.arch {
+            Some(PrefixArch::Win64) => {}
+            Some(PrefixArch::Win32) => warnings.push(
+                "the prefix is 32-bit only (#arch=win32); 64-bit wine can't run it".to_string(),
+            ),
+            None => warnings.push(
+                "system.reg has no #arch= header, so the prefix may be too old to run".to_string(),
+            ),
+        }
+        Ok(AdoptedBottle {
+            bottle: record,
+            prefix,
+            warnings,
+        })
+    }
+
+    /// where the prefix of a bottle adopted in place really is, or `None`
+    /// for one that lives in the bottle's directory. paths in such a prefix
+    /// name that place rather than the bottle.
+    pub(crate) async fn linked_prefix(&self, id: Uuid) -> Result<Option<PathBuf>> {
+        let prefix = self.bottle_prefix(id);
+        if !fs::symlink_metadata(&prefix).await?.is_symlink() {
+            return Ok(None);
+        }
+        let real = fs::canonicalize(&prefix)
+            .await
+            .with_context(|| format!("the prefix of bottle {id} links to nothing"))?;
+        Ok(Some(real))
+    }
+
+    async fn take_prefix(&self, source: &Path, id: Uuid, mode: AdoptMode) -> Result<()> {
+        let prefix = self.bottle_prefix(id);
+        match mode {
+            AdoptMode::Link => Ok(fs::symlink(source, &prefix).await?),
+            AdoptMode::Copy => {
+                let (from, to) = (source.to_path_buf(), prefix.clone());
+                tokio::task::spawn_blocking(move || {
+                    Copier::new(None)
+                        .retarget(from.clone(), to.clone())
+                        .copy_tree(&from, &to)
+                })
+                .await??;
+                rewrite_hives(&prefix, source, &prefix).await
+            }
+        }
+    }
+}
+
+/// reads what can be told about the prefix at `prefix` from its
+/// `system.reg`. a directory without one isn't a wine prefix.
+pub async fn inspect_prefix(prefix: &Path) -> Result<PrefixInfo> {
+    let path = prefix.join("system.reg");
+    let data = fs::read(&path)
+        .await
+        .with_context(|| format!("{} is not a wine prefix: no system.reg", prefix.display()))?;
+    Ok(parse_system_reg(&String::from_utf8_lossy(&data)))
+}
+
+fn parse_system_reg(text: &str) -> PrefixInfo {
+    let mut info = PrefixInfo::default();
+    let mut current_version = None;
+    let mut in_key = false;
+    for line in text.lines() {
+        if let Some(arch) = line.strip_prefix("#arch=") {
+            info.arch = match arch.trim() {
+                "win32" => Some(PrefixArch::Win32),
+                "win64" => Some(PrefixArch::Win64),
+                _ => None,
+            };
+        } else if let Some(key) = line.strip_prefix('[') {
+            let key = key.split(']').next().unwrap_or_default();
+            in_key = key.eq_ignore_ascii_case(CURRENT_VERSION_KEY);
+        } else if in_key {
+            let Some((name, value)) = string_value(line) else {
+                continue;
+            };
+            match name.to_ascii_lowercase().as_str() {
+                "productname" => info.product_name = Some(value),
+                "currentbuild" | "currentbuildnumber" => {
+                    info.build = info.build.or(value.parse().ok());
+                }
+                "currentversion" => current_version = Some(value),
+                _ => {}
+            }
+        }
+    }
+    info.windows_version = windows_version(info.build, current_version.as_deref());
+    info
+}
+
+/// `"Name"="value"`, the way wine writes a string value.
+fn string_value(line: &str) -> Option<(String, String)> {
+    let (name, value) = line.strip_prefix('"')?.split_once("\"=\"")?;
+    let value = value.strip_suffix('"')?;
+    Some((
+        name.to_string(),
+        value.replace("\\\"", "\"").replace("\\\\", "\\"),
+    ))
+}
+
+/// the winecfg name for a windows build, or the nt version when a prefix
+/// doesn't record the build.
+fn windows_version(build: Option<u32>, current_version: Option<&str>) -> Option<String> {
+    let name = match build {
+        Some(22000..) => "win11",
+        Some(10240..) => "win10",
+        Some(9600..) => "win81",
+        Some(9200..) => "win8",
+        Some(7600..) => "win7",
+        Some(6000..) => "vista",
+        Some(3790..) => "win2003",
+        Some(2600..) => "winxp",
+        Some(_) => return None,
+        None => match current_version? {
+            "10.0" => "win10",
+            "6.3" => "win81",
+            "6.2" => "win8",
+            "6.1" => "win7",
+            "6.0" => "vista",
+            "5.2" => "win2003",
+            "5.1" => "winxp",
+            _ => return None,
+        },
+    };
+    Some(name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs as stdfs;
+
+    const SYSTEM_REG: &str = r#"WINE REGISTRY Version 2
+;; All keys relative to \\Machine
+
+#arch=win64
+
+[Software\\Microsoft\\Windows NT\\CurrentVersion] 1700000000
+#time=1da1b2c3d4e5f60
+"CurrentBuild"="19045"
+"CurrentBuildNumber"="19045"
+"CurrentVersion"="6.3"
+"InstallPath"="C:\\windows"
+"ProductName"="Windows 10 Pro"
+
+[Software\\Wow6432Node\\Microsoft\\Windows NT\\CurrentVersion] 1700000000
+"ProductName"="Windows 7"
+"#;
+
+    #[test]
+    fn system_reg_tells_arch_and_version() {
+        let info = parse_system_reg(SYSTEM_REG);
+        assert_eq!(info.arch, Some(PrefixArch::Win64));
+        assert_eq!(info.windows_version.as_deref(), Some("win10"));
+        assert_eq!(info.product_name.as_deref(), Some("Windows 10 Pro"));
+        assert_eq!(info.build, Some(19045));
+
+        let old = "WINE REGISTRY Version 2\n\n[Software\\\\Microsoft\\\\Windows NT\\\\CurrentVersion]\n\"CurrentVersion\"=\"5.1\"\n";
+        let info = parse_system_reg(old);
+        assert_eq!(info.arch, None);
+        assert_eq!(info.windows_version.as_deref(), Some("winxp"));
+    }
+
+    #[tokio::test]
+    async fn prefixes_are_copied_or_linked() {
+        let base = std::env::temp_dir().join(format!("silicon-alloy-adopt-{}", Uuid::new_v4()));
+        stdfs::create_dir_all(base.join("old-prefix/drive_c/windows")).unwrap();
+        let source = stdfs::canonicalize(base.join("old-prefix")).unwrap();
+        stdfs::create_dir_all(source.join("dosdevices")).unwrap();
+        let registry = SYSTEM_REG.to_string() + &format!("\"Root\"=\"{}\"\n", source.display());
+        stdfs::write(source.join("system.reg"), registry).unwrap();
+        std::os::unix::fs::symlink(source.join("drive_c"), source.join("dosdevices/c:")).unwrap();
+        let store = BottleStore::with_root(base.join("bottles")).unwrap();
+        let runtime = WineRuntime {
+            label: "wine".to_string(),
+            wine64_path: PathBuf::from("/opt/wine64"),
+            version: "9.0".to_string(),
+            channel: None,
+        };
+
+        let copied = store
+            .adopt_prefix(&source, "copied", runtime.clone(), AdoptMode::Copy)
+            .await
+            .unwrap();
+        assert_eq!(copied.prefix.windows_version.as_deref(), Some("win10"));
+        assert!(copied.warnings.is_empty());
+        let prefix = store.bottle_prefix(copied.bottle.id);
+        assert_eq!(
+            stdfs::read_link(prefix.join("dosdevices/c:")).unwrap(),
+            prefix.join("drive_c")
+        );
+        let hive = stdfs::read_to_string(prefix.join("system.reg")).unwrap();
+        assert!(hive.contains(&prefix.display().to_string()));
+
+        let linked = store
+            .adopt_prefix(&source, "linked", runtime.clone(), AdoptMode::Link)
+            .await
+            .unwrap();
+        let link = store.bottle_prefix(linked.bottle.id);
+        assert_eq!(stdfs::read_link(&link).unwrap(), source);
+        assert_eq!(store.list().await.unwrap().len(), 2);
+        // deleting the bottle leaves a linked prefix where it was
+        let lock = store
+            .lock(linked.bottle.id, crate::lock::LockMode::Exclusive, "test")
+            .unwrap();
+        store.remove(&lock).await.unwrap();
+        assert!(source.join("system.reg").exists());
+
+        assert!(store
+            .adopt_prefix(&source, "copied", runtime.clone(), AdoptMode::Copy)
+            .await
+            .is_err());
+        assert!(store
+            .adopt_prefix(&base, "not a prefix", runtime.clone(), AdoptMode::Link)
+            .await
+            .is_err());
+        assert!(store
+            .adopt_prefix(&prefix, "again", runtime, AdoptMode::Link)
+            .await
+            .is_err());
+        assert_eq!(store.list().await.unwrap().len(), 1);
+        stdfs::remove_dir_all(&base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn linked_prefixes_are_followed() {
+        let base = std::env::temp_dir().join(format!("silicon-alloy-adopt-{}", Uuid::new_v4()));
+        stdfs::create_dir_all(base.join("old-prefix/dosdevices")).unwrap();
+        let source = stdfs::canonicalize(base.join("old-prefix")).unwrap();
+        stdfs::create_dir_all(source.join("drive_c")).unwrap();
+        let registry = SYSTEM_REG.to_string() + &format!("\"Root\"=\"{}\"\n", source.display());
+        stdfs::write(source.join("system.reg"), &registry).unwrap();
+        std::os::unix::fs::symlink(source.join("drive_c"), source.join("dosdevices/c:")).unwrap();
+        let store = BottleStore::with_root(base.join("bottles")).unwrap();
+        let runtime = WineRuntime {
+            label: "wine".to_string(),
+            wine64_path: PathBuf::from("/opt/wine64"),
+            version: "9.0".to_string(),
+            channel: None,
+        };
+        let linked = store
+            .adopt_prefix(&source, "linked", runtime, AdoptMode::Link)
+            .await
+            .unwrap()
+            .bottle;
+        let lock = store
+            .lock(linked.id, crate::lock::LockMode::Exclusive, "test")
+            .unwrap();
+        let changes = crate::BottleChanges {
+            env_set: [(
+                "DATA".to_string(),
+                source.join("drive_c").display().to_string(),
+            )]
+            .into(),
+            ..Default::default()
+        };
+        store.update_bottle(&lock, &changes).await.unwrap();
+
+        // copies point at themselves rather than at the linked prefix
+        let copied = |copy: BottleRecord| {
+            let prefix = store.bottle_prefix(copy.id);
+            assert!(!stdfs::symlink_metadata(&prefix).unwrap().is_symlink());
+            assert_eq!(
+                stdfs::read_link(prefix.join("dosdevices/c:")).unwrap(),
+                prefix.join("drive_c")
+            );
+            let hive = stdfs::read_to_string(prefix.join("system.reg")).unwrap();
+            assert!(hive.contains(&prefix.display().to_string()), "{hive}");
+            assert!(!hive.contains(&source.display().to_string()), "{hive}");
+            let data = prefix.join("drive_c").display().to_string();
+            assert_eq!(copy.environment, [("DATA".to_string(), data)]);
+        };
+        copied(store.clone_bottle(&lock, "cloned").await.unwrap());
+        let archive = base.join("linked.tar.zst");
+        store.export_bottle(&lock, &archive).await.unwrap();
+        let imported = store
+            .import_bottle(&archive, Some("imported"), &[])
+            .await
+            .unwrap();
+        copied(imported.bottle);
+
+        // a restore goes into the linked prefix, and the link stays
+        let snapshot = store.create_snapshot(&lock, "adopted").await.unwrap();
+        stdfs::write(source.join("system.reg"), "changed").unwrap();
+        store.restore_snapshot(&lock, snapshot.id).await.unwrap();
+        let link = store.bottle_prefix(linked.id);
+        assert_eq!(stdfs::read_link(&link).unwrap(), source);
+        assert_eq!(
+            stdfs::read_to_string(source.join("system.reg")).unwrap(),
+            registry
+        );
+        // nothing is left next to the prefix
+        assert_eq!(stdfs::read_dir(&base).unwrap().count(), 3);
+        drop(lock);
+        stdfs::remove_dir_all(&base).unwrap();
+    }
+}
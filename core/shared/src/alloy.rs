@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::api::{methods, Method};
+use crate::{BottleRecord, WineRuntime};
+
+/// a request in the line protocol of alloyctl and alloy-daemon, which
+/// silicon-alloy-daemon answers too. these types mirror `alloy_core::rpc`
+/// field for field; alloy-core lives in a separate, differently licensed
+/// workspace and can't be depended on from here.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlloyRequest {
+    pub id: Uuid,
+    pub command: AlloyCommand,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum AlloyCommand {
+    Create {
+        name: String,
+    },
+    List,
+    Run {
+        name: String,
+        executable: String,
+        args: Vec<String>,
+        env: Option<HashMap<String, String>>,
+    },
+    Destroy {
+        name: String,
+        #[serde(default)]
+        permanent: bool,
+    },
+    Restore {
+        name: String,
+    },
+    ListTrash,
+    PurgeTrash {
+        #[serde(default)]
+        name: Option<String>,
+    },
+    Ping,
+    ListRecipes,
+    ApplyRecipe {
+        bottle: String,
+        recipe: String,
+    },
+}
+
+impl AlloyCommand {
+    /// the json-rpc method doing the same job. access rules, metrics and
+    /// read-only lists all go by this name.
+    pub fn method(&self) -> &'static str {
+        match self {
+            AlloyCommand::Create { .. } => methods::BottleCreate::NAME,
+            AlloyCommand::List => methods::BottleList::NAME,
+            AlloyCommand::Run { .. } => methods::BottleRun::NAME,
+            AlloyCommand::Destroy { .. } => methods::BottleDelete::NAME,
+            AlloyCommand::Restore { .. } => methods::BottleRestore::NAME,
+            AlloyCommand::ListTrash => methods::TrashList::NAME,
+            AlloyCommand::PurgeTrash { .. } => methods::TrashPurge::NAME,
+            AlloyCommand::Ping => methods::ServicePing::NAME,
+            AlloyCommand::ListRecipes => methods::RecipeList::NAME,
+            AlloyCommand::ApplyRecipe { .. } => methods::RecipeApply::NAME,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlloyResponse {
+    pub id: Uuid,
+    pub status: AlloyStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum AlloyStatus {
+    Ok,
+    Error { message: String },
+}
+
+impl AlloyResponse {
+    pub fn ok(id: Uuid, result: Option<Value>) -> Self {
+        Self {
+            id,
+            status: AlloyStatus::Ok,
+            result,
+        }
+    }
+
+    pub fn error(id: Uuid, message: impl Into<String>) -> Self {
+        Self {
+            id,
+            status: AlloyStatus::Error {
+                message: message.into(),
+            },
+            result: None,
+        }
+    }
+}
+
+/// what `list` and `create` return for a bottle: alloy-daemon's summary,
+/// plus the uuid silicon-alloy knows it by.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlloyBottle {
+    pub name: String,
+    pub id: Uuid,
+    pub path: PathBuf,
+    pub runtime: WineRuntime,
+}
+
+/// what `list_trash` returns for a trashed bottle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlloyTrashedBottle {
+    pub id: Uuid,
+    pub name: String,
+    /// unix seconds
+    pub deleted_at: u64,
+    pub size: u64,
+}
+
+/// true for a json-rpc payload that is really an alloyctl request: an object
+/// with a `command` member and no `method`.
+pub fn is_alloy_request(value: &Value) -> bool {
+    value.get("command").is_some() && value.get("method").is_none()
+}
+
+/// the name alloyctl uses for a bottle, the way alloy-daemon cleans up names:
+/// lowercase letters, digits, `-` and `_`, with spaces turned into dashes and
+/// anything else dropped. `None` when nothing usable is left.
+pub fn alloy_name(name: &str) -> Option<String> {
+    let mut clean = String::new();
+    for ch in name.trim().chars() {
+        match ch {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => clean.push(ch.to_ascii_lowercase()),
+            '-' | '_' => clean.push(ch),
+            ' ' => clean.push('-'),
+            _ => {}
+        }
+    }
+    let clean = clean.trim_matches(['-', '_']);
+    (!clean.is_empty()).then(|| clean.to_string())
+}
+
+/// the bottles alloyctl's `name` refers to: the one with that uuid if it is
+/// one, otherwise every bottle whose name cleans up to the same alloy name.
+pub fn matching_bottles<'a>(bottles: &'a [BottleRecord], name: &str) -> Vec<&'a BottleRecord> {
+    if let Ok(id) = name.trim().parse::<Uuid>() {
+        return bottles.iter().filter(|bottle| bottle.id == id).collect();
+    }
+    let Some(wanted) = alloy_name(name) else {
+        return Vec::new();
+    };
+    bottles
+        .iter()
+        .filter(|bottle| alloy_name(&bottle.name).as_deref() == Some(wanted.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_match_the_way_alloyctl_spells_them() {
+        assert_eq!(alloy_name("My Fancy App").as_deref(), Some("my-fancy-app"));
+        assert_eq!(alloy_name("  Steam (x86)! ").as_deref(), Some("steam-x86"));
+        assert_eq!(alloy_name("!!!"), None);
+
+        let runtime = WineRuntime {
+            label: "wine".to_string(),
+            wine64_path: PathBuf::from("/opt/wine64"),
+            version: "9.0".to_string(),
+            channel: None,
+        };
+        let bottle = |name: &str| BottleRecord {
+            schema_version: crate::SCHEMA_VERSION,
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            created_at: 0,
+            wine_runtime: runtime.clone(),
+            environment: Vec::new(),
+            notes: None,
+            tags: Vec::new(),
+        };
+        let bottles = vec![bottle("Steam"), bottle("steam"), bottle("Office 2010")];
+        assert_eq!(matching_bottles(&bottles, "STEAM").len(), 2);
+        assert_eq!(
+            matching_bottles(&bottles, "office-2010")[0].name,
+            "Office 2010"
+        );
+        let by_id = bottles[2].id.to_string();
+        assert_eq!(matching_bottles(&bottles, &by_id)[0].id, bottles[2].id);
+        assert!(matching_bottles(&bottles, "notepad").is_empty());
+    }
+
+    #[test]
+    fn tells_the_protocols_apart() {
+        let alloy = serde_json::json!({ "id": Uuid::new_v4(), "command": { "command": "ping" } });
+        assert!(is_alloy_request(&alloy));
+        let request: AlloyRequest = serde_json::from_value(alloy).unwrap();
+        assert_eq!(request.command.method(), "service.ping");
+        let rpc = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "service.ping" });
+        assert!(!is_alloy_request(&rpc));
+    }
+}
@@ -0,0 +1,772 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+use schemars::gen::SchemaGenerator;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::adopt::{AdoptMode, PrefixInfo};
+use crate::config::DaemonConfig;
+use crate::metrics::MetricsSnapshot;
+use crate::snapshot::SnapshotRecord;
+use crate::trash::TrashedBottle;
+use crate::usage::{BottleUsage, UsageCategory};
+use crate::{BottleChanges, BottleRecord, RuntimeDescriptor};
+
+/// method name used for event notifications pushed to subscribers.
+pub const EVENT_NOTIFICATION: &str = "events.event";
+/// method name used to tell a subscriber it fell behind and missed events.
+pub const LAGGED_NOTIFICATION: &str = "events.lagged";
+
+/// version of the wire protocol spoken over the daemon socket. bumped when a
+/// method is removed or changes shape incompatibly; new methods and new
+/// optional fields leave it alone, clients find those via `service.capabilities`.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// ties a json-rpc method name to its params and result types. the daemon,
+/// the cli and `silicon-alloy-client` all go through these, so the wire
+/// format is defined in exactly one place.
+pub trait Method {
+    const NAME: &'static str;
+    type Params: Serialize + DeserializeOwned + JsonSchema;
+    type Result: Serialize + DeserializeOwned + JsonSchema;
+}
+
+macro_rules! methods {
+    ($($ty:ident => $name:literal, $params:ty, $result:ty;)*) => {
+        $(
+            pub struct $ty;
+
+            impl super::Method for $ty {
+                const NAME: &'static str = $name;
+                type Params = $params;
+                type Result = $result;
+            }
+        )*
+
+        /// names of every method the daemon answers.
+        pub const NAMES: &[&str] = &[$($name),*];
+
+        /// every method with the json schema of its params and result.
+        pub fn catalog() -> Vec<super::MethodSchema> {
+            vec![$(super::MethodSchema::of::<$ty>()),*]
+        }
+    };
+}
+
+pub mod methods {
+    use super::*;
+
+    methods! {
+        ServicePing => "service.ping", Empty, PingReply;
+        ServiceInfo => "service.info", Empty, super::ServiceInfo;
+        ServiceCapabilities => "service.capabilities", Empty, Capabilities;
+        ServiceShutdown => "service.shutdown", ShutdownParams, ShuttingDown;
+        ServiceReload => "service.reload", Empty, Reloaded;
+        ServiceMetrics => "service.metrics", Empty, MetricsSnapshot;
+        RuntimeList => "runtime.list", Empty, super::RuntimeList;
+        BottleList => "bottle.list", Empty, crate::BottleList;
+        BottleCreate => "bottle.create", BottleCreateParams, BottleReply;
+        BottleDelete => "bottle.delete", BottleDeleteParams, BottleDeleted;
+        BottleUpdate => "bottle.update", BottleUpdateParams, BottleReply;
+        BottleClone => "bottle.clone", BottleCloneParams, BottleReply;
+        BottleExport => "bottle.export", BottleExportParams, BottleExported;
+        BottleImport => "bottle.import", BottleImportParams, BottleImported;
+        BottleRun => "bottle.run", BottleRunParams, Deferred<RunResult>;
+        BottleSnapshotCreate => "bottle.snapshot.create", SnapshotCreateParams, SnapshotReply;
+        BottleSnapshotList => "bottle.snapshot.list", SnapshotListParams, SnapshotList;
+        BottleSnapshotRestore => "bottle.snapshot.restore", SnapshotParams, BottleReply;
+        BottleSnapshotDelete => "bottle.snapshot.delete", SnapshotParams, SnapshotDeleted;
+        BottleRestore => "bottle.restore", BottleRestoreParams, BottleReply;
+        TrashList => "trash.list", Empty, super::TrashList;
+        TrashPurge => "trash.purge", TrashPurgeParams, TrashPurged;
+        BottleUsage => "bottle.usage", BottleUsageParams, UsageReport;
+        BottleClean => "bottle.clean", BottleCleanParams, BottleCleaned;
+        LogsClean => "logs.clean", Empty, LogsCleaned;
+        BottleAdopt => "bottle.adopt", BottleAdoptParams, BottleAdopted;
+        RecipeList => "recipe.list", Empty, super::RecipeList;
+        RecipeApply => "recipe.apply", RecipeApplyParams, Deferred<RecipeApplied>;
+        ShortcutCreate => "shortcut.create", ShortcutCreateParams, ShortcutCreated;
+        EventsSubscribe => "events.subscribe", EventFilter, Subscribed;
+        EventsUnsubscribe => "events.unsubscribe", UnsubscribeParams, Unsubscribed;
+        JobStatus => "job.status", JobIdParams, JobReply;
+        JobList => "job.list", JobListParams, super::JobList;
+        JobWait => "job.wait", JobWaitParams, JobWaitReply;
+        JobCancel => "job.cancel", JobIdParams, JobReply;
+    }
+}
+
+/// methods that only look at daemon state. peers granted read-only access
+/// may call these and nothing else.
+pub const READ_ONLY_METHODS: &[&str] = &[
+    methods::ServicePing::NAME,
+    methods::ServiceInfo::NAME,
+    methods::ServiceCapabilities::NAME,
+    methods::ServiceMetrics::NAME,
+    methods::RuntimeList::NAME,
+    methods::BottleList::NAME,
+    methods::BottleSnapshotList::NAME,
+    methods::TrashList::NAME,
+    methods::BottleUsage::NAME,
+    methods::RecipeList::NAME,
+    methods::EventsSubscribe::NAME,
+    methods::EventsUnsubscribe::NAME,
+    methods::JobStatus::NAME,
+    methods::JobList::NAME,
+    methods::JobWait::NAME,
+];
+
+/// params for methods that take none. absent or `null` params decode to it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Empty {}
+
+/// what a stopping daemon does with the wine processes it started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChildPolicy {
+    /// terminate them and shut down each bottle's wineserver
+    #[default]
+    Kill,
+    /// leave them running
+    Detach,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ShutdownParams {
+    /// overrides the daemon's configured policy for this shutdown
+    #[serde(default)]
+    pub children: Option<ChildPolicy>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ShuttingDown {
+    pub children: ChildPolicy,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BottleCreateParams {
+    pub name: String,
+    pub wine_version: String,
+    #[serde(default)]
+    pub wine_label: Option<String>,
+    #[serde(default)]
+    pub wine_path: Option<PathBuf>,
+    #[serde(default)]
+    pub channel: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BottleDeleteParams {
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    /// instead of `id`: the bottle's uuid, a unique prefix of it, or its name
+    #[serde(default)]
+    pub bottle: Option<String>,
+    /// wait for a busy bottle instead of failing
+    #[serde(default)]
+    pub wait: bool,
+    /// remove the bottle for good instead of moving it to the trash
+    #[serde(default)]
+    pub permanent: bool,
+}
+
+/// names a trashed bottle.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BottleRestoreParams {
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    /// instead of `id`: the bottle's uuid, a unique prefix of it, or its name
+    #[serde(default)]
+    pub bottle: Option<String>,
+    /// restore under a new name, for when the old one has been taken
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// names a trashed bottle, or none to empty the whole trash.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct TrashPurgeParams {
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    /// instead of `id`: the bottle's uuid, a unique prefix of it, or its name
+    #[serde(default)]
+    pub bottle: Option<String>,
+}
+
+/// names a bottle, or none to measure every one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct BottleUsageParams {
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    /// instead of `id`: the bottle's uuid, a unique prefix of it, or its name
+    #[serde(default)]
+    pub bottle: Option<String>,
+    /// read every directory again instead of trusting the cache
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BottleCleanParams {
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    /// instead of `id`: the bottle's uuid, a unique prefix of it, or its name
+    #[serde(default)]
+    pub bottle: Option<String>,
+    /// any of `temp`, `installer_cache`, `shader_cache` and `logs`
+    pub categories: Vec<UsageCategory>,
+    /// wait for a busy bottle instead of failing
+    #[serde(default)]
+    pub wait: bool,
+}
+
+/// takes over an existing wine prefix. the runtime is picked as for
+/// `bottle.create`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BottleAdoptParams {
+    /// absolute path of the prefix, the directory holding `system.reg`
+    pub path: PathBuf,
+    #[serde(default)]
+    pub mode: AdoptMode,
+    #[serde(flatten)]
+    pub create: BottleCreateParams,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BottleUpdateParams {
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    /// instead of `id`: the bottle's uuid, a unique prefix of it, or its name
+    #[serde(default)]
+    pub bottle: Option<String>,
+    #[serde(flatten)]
+    pub changes: BottleChanges,
+    /// wait for a busy bottle instead of failing
+    #[serde(default)]
+    pub wait: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BottleCloneParams {
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    /// instead of `id`: the bottle's uuid, a unique prefix of it, or its name
+    #[serde(default)]
+    pub bottle: Option<String>,
+    /// name of the new bottle
+    pub name: String,
+    /// wait for a busy bottle instead of failing
+    #[serde(default)]
+    pub wait: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BottleExportParams {
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    /// instead of `id`: the bottle's uuid, a unique prefix of it, or its name
+    #[serde(default)]
+    pub bottle: Option<String>,
+    /// absolute path of the archive to write, usually ending in `.tar.zst`
+    pub path: PathBuf,
+    /// wait for a busy bottle instead of failing
+    #[serde(default)]
+    pub wait: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BottleImportParams {
+    /// absolute path of an archive written by `bottle.export`
+    pub path: PathBuf,
+    /// defaults to the exported bottle's name
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SnapshotCreateParams {
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    /// instead of `id`: the bottle's uuid, a unique prefix of it, or its name
+    #[serde(default)]
+    pub bottle: Option<String>,
+    pub label: String,
+    /// wait for a busy bottle instead of failing
+    #[serde(default)]
+    pub wait: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SnapshotListParams {
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    /// instead of `id`: the bottle's uuid, a unique prefix of it, or its name
+    #[serde(default)]
+    pub bottle: Option<String>,
+}
+
+/// names one snapshot of a bottle.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SnapshotParams {
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    /// instead of `id`: the bottle's uuid, a unique prefix of it, or its name
+    #[serde(default)]
+    pub bottle: Option<String>,
+    pub snapshot: Uuid,
+    /// wait for a busy bottle instead of failing
+    #[serde(default)]
+    pub wait: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BottleRunParams {
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    /// instead of `id`: the bottle's uuid, a unique prefix of it, or its name
+    #[serde(default)]
+    pub bottle: Option<String>,
+    pub executable: PathBuf,
+    #[serde(default)]
+    pub args: Option<Vec<String>>,
+    /// set for this run on top of the bottle's own environment
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// hand the run off to a job and return its id instead of waiting
+    #[serde(default)]
+    pub background: bool,
+    /// wait for a busy bottle instead of failing
+    #[serde(default)]
+    pub wait: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RecipeApplyParams {
+    #[serde(default)]
+    pub bottle_id: Option<Uuid>,
+    /// instead of `bottle_id`: the bottle's uuid, a unique prefix of it, or its name
+    #[serde(default)]
+    pub bottle: Option<String>,
+    pub recipe_id: String,
+    #[serde(default)]
+    pub background: bool,
+    /// wait for a busy bottle instead of failing
+    #[serde(default)]
+    pub wait: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ShortcutCreateParams {
+    #[serde(default)]
+    pub bottle_id: Option<Uuid>,
+    /// instead of `bottle_id`: the bottle's uuid, a unique prefix of it, or its name
+    #[serde(default)]
+    pub bottle: Option<String>,
+    pub name: String,
+    pub executable: String,
+    #[serde(default)]
+    pub destination: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct UnsubscribeParams {
+    pub subscription: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct JobIdParams {
+    pub id: Uuid,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct JobListParams {
+    #[serde(default)]
+    pub bottle_id: Option<Uuid>,
+    /// a bottle selector, as for `bottle_id` elsewhere
+    #[serde(default)]
+    pub bottle: Option<String>,
+    #[serde(default)]
+    pub state: Option<JobState>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct JobWaitParams {
+    pub id: Uuid,
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PingReply {
+    pub status: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ServiceInfo {
+    pub version: String,
+    pub pid: u32,
+    /// unix seconds
+    pub started_at: u64,
+    pub runtime_dir: PathBuf,
+    pub bottle_root: PathBuf,
+    pub runtimes: Vec<RuntimeDescriptor>,
+    /// the effective configuration and where each value came from
+    pub config: DaemonConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Reloaded {
+    pub config: DaemonConfig,
+    /// settings that changed but only take effect after a restart
+    pub restart_required: Vec<String>,
+}
+
+/// a method as described by `service.capabilities`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct MethodSchema {
+    pub name: String,
+    pub params: Value,
+    pub result: Value,
+}
+
+impl MethodSchema {
+    pub fn of<M: Method>() -> Self {
+        Self {
+            name: M::NAME.to_string(),
+            params: schema_value::<M::Params>(),
+            result: schema_value::<M::Result>(),
+        }
+    }
+}
+
+fn schema_value<T: JsonSchema>() -> Value {
+    let schema = SchemaGenerator::default().into_root_schema_for::<T>();
+    serde_json::to_value(schema).expect("json schema serializes")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Capabilities {
+    pub protocol_version: u32,
+    pub version: String,
+    pub methods: Vec<MethodSchema>,
+}
+
+impl Capabilities {
+    pub fn supports(&self, method: &str) -> bool {
+        self.methods.iter().any(|entry| entry.name == method)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RuntimeList {
+    pub runtimes: Vec<RuntimeDescriptor>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BottleReply {
+    pub bottle: BottleRecord,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BottleDeleted {
+    pub deleted: Uuid,
+    /// whether the bottle went to the trash rather than away for good
+    #[serde(default)]
+    pub trashed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TrashList {
+    pub bottles: Vec<TrashedBottle>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TrashPurged {
+    pub purged: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct UsageReport {
+    /// largest first
+    pub bottles: Vec<BottleUsage>,
+    /// bytes the daemon's own logs take up under the data root; only
+    /// reported when every bottle is measured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BottleCleaned {
+    pub bottle_id: Uuid,
+    /// bytes freed, over all categories
+    pub reclaimed: u64,
+    /// bytes freed per category
+    pub categories: BTreeMap<UsageCategory, u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct LogsCleaned {
+    /// bytes freed
+    pub reclaimed: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BottleAdopted {
+    pub bottle: BottleRecord,
+    pub prefix: PrefixInfo,
+    /// things to look at before running anything in the bottle
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BottleExported {
+    pub path: PathBuf,
+    /// files in the archive, `bottle.json` included
+    pub files: usize,
+    /// size of the archive in bytes
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BottleImported {
+    pub bottle: BottleRecord,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SnapshotReply {
+    pub snapshot: SnapshotRecord,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SnapshotList {
+    pub snapshots: Vec<SnapshotRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SnapshotDeleted {
+    pub deleted: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RunResult {
+    pub exit_status: Option<i32>,
+    pub success: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RecipeSummary {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RecipeList {
+    pub recipes: Vec<RecipeSummary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RecipeApplied {
+    pub applied: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ShortcutCreated {
+    pub shortcut: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Subscribed {
+    pub subscription: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Unsubscribed {
+    pub unsubscribed: Uuid,
+}
+
+/// result of a method that can run in the background: either the finished
+/// result, or the job it was handed off to.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Deferred<T> {
+    Job { job: Job },
+    Done(T),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    pub fn is_terminal(self) -> bool {
+        matches!(
+            self,
+            JobState::Succeeded | JobState::Failed | JobState::Cancelled
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Job {
+    pub id: Uuid,
+    pub method: String,
+    pub bottle_id: Uuid,
+    pub state: JobState,
+    pub created_at: u64,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    pub result: Option<Value>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct JobReply {
+    pub job: Job,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct JobList {
+    pub jobs: Vec<Job>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct JobWaitReply {
+    pub job: Job,
+    pub timed_out: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DaemonEvent {
+    BottleCreated {
+        bottle_id: Uuid,
+        name: String,
+    },
+    BottleUpdated {
+        bottle_id: Uuid,
+    },
+    BottleDeleted {
+        bottle_id: Uuid,
+    },
+    ProcessStarted {
+        bottle_id: Uuid,
+        pid: Option<u32>,
+        command: PathBuf,
+    },
+    ProcessExited {
+        bottle_id: Uuid,
+        pid: Option<u32>,
+        exit_status: Option<i32>,
+        success: bool,
+    },
+    RecipeStepStarted {
+        bottle_id: Uuid,
+        recipe_id: String,
+        step: usize,
+        action: String,
+    },
+    RecipeStepFinished {
+        bottle_id: Uuid,
+        recipe_id: String,
+        step: usize,
+        action: String,
+    },
+    RecipeStepFailed {
+        bottle_id: Uuid,
+        recipe_id: String,
+        step: usize,
+        action: String,
+        error: String,
+    },
+    JobUpdated {
+        bottle_id: Uuid,
+        job_id: Uuid,
+        method: String,
+        state: JobState,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    BottleCreated,
+    BottleUpdated,
+    BottleDeleted,
+    ProcessStarted,
+    ProcessExited,
+    RecipeStepStarted,
+    RecipeStepFinished,
+    RecipeStepFailed,
+    JobUpdated,
+}
+
+impl DaemonEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            DaemonEvent::BottleCreated { .. } => EventKind::BottleCreated,
+            DaemonEvent::BottleUpdated { .. } => EventKind::BottleUpdated,
+            DaemonEvent::BottleDeleted { .. } => EventKind::BottleDeleted,
+            DaemonEvent::ProcessStarted { .. } => EventKind::ProcessStarted,
+            DaemonEvent::ProcessExited { .. } => EventKind::ProcessExited,
+            DaemonEvent::RecipeStepStarted { .. } => EventKind::RecipeStepStarted,
+            DaemonEvent::RecipeStepFinished { .. } => EventKind::RecipeStepFinished,
+            DaemonEvent::RecipeStepFailed { .. } => EventKind::RecipeStepFailed,
+            DaemonEvent::JobUpdated { .. } => EventKind::JobUpdated,
+        }
+    }
+
+    pub fn bottle_id(&self) -> Uuid {
+        match self {
+            DaemonEvent::BottleCreated { bottle_id, .. }
+            | DaemonEvent::BottleUpdated { bottle_id }
+            | DaemonEvent::BottleDeleted { bottle_id }
+            | DaemonEvent::ProcessStarted { bottle_id, .. }
+            | DaemonEvent::ProcessExited { bottle_id, .. }
+            | DaemonEvent::RecipeStepStarted { bottle_id, .. }
+            | DaemonEvent::RecipeStepFinished { bottle_id, .. }
+            | DaemonEvent::RecipeStepFailed { bottle_id, .. }
+            | DaemonEvent::JobUpdated { bottle_id, .. } => *bottle_id,
+        }
+    }
+}
+
+/// narrows a subscription. an empty list means "everything".
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct EventFilter {
+    #[serde(default)]
+    pub bottles: Vec<Uuid>,
+    #[serde(default)]
+    pub kinds: Vec<EventKind>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &DaemonEvent) -> bool {
+        (self.bottles.is_empty() || self.bottles.contains(&event.bottle_id()))
+            && (self.kinds.is_empty() || self.kinds.contains(&event.kind()))
+    }
+}
+
+/// params of an [`EVENT_NOTIFICATION`].
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct EventNotification {
+    pub subscription: Uuid,
+    pub event: DaemonEvent,
+}
+
+/// params of a [`LAGGED_NOTIFICATION`].
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct LaggedNotification {
+    pub subscription: Uuid,
+    pub missed: u64,
+}
@@ -0,0 +1,648 @@
+use std::collections::BTreeMap;
+use std::fs::{self as stdfs, File, Permissions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use uuid::Uuid;
+
+use crate::lock::{BottleLock, LockMode};
+use crate::{
+    migrate, rewrite_environment, rewrite_hives, unix_timestamp, BottleRecord, BottleStore,
+    RuntimeDescriptor, WineRuntime, BOTTLE_META, REGISTRY_HIVES, SCHEMA_VERSION,
+};
+
+const ARCHIVE_MANIFEST: &str = "manifest.json";
+const ARCHIVE_FORMAT: u32 = 1;
+
+/// the last entry of an export archive: what the bottle ran on and a hash of
+/// every file before it, so an import can tell a damaged or edited archive
+/// from the one that was exported.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    pub format: u32,
+    pub bottle_id: Uuid,
+    pub name: String,
+    pub exported_at: u64,
+    /// the bottle's directory on the exporting machine. paths naming it are
+    /// pointed at the imported bottle's directory instead.
+    pub source_dir: PathBuf,
+    /// where the prefix really was, for one adopted in place. paths naming
+    /// it are pointed at the imported bottle's prefix.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_prefix: Option<PathBuf>,
+    pub runtime: WineRuntime,
+    pub files: Vec<ArchiveFile>,
+    pub links: Vec<ArchiveLink>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveLink {
+    pub path: PathBuf,
+    pub target: PathBuf,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportedBottle {
+    pub bottle: BottleRecord,
+    /// things the bottle may need fixing by hand, like a missing runtime
+    pub warnings: Vec<String>,
+}
+
+impl BottleStore {
+    /// writes the bottle to `dest` as a zstd-compressed tar of `bottle.json`,
+    /// the prefix and a manifest. the archive appears at `dest` only once it
+    /// is complete.
+    /// needs the bottle exclusively: a program writing to the prefix would
+    /// leave files that fail their hash.
+    pub async fn export_bottle(&self, lock: &BottleLock, dest: &Path) -> Result<ArchiveManifest> {
+        let id = lock.covers(LockMode::Exclusive)?;
+        let record = self.record(id).await?;
+        let bottle_dir = self.root.join(id.to_string());
+        let linked = self.linked_prefix(id).await?;
+        let mut partial = dest.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        let out = partial.clone();
+        let written =
+            tokio::task::spawn_blocking(move || write_archive(&bottle_dir, linked, &record, &out))
+                .await?;
+        match written {
+            Ok(manifest) => {
+                fs::rename(&partial, dest).await?;
+                Ok(manifest)
+            }
+            Err(err) => {
+                let _ = fs::remove_file(&partial).await;
+                Err(err).with_context(|| format!("failed to export bottle {id}"))
+            }
+        }
+    }
+
+    /// unpacks an archive written by `export_bottle` into a new bottle. every
+    /// file is checked against the manifest, and the bottle is pointed at the
+    /// installed runtime with the same channel and version.
+    pub async fn import_bottle(
+        &self,
+        archive: &Path,
+        name: Option<&str>,
+        runtimes: &[RuntimeDescriptor],
+    ) -> Result<ImportedBottle> {
+        let id = Uuid::new_v4();
+        // the exported name can only be checked once it's unpacked
+        if let Some(name) = name {
+            self.check_name(name, id).await?;
+        }
+        let bottle_dir = self.root.join(id.to_string());
+        fs::create_dir_all(bottle_dir.join("prefix"))
+            .await
+            .context("failed to create bottle directory")?;
+        let imported = self.unpack(archive, id, &bottle_dir, name, runtimes).await;
+        if imported.is_err() {
+            let _ = fs::remove_dir_all(&bottle_dir).await;
+        }
+        imported.with_context(|| format!("failed to import {}", archive.display()))
+    }
+
+    async fn unpack(
+        &self,
+        archive: &Path,
+        id: Uuid,
+        bottle_dir: &Path,
+        name: Option<&str>,
+        runtimes: &[RuntimeDescriptor],
+    ) -> Result<ImportedBottle> {
+        let (from, to) = (archive.to_path_buf(), bottle_dir.to_path_buf());
+        let (exported, manifest) =
+            tokio::task::spawn_blocking(move || read_archive(&from, &to)).await??;
+        let source = &manifest.source_dir;
+        let prefix = self.bottle_prefix(id);
+        let mut environment = exported.environment;
+        if let Some(source_prefix) = &manifest.source_prefix {
+            rewrite_hives(&prefix, source_prefix, &prefix).await?;
+            environment = rewrite_environment(environment, source_prefix, &prefix);
+        }
+        rewrite_hives(&prefix, source, bottle_dir).await?;
+        let (wine_runtime, warning) = match_runtime(manifest.runtime.clone(), runtimes);
+        let record = BottleRecord {
+            schema_version: SCHEMA_VERSION,
+            id,
+            name: name.unwrap_or(&exported.name).trim().to_string(),
+            created_at: unix_timestamp(),
+            wine_runtime,
+            environment: rewrite_environment(environment, source, bottle_dir),
+            notes: exported.notes,
+            tags: exported.tags,
+        };
+        // written last, so an import that failed halfway is never listed
+        self.write_named_record(bottle_dir, &record).await?;
+        Ok(ImportedBottle {
+            bottle: record,
+            warnings: warning.into_iter().collect(),
+        })
+    }
+}
+
+/// the installed runtime matching the exported one's channel and version, or
+/// the exported one as it was, with a warning, when nothing here matches.
+fn match_runtime(
+    exported: WineRuntime,
+    installed: &[RuntimeDescriptor],
+) -> (WineRuntime, Option<String>) {
+    let found = installed.iter().find(|runtime| {
+        exported.channel.as_deref() == Some(runtime.channel.as_str())
+            && runtime.version == exported.version
+    });
+    match found {
+        Some(runtime) => (runtime.clone().into_wine_runtime(), None),
+        None => {
+            let warning = format!(
+                "no installed runtime matches channel {} version {}; the bottle still points at {}",
+                exported.channel.as_deref().unwrap_or("unknown"),
+                exported.version,
+                exported.wine64_path.display()
+            );
+            tracing::warn!("{warning}");
+            (exported, Some(warning))
+        }
+    }
+}
+
+fn write_archive(
+    bottle_dir: &Path,
+    linked: Option<PathBuf>,
+    record: &BottleRecord,
+    out: &Path,
+) -> Result<ArchiveManifest> {
+    let file = File::create(out).with_context(|| format!("failed to create {}", out.display()))?;
+    let encoder = zstd::Encoder::new(file, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+    let mut builder = tar::Builder::new(encoder);
+    let mut manifest = ArchiveManifest {
+        format: ARCHIVE_FORMAT,
+        bottle_id: record.id,
+        name: record.name.clone(),
+        exported_at: unix_timestamp(),
+        source_dir: bottle_dir.to_path_buf(),
+        source_prefix: linked,
+        runtime: record.wine_runtime.clone(),
+        files: Vec::new(),
+        links: Vec::new(),
+    };
+    let meta = serde_json::to_vec_pretty(record)?;
+    append_bytes(&mut builder, Path::new(BOTTLE_META), &meta)?;
+    manifest.files.push(ArchiveFile {
+        path: PathBuf::from(BOTTLE_META),
+        size: meta.len() as u64,
+        sha256: format!("{:x}", Sha256::digest(&meta)),
+    });
+    append_tree(
+        &mut builder,
+        &bottle_dir.join("prefix"),
+        Path::new("prefix"),
+        &mut manifest,
+    )?;
+    let data = serde_json::to_vec_pretty(&manifest)?;
+    append_bytes(&mut builder, Path::new(ARCHIVE_MANIFEST), &data)?;
+    builder.into_inner()?.finish()?.sync_all()?;
+    Ok(manifest)
+}
+
+fn append_bytes<W: Write>(builder: &mut tar::Builder<W>, path: &Path, data: &[u8]) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_mode(0o644);
+    header.set_mtime(unix_timestamp());
+    header.set_size(data.len() as u64);
+    builder.append_data(&mut header, path, data)?;
+    Ok(())
+}
+
+/// appends `dir` as `name`, sorted so the same prefix always packs the same.
+fn append_tree<W: Write>(
+    builder: &mut tar::Builder<W>,
+    dir: &Path,
+    name: &Path,
+    manifest: &mut ArchiveManifest,
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    // followed: the prefix of an adopted bottle may be a link to it
+    header.set_metadata(&stdfs::metadata(dir)?);
+    builder.append_data(&mut header, name, io::empty())?;
+    let mut entries = stdfs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let source = entry.path();
+        let name = name.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        let metadata = entry.metadata()?;
+        if file_type.is_dir() {
+            append_tree(builder, &source, &name, manifest)?;
+        } else if file_type.is_symlink() {
+            let target = stdfs::read_link(&source)?;
+            let mut header = tar::Header::new_gnu();
+            header.set_metadata(&metadata);
+            builder.append_link(&mut header, &name, &target)?;
+            manifest.links.push(ArchiveLink { path: name, target });
+        } else if file_type.is_file() {
+            let mut header = tar::Header::new_gnu();
+            header.set_metadata(&metadata);
+            let mut reader = Hashing::new(File::open(&source)?.take(metadata.len()));
+            builder.append_data(&mut header, &name, &mut reader)?;
+            if reader.read != metadata.len() {
+                bail!("{} changed while it was exported", source.display());
+            }
+            manifest.files.push(ArchiveFile {
+                path: name,
+                size: metadata.len(),
+                sha256: reader.finish(),
+            });
+        }
+        // sockets and fifos belong to a running wineserver, not the prefix
+    }
+    Ok(())
+}
+
+/// extracts into `bottle_dir` without trusting the archive: entries may only
+/// name paths inside it, never through a symlink, and only plain files,
+/// directories and symlinks are made. everything must match the manifest.
+fn read_archive(archive: &Path, bottle_dir: &Path) -> Result<(BottleRecord, ArchiveManifest)> {
+    let file =
+        File::open(archive).with_context(|| format!("failed to open {}", archive.display()))?;
+    let mut tar = tar::Archive::new(zstd::Decoder::new(file)?);
+    let mut record = None;
+    let mut manifest = None;
+    let mut files = BTreeMap::new();
+    let mut links = BTreeMap::new();
+    let mut dirs = Vec::new();
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if path.as_os_str().is_empty()
+            || !path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            bail!("archive entry {} escapes the bottle", path.display());
+        }
+        let kind = entry.header().entry_type();
+        let mode = entry.header().mode()? & 0o777;
+        let mtime = entry.header().mtime()?;
+        if path == Path::new(ARCHIVE_MANIFEST) || path == Path::new(BOTTLE_META) {
+            if kind != tar::EntryType::Regular {
+                bail!("archive entry {} is not a file", path.display());
+            }
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            if path == Path::new(ARCHIVE_MANIFEST) {
+                manifest = Some(data);
+            } else {
+                let sha256 = format!("{:x}", Sha256::digest(&data));
+                files.insert(path, (data.len() as u64, sha256));
+                record = Some(data);
+            }
+            continue;
+        }
+        if !path.starts_with("prefix") {
+            bail!("unexpected archive entry {}", path.display());
+        }
+        // a symlink unpacked earlier must not carry a later entry outside
+        for ancestor in path.ancestors().skip(1) {
+            let metadata = stdfs::symlink_metadata(bottle_dir.join(ancestor));
+            if metadata.is_ok_and(|metadata| metadata.is_symlink()) {
+                bail!("archive entry {} goes through a symlink", path.display());
+            }
+        }
+        // hives are rewritten after unpacking, so one must be a plain file
+        let hive = REGISTRY_HIVES
+            .iter()
+            .any(|hive| path == Path::new("prefix").join(hive));
+        if hive && kind != tar::EntryType::Regular {
+            bail!("archive entry {} is not a file", path.display());
+        }
+        let target = bottle_dir.join(&path);
+        match kind {
+            tar::EntryType::Directory => {
+                match stdfs::create_dir(&target) {
+                    Ok(()) => {}
+                    Err(err)
+                        if err.kind() == io::ErrorKind::AlreadyExists
+                            && stdfs::symlink_metadata(&target)?.is_dir() => {}
+                    Err(err) => {
+                        return Err(err)
+                            .with_context(|| format!("failed to create {}", path.display()))
+                    }
+                }
+                dirs.push((target, mode));
+            }
+            tar::EntryType::Regular => {
+                // create_new never follows a symlink already at the target
+                let mut out = File::create_new(&target)
+                    .with_context(|| format!("failed to create {}", path.display()))?;
+                let mut reader = Hashing::new(&mut entry);
+                io::copy(&mut reader, &mut out)?;
+                out.set_modified(UNIX_EPOCH + Duration::from_secs(mtime))?;
+                out.set_permissions(Permissions::from_mode(mode))?;
+                files.insert(path, (reader.read, reader.finish()));
+            }
+            tar::EntryType::Symlink => {
+                let link = entry
+                    .link_name()?
+                    .ok_or_else(|| anyhow!("symlink {} has no target", path.display()))?
+                    .into_owned();
+                std::os::unix::fs::symlink(&link, &target)
+                    .with_context(|| format!("failed to create {}", path.display()))?;
+                links.insert(path, link);
+            }
+            other => bail!(
+                "archive entry {} is a {other:?}, which a bottle never holds",
+                path.display()
+            ),
+        }
+    }
+    let manifest: ArchiveManifest =
+        serde_json::from_slice(&manifest.ok_or_else(|| anyhow!("archive has no manifest"))?)?;
+    if manifest.format != ARCHIVE_FORMAT {
+        bail!("archive format {} is not supported", manifest.format);
+    }
+    verify(&manifest, files, links)?;
+    let (record, _) = migrate::upgrade(&record.ok_or_else(|| anyhow!("archive has no bottle"))?)?;
+    let prefix = bottle_dir.join("prefix");
+    let mut retargets = Vec::new();
+    if let Some(source_prefix) = &manifest.source_prefix {
+        retargets.push((source_prefix.as_path(), prefix.as_path()));
+    }
+    retargets.push((manifest.source_dir.as_path(), bottle_dir));
+    for link in &manifest.links {
+        let retargeted = retargets
+            .iter()
+            .find_map(|(from, to)| Some(to.join(link.target.strip_prefix(from).ok()?)));
+        if let Some(target) = retargeted {
+            let path = bottle_dir.join(&link.path);
+            stdfs::remove_file(&path)?;
+            std::os::unix::fs::symlink(target, &path)?;
+        }
+    }
+    // deepest first, so a read-only directory is only locked once it's filled
+    for (dir, mode) in dirs.iter().rev() {
+        stdfs::set_permissions(dir, Permissions::from_mode(*mode))?;
+    }
+    Ok((record, manifest))
+}
+
+fn verify(
+    manifest: &ArchiveManifest,
+    mut files: BTreeMap<PathBuf, (u64, String)>,
+    mut links: BTreeMap<PathBuf, PathBuf>,
+) -> Result<()> {
+    for expected in &manifest.files {
+        match files.remove(&expected.path) {
+            Some((size, sha256)) if size == expected.size && sha256 == expected.sha256 => {}
+            Some(_) => bail!("{} does not match its hash", expected.path.display()),
+            None => bail!("{} is missing from the archive", expected.path.display()),
+        }
+    }
+    for expected in &manifest.links {
+        match links.remove(&expected.path) {
+            Some(target) if target == expected.target => {}
+            Some(_) => bail!(
+                "{} does not match its manifest entry",
+                expected.path.display()
+            ),
+            None => bail!("{} is missing from the archive", expected.path.display()),
+        }
+    }
+    if let Some(extra) = files.keys().chain(links.keys()).next() {
+        bail!("{} is not in the manifest", extra.display());
+    }
+    Ok(())
+}
+
+/// hashes everything read through it.
+struct Hashing<R> {
+    inner: R,
+    hasher: Sha256,
+    read: u64,
+}
+
+impl<R: Read> Hashing<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            read: 0,
+        }
+    }
+
+    fn finish(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+impl<R: Read> Read for Hashing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        self.read += read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runtime() -> WineRuntime {
+        WineRuntime {
+            label: "wine 9.0".to_string(),
+            wine64_path: PathBuf::from("/elsewhere/wine64"),
+            version: "9.0".to_string(),
+            channel: Some("rossetta".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn archives_move_bottles_between_stores() {
+        let root = std::env::temp_dir().join(format!("silicon-alloy-archive-{}", Uuid::new_v4()));
+        let exporter = BottleStore::with_root(root.join("a")).unwrap();
+        let importer = BottleStore::with_root(root.join("b")).unwrap();
+        let bottle = exporter.create("steam", runtime()).await.unwrap();
+        let prefix = exporter.bottle_prefix(bottle.id);
+        let bottle_dir = exporter.root().join(bottle.id.to_string());
+        stdfs::create_dir_all(prefix.join("drive_c/windows")).unwrap();
+        stdfs::create_dir_all(prefix.join("dosdevices")).unwrap();
+        stdfs::write(prefix.join("drive_c/windows/win.ini"), "[fonts]").unwrap();
+        std::os::unix::fs::symlink(prefix.join("drive_c"), prefix.join("dosdevices/c:")).unwrap();
+        std::os::unix::fs::symlink("/", prefix.join("dosdevices/z:")).unwrap();
+        let escaped = bottle_dir.to_string_lossy().replace('/', "\\\\");
+        stdfs::write(prefix.join("user.reg"), format!("\"Path\"=\"Z:{escaped}\"")).unwrap();
+
+        let archive = root.join("steam.tar.zst");
+        let lock = exporter
+            .lock(bottle.id, LockMode::Exclusive, "test")
+            .unwrap();
+        let manifest = exporter.export_bottle(&lock, &archive).await.unwrap();
+        assert_eq!(manifest.files.len(), 3);
+        assert_eq!(manifest.links.len(), 2);
+
+        let installed = RuntimeDescriptor {
+            channel: "rossetta".to_string(),
+            label: "wine x86_64 9.0".to_string(),
+            version: "9.0".to_string(),
+            wine64_path: PathBuf::from("/here/wine64"),
+            notes: None,
+        };
+        let imported = importer
+            .import_bottle(&archive, None, &[installed])
+            .await
+            .unwrap();
+        assert!(imported.warnings.is_empty());
+        let copy = imported.bottle;
+        assert_ne!(copy.id, bottle.id);
+        assert_eq!(copy.name, "steam");
+        assert_eq!(copy.wine_runtime.wine64_path, Path::new("/here/wine64"));
+        let copy_dir = importer.root().join(copy.id.to_string());
+        let copy_prefix = importer.bottle_prefix(copy.id);
+        assert_eq!(
+            stdfs::read_to_string(copy_prefix.join("drive_c/windows/win.ini")).unwrap(),
+            "[fonts]"
+        );
+        assert_eq!(
+            stdfs::read_link(copy_prefix.join("dosdevices/c:")).unwrap(),
+            copy_prefix.join("drive_c")
+        );
+        assert_eq!(
+            stdfs::read_link(copy_prefix.join("dosdevices/z:")).unwrap(),
+            Path::new("/")
+        );
+        let hive = stdfs::read_to_string(copy_prefix.join("user.reg")).unwrap();
+        assert!(hive.contains(&copy_dir.to_string_lossy().replace('/', "\\\\")));
+
+        let again = importer
+            .import_bottle(&archive, Some("steam 2"), &[])
+            .await
+            .unwrap();
+        assert_eq!(again.bottle.name, "steam 2");
+        assert_eq!(again.bottle.wine_runtime.wine64_path, runtime().wine64_path);
+        assert_eq!(again.warnings.len(), 1);
+        // names stay unique
+        let err = importer
+            .import_bottle(&archive, None, &[])
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("already called steam"), "{err:#}");
+        assert_eq!(importer.list().await.unwrap().len(), 2);
+        stdfs::remove_dir_all(&root).unwrap();
+    }
+
+    /// packs `entries` as given, with no manifest checks or path cleaning.
+    fn hostile_archive(path: &Path, entries: &[(&str, tar::EntryType, &str)]) {
+        let encoder = zstd::Encoder::new(File::create(path).unwrap(), 0).unwrap();
+        let mut builder = tar::Builder::new(encoder);
+        for (name, kind, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(*kind);
+            header.set_mode(0o644);
+            if *kind == tar::EntryType::Symlink {
+                header.set_link_name(data).unwrap();
+                header.set_size(0);
+                header.set_cksum();
+                builder.append(&header, io::empty()).unwrap();
+            } else {
+                header.set_size(data.len() as u64);
+                header.set_cksum();
+                builder.append(&header, data.as_bytes()).unwrap();
+            }
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[tokio::test]
+    async fn imports_refuse_entries_leaving_the_bottle() {
+        let root = std::env::temp_dir().join(format!("silicon-alloy-archive-{}", Uuid::new_v4()));
+        let store = BottleStore::with_root(root.join("bottles")).unwrap();
+        let outside = root.join("outside");
+        stdfs::create_dir_all(&outside).unwrap();
+        let file = tar::EntryType::Regular;
+        let link = tar::EntryType::Symlink;
+        let outside_str = outside.to_str().unwrap();
+        let cases: [&[(&str, tar::EntryType, &str)]; 3] = [
+            &[("prefix/../../escaped", file, "x")],
+            &[
+                ("prefix/out", link, outside_str),
+                ("prefix/out/escaped", file, "x"),
+            ],
+            &[("prefix/drive_c", file, "not in any manifest")],
+        ];
+        for entries in cases {
+            let archive = root.join("hostile.tar.zst");
+            hostile_archive(&archive, entries);
+            assert!(store.import_bottle(&archive, None, &[]).await.is_err());
+        }
+        assert!(!root.join("escaped").exists());
+        assert!(!outside.join("escaped").exists());
+
+        // a hive that is a symlink, listed in a manifest naming what's in
+        // the file it points at, would have the import rewrite that file
+        let victim = outside.join("authorized_keys");
+        stdfs::write(&victim, "ssh-ed25519 SECRET").unwrap();
+        let record = serde_json::to_string(&BottleRecord {
+            schema_version: SCHEMA_VERSION,
+            id: Uuid::new_v4(),
+            name: "keys".to_string(),
+            created_at: 0,
+            wine_runtime: runtime(),
+            environment: Vec::new(),
+            notes: None,
+            tags: Vec::new(),
+        })
+        .unwrap();
+        let manifest = serde_json::to_string(&ArchiveManifest {
+            format: ARCHIVE_FORMAT,
+            bottle_id: Uuid::new_v4(),
+            name: "keys".to_string(),
+            exported_at: 0,
+            source_dir: PathBuf::from("SECRET"),
+            source_prefix: None,
+            runtime: runtime(),
+            files: vec![ArchiveFile {
+                path: PathBuf::from(BOTTLE_META),
+                size: record.len() as u64,
+                sha256: format!("{:x}", Sha256::digest(record.as_bytes())),
+            }],
+            links: vec![ArchiveLink {
+                path: PathBuf::from("prefix/user.reg"),
+                target: victim.clone(),
+            }],
+        })
+        .unwrap();
+        let archive = root.join("hostile.tar.zst");
+        hostile_archive(
+            &archive,
+            &[
+                (BOTTLE_META, file, &record),
+                ("prefix/user.reg", link, victim.to_str().unwrap()),
+                (ARCHIVE_MANIFEST, file, &manifest),
+            ],
+        );
+        assert!(store.import_bottle(&archive, None, &[]).await.is_err());
+        assert_eq!(
+            stdfs::read_to_string(&victim).unwrap(),
+            "ssh-ed25519 SECRET"
+        );
+        assert!(store.list().await.unwrap().is_empty());
+        stdfs::remove_dir_all(&root).unwrap();
+    }
+}
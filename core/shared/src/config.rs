@@ -0,0 +1,492 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::api::{ChildPolicy, READ_ONLY_METHODS};
+use crate::trash::Retention;
+use crate::{project_dirs, RuntimeDescriptor};
+
+/// points the daemon and cli at a config file other than the default one.
+pub const CONFIG_ENV: &str = "SILICON_ALLOY_CONFIG";
+pub const BOTTLE_ROOT_ENV: &str = "SILICON_ALLOY_BOTTLE_ROOT";
+/// a list of directories, separated like `PATH`.
+pub const RUNTIME_ROOTS_ENV: &str = "SILICON_ALLOY_RUNTIME_ROOTS";
+pub const RECIPES_ENV: &str = "SILICON_ALLOY_RECIPES";
+pub const LOG_ENV: &str = "SILICON_ALLOY_LOG";
+pub const SOCKET_ENV: &str = "SILICON_ALLOY_SOCKET";
+/// a single arm64 `wine64`, registered as an extra `native-arm64` runtime.
+pub const ARM64_WINE64_ENV: &str = "SILICON_ALLOY_ARM64_WINE64";
+pub const DEFAULT_CHANNEL_ENV: &str = "SILICON_ALLOY_DEFAULT_CHANNEL";
+pub const CHILD_POLICY_ENV: &str = "SILICON_ALLOY_CHILD_POLICY";
+/// seconds; `0` turns the idle timeout off.
+pub const IDLE_TIMEOUT_ENV: &str = "SILICON_ALLOY_IDLE_TIMEOUT";
+/// turns the localhost http/websocket gateway on, at this port.
+pub const GATEWAY_PORT_ENV: &str = "SILICON_ALLOY_GATEWAY_PORT";
+/// largest request line a client may send, in bytes.
+pub const MAX_REQUEST_BYTES_ENV: &str = "SILICON_ALLOY_MAX_REQUEST_BYTES";
+/// seconds a client may stay silent before it is disconnected; `0` for never.
+pub const CLIENT_IDLE_TIMEOUT_ENV: &str = "SILICON_ALLOY_CLIENT_IDLE_TIMEOUT";
+/// seconds a client gets to finish a request it started sending; `0` for never.
+pub const READ_TIMEOUT_ENV: &str = "SILICON_ALLOY_READ_TIMEOUT";
+pub const MAX_CONNECTIONS_ENV: &str = "SILICON_ALLOY_MAX_CONNECTIONS";
+/// extra uids allowed to use every method.
+pub const ALLOWED_UIDS_ENV: &str = "SILICON_ALLOY_ALLOWED_UIDS";
+/// extra uids limited to the read-only methods.
+pub const READ_ONLY_UIDS_ENV: &str = "SILICON_ALLOY_READ_ONLY_UIDS";
+/// replaces the daemon's built-in list of read-only methods.
+pub const READ_ONLY_METHODS_ENV: &str = "SILICON_ALLOY_READ_ONLY_METHODS";
+/// days a deleted bottle stays in the trash; `0` keeps it until purged.
+pub const TRASH_MAX_AGE_DAYS_ENV: &str = "SILICON_ALLOY_TRASH_MAX_AGE_DAYS";
+/// bytes the trash may take up; `0` for no limit.
+pub const TRASH_MAX_BYTES_ENV: &str = "SILICON_ALLOY_TRASH_MAX_BYTES";
+
+/// where a setting got its value from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    Default,
+    File,
+    /// the environment variable that overrode it
+    Env(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Setting<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+impl<T> Setting<T> {
+    pub fn default(value: T) -> Self {
+        Self {
+            value,
+            source: Source::Default,
+        }
+    }
+
+    fn file(self, value: Option<T>) -> Self {
+        match value {
+            Some(value) => Self {
+                value,
+                source: Source::File,
+            },
+            None => self,
+        }
+    }
+
+    fn env(
+        self,
+        env: &dyn Fn(&str) -> Option<String>,
+        var: &str,
+        parse: impl FnOnce(&str) -> Result<T>,
+    ) -> Result<Self> {
+        match env(var) {
+            Some(raw) => Ok(Self {
+                value: parse(&raw).with_context(|| format!("invalid {var}"))?,
+                source: Source::Env(var.to_string()),
+            }),
+            None => Ok(self),
+        }
+    }
+}
+
+/// the daemon's effective configuration: built-in defaults, then the config
+/// file, then environment variables, each replacing what came before.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DaemonConfig {
+    /// the file that was consulted, whether or not it exists
+    pub file: PathBuf,
+    pub file_found: bool,
+    pub bottle_root: Setting<PathBuf>,
+    /// directories scanned for `wine-<arch>-<version>` runtimes
+    pub runtime_roots: Setting<Vec<PathBuf>>,
+    /// searched in order; the first recipe with a given id wins
+    pub recipe_paths: Setting<Vec<PathBuf>>,
+    pub log_filter: Setting<String>,
+    pub socket_path: Setting<PathBuf>,
+    /// runtimes living outside the runtime roots
+    pub extra_runtimes: Setting<Vec<RuntimeDescriptor>>,
+    /// channel used by `bottle.create` when the caller names none
+    pub default_channel: Setting<String>,
+    pub child_policy: Setting<ChildPolicy>,
+    /// seconds without clients or work before the daemon exits, 0 for never
+    pub idle_timeout: Setting<u64>,
+    /// port of the http/websocket gateway on 127.0.0.1, off when unset
+    pub gateway_port: Setting<Option<u16>>,
+    /// longest request line, or http body, a client may send
+    pub max_request_bytes: Setting<usize>,
+    /// seconds a client without subscriptions may stay silent, 0 for never
+    pub client_idle_timeout: Setting<u64>,
+    /// seconds to finish sending a request once it has started, 0 for never
+    pub read_timeout: Setting<u64>,
+    /// clients served at once; more wait until one leaves
+    pub max_connections: Setting<usize>,
+    pub allowed_uids: Setting<BTreeSet<u32>>,
+    pub read_only_uids: Setting<BTreeSet<u32>>,
+    pub read_only_methods: Setting<BTreeSet<String>>,
+    /// days a deleted bottle is kept in the trash, 0 for until purged
+    pub trash_max_age_days: Setting<u64>,
+    /// bytes the trash may take up before the longest deleted bottles go,
+    /// 0 for no limit
+    pub trash_max_bytes: Setting<u64>,
+}
+
+/// the file as written by the user. every key is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    bottle_root: Option<PathBuf>,
+    runtime_roots: Option<Vec<PathBuf>>,
+    recipe_paths: Option<Vec<PathBuf>>,
+    log: Option<String>,
+    socket: Option<PathBuf>,
+    extra_runtimes: Option<Vec<RuntimeDescriptor>>,
+    default_channel: Option<String>,
+    child_policy: Option<ChildPolicy>,
+    idle_timeout: Option<u64>,
+    gateway_port: Option<u16>,
+    #[serde(default)]
+    limits: LimitsSection,
+    #[serde(default)]
+    access: AccessSection,
+    #[serde(default)]
+    trash: TrashSection,
+    /// read by alloy-daemon and alloyctl, which have their own parser
+    #[allow(dead_code)]
+    alloy: Option<toml::Table>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LimitsSection {
+    max_request_bytes: Option<usize>,
+    client_idle_timeout: Option<u64>,
+    read_timeout: Option<u64>,
+    max_connections: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AccessSection {
+    allowed_uids: Option<BTreeSet<u32>>,
+    read_only_uids: Option<BTreeSet<u32>>,
+    read_only_methods: Option<BTreeSet<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TrashSection {
+    max_age_days: Option<u64>,
+    max_bytes: Option<u64>,
+}
+
+/// `daemon.toml` in the project config dir, unless `SILICON_ALLOY_CONFIG`
+/// names another file.
+pub fn config_path() -> Result<PathBuf> {
+    if let Ok(path) = std::env::var(CONFIG_ENV) {
+        return Ok(PathBuf::from(path));
+    }
+    Ok(project_dirs()?.config_dir().join("daemon.toml"))
+}
+
+impl DaemonConfig {
+    /// reads the config file and the process environment.
+    pub fn load() -> Result<Self> {
+        Self::load_from(&config_path()?, &|var| std::env::var(var).ok())
+    }
+
+    /// reads `path`, which may be missing, and takes overrides from `env`.
+    pub fn load_from(path: &Path, env: &dyn Fn(&str) -> Option<String>) -> Result<Self> {
+        let (file, file_found) = match std::fs::read_to_string(path) {
+            Ok(data) => {
+                let file: ConfigFile = toml::from_str(&data)
+                    .with_context(|| format!("invalid config file {}", path.display()))?;
+                (file, true)
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                (ConfigFile::default(), false)
+            }
+            Err(err) => {
+                return Err(err).with_context(|| format!("unable to read {}", path.display()))
+            }
+        };
+        let defaults = Defaults::new()?;
+        Self {
+            file: path.to_path_buf(),
+            file_found,
+            bottle_root: Setting::default(defaults.bottle_root)
+                .file(file.bottle_root)
+                .env(env, BOTTLE_ROOT_ENV, parse_path)?,
+            runtime_roots: Setting::default(vec![defaults.runtime_root])
+                .file(file.runtime_roots)
+                .env(env, RUNTIME_ROOTS_ENV, |raw| {
+                    Ok(std::env::split_paths(raw).collect())
+                })?,
+            recipe_paths: Setting::default(vec![defaults.recipe_path])
+                .file(file.recipe_paths)
+                .env(env, RECIPES_ENV, |raw| Ok(vec![parse_path(raw)?]))?,
+            log_filter: Setting::default("info".to_string()).file(file.log).env(
+                env,
+                LOG_ENV,
+                |raw| Ok(raw.to_string()),
+            )?,
+            socket_path: Setting::default(defaults.socket_path)
+                .file(file.socket)
+                .env(env, SOCKET_ENV, parse_path)?,
+            extra_runtimes: Setting::default(Vec::new()).file(file.extra_runtimes).env(
+                env,
+                ARM64_WINE64_ENV,
+                |raw| Ok(vec![arm64_runtime(parse_path(raw)?)]),
+            )?,
+            default_channel: Setting::default("rossetta".to_string())
+                .file(file.default_channel)
+                .env(env, DEFAULT_CHANNEL_ENV, |raw| Ok(raw.trim().to_string()))?,
+            child_policy: Setting::default(ChildPolicy::default())
+                .file(file.child_policy)
+                .env(env, CHILD_POLICY_ENV, parse_child_policy)?,
+            idle_timeout: Setting::default(0).file(file.idle_timeout).env(
+                env,
+                IDLE_TIMEOUT_ENV,
+                parse_seconds,
+            )?,
+            gateway_port: Setting::default(None)
+                .file(file.gateway_port.map(Some))
+                .env(env, GATEWAY_PORT_ENV, |raw| {
+                    raw.trim()
+                        .parse()
+                        .map(Some)
+                        .map_err(|_| anyhow!("expected a port number, got {raw:?}"))
+                })?,
+            max_request_bytes: Setting::default(DEFAULT_MAX_REQUEST_BYTES)
+                .file(file.limits.max_request_bytes)
+                .env(env, MAX_REQUEST_BYTES_ENV, parse_count)?,
+            client_idle_timeout: Setting::default(DEFAULT_CLIENT_IDLE_TIMEOUT)
+                .file(file.limits.client_idle_timeout)
+                .env(env, CLIENT_IDLE_TIMEOUT_ENV, parse_seconds)?,
+            read_timeout: Setting::default(DEFAULT_READ_TIMEOUT)
+                .file(file.limits.read_timeout)
+                .env(env, READ_TIMEOUT_ENV, parse_seconds)?,
+            max_connections: Setting::default(DEFAULT_MAX_CONNECTIONS)
+                .file(file.limits.max_connections)
+                .env(env, MAX_CONNECTIONS_ENV, parse_count)?,
+            allowed_uids: Setting::default(BTreeSet::new())
+                .file(file.access.allowed_uids)
+                .env(env, ALLOWED_UIDS_ENV, parse_uids)?,
+            read_only_uids: Setting::default(BTreeSet::new())
+                .file(file.access.read_only_uids)
+                .env(env, READ_ONLY_UIDS_ENV, parse_uids)?,
+            read_only_methods: Setting::default(
+                READ_ONLY_METHODS.iter().map(|m| m.to_string()).collect(),
+            )
+            .file(file.access.read_only_methods)
+            .env(env, READ_ONLY_METHODS_ENV, |raw| Ok(parse_list(raw)))?,
+            trash_max_age_days: Setting::default(DEFAULT_TRASH_MAX_AGE_DAYS)
+                .file(file.trash.max_age_days)
+                .env(env, TRASH_MAX_AGE_DAYS_ENV, parse_count)?,
+            trash_max_bytes: Setting::default(0)
+                .file(file.trash.max_bytes)
+                .env(env, TRASH_MAX_BYTES_ENV, parse_count)?,
+        }
+        .validate()
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        seconds(self.idle_timeout.value)
+    }
+
+    pub fn client_idle_timeout(&self) -> Option<Duration> {
+        seconds(self.client_idle_timeout.value)
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        seconds(self.read_timeout.value)
+    }
+
+    /// what the daemon keeps of the trash.
+    pub fn trash_retention(&self) -> Retention {
+        Retention {
+            max_age: seconds(self.trash_max_age_days.value.saturating_mul(24 * 60 * 60)),
+            max_bytes: Some(self.trash_max_bytes.value).filter(|&max_bytes| max_bytes > 0),
+        }
+    }
+
+    fn validate(self) -> Result<Self> {
+        if self.max_request_bytes.value == 0 {
+            return Err(anyhow!("max_request_bytes must be at least 1"));
+        }
+        if self.max_connections.value == 0 {
+            return Err(anyhow!("max_connections must be at least 1"));
+        }
+        Ok(self)
+    }
+}
+
+/// zero means "never" for every timeout in the file.
+fn seconds(value: u64) -> Option<Duration> {
+    (value > 0).then(|| Duration::from_secs(value))
+}
+
+const DEFAULT_MAX_REQUEST_BYTES: usize = 1 << 20;
+const DEFAULT_CLIENT_IDLE_TIMEOUT: u64 = 600;
+const DEFAULT_READ_TIMEOUT: u64 = 30;
+const DEFAULT_MAX_CONNECTIONS: usize = 64;
+const DEFAULT_TRASH_MAX_AGE_DAYS: u64 = 30;
+
+/// the built-in locations. nothing is created here; the daemon creates what
+/// it uses when it starts.
+struct Defaults {
+    bottle_root: PathBuf,
+    runtime_root: PathBuf,
+    recipe_path: PathBuf,
+    socket_path: PathBuf,
+}
+
+impl Defaults {
+    fn new() -> Result<Self> {
+        let dirs = project_dirs()?;
+        let data = dirs.data_dir();
+        Ok(Self {
+            bottle_root: data.join("bottles"),
+            runtime_root: data.join("runtime"),
+            recipe_path: data.join("recipes"),
+            // the daemon won't change the mode of a directory it didn't
+            // create, so the socket gets one of its own
+            socket_path: dirs
+                .runtime_dir()
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| data.join("run"))
+                .join("daemon.sock"),
+        })
+    }
+}
+
+fn arm64_runtime(wine64_path: PathBuf) -> RuntimeDescriptor {
+    RuntimeDescriptor {
+        channel: "native-arm64".to_string(),
+        label: "wine arm64 (external)".to_string(),
+        version: "experimental".to_string(),
+        wine64_path,
+        notes: Some(format!("provided via {ARM64_WINE64_ENV}")),
+    }
+}
+
+fn parse_path(raw: &str) -> Result<PathBuf> {
+    if raw.is_empty() {
+        return Err(anyhow!("expected a path"));
+    }
+    Ok(PathBuf::from(raw))
+}
+
+fn parse_seconds(raw: &str) -> Result<u64> {
+    raw.trim()
+        .parse()
+        .map_err(|_| anyhow!("expected a number of seconds, got {raw:?}"))
+}
+
+fn parse_count<T: FromStr>(raw: &str) -> Result<T> {
+    raw.trim()
+        .parse()
+        .map_err(|_| anyhow!("expected a number, got {raw:?}"))
+}
+
+fn parse_child_policy(raw: &str) -> Result<ChildPolicy> {
+    serde_json::from_value(serde_json::Value::String(raw.trim().to_string()))
+        .map_err(|_| anyhow!("expected `kill` or `detach`, got {raw:?}"))
+}
+
+fn parse_list(raw: &str) -> BTreeSet<String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn parse_uids(raw: &str) -> Result<BTreeSet<u32>> {
+    parse_list(raw)
+        .iter()
+        .map(|uid| uid.parse().with_context(|| format!("invalid uid {uid:?}")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_overrides_file_overrides_defaults() {
+        let dir =
+            std::env::temp_dir().join(format!("silicon-alloy-config-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("daemon.toml");
+        let no_env = |_: &str| None;
+
+        let config = DaemonConfig::load_from(&path, &no_env).unwrap();
+        assert!(!config.file_found);
+        assert_eq!(config.log_filter, Setting::default("info".to_string()));
+        assert_eq!(config.idle_timeout(), None);
+
+        std::fs::write(
+            &path,
+            r#"
+            log = "debug"
+            recipe_paths = ["/srv/recipes", "/opt/recipes"]
+            idle_timeout = 30
+
+            [[extra_runtimes]]
+            channel = "custom-arm64"
+            label = "wine arm64"
+            version = "9.0"
+            wine64_path = "/opt/wine/bin/wine64"
+
+            [limits]
+            max_connections = 8
+
+            [access]
+            read_only_uids = [501]
+
+            [trash]
+            max_bytes = 1000000
+            "#,
+        )
+        .unwrap();
+        let env = |var: &str| match var {
+            LOG_ENV => Some("trace".to_string()),
+            CHILD_POLICY_ENV => Some("detach".to_string()),
+            _ => None,
+        };
+        let config = DaemonConfig::load_from(&path, &env).unwrap();
+        assert!(config.file_found);
+        assert_eq!(config.log_filter.value, "trace");
+        assert_eq!(config.log_filter.source, Source::Env(LOG_ENV.to_string()));
+        assert_eq!(config.recipe_paths.source, Source::File);
+        assert_eq!(config.recipe_paths.value.len(), 2);
+        assert_eq!(config.extra_runtimes.value[0].channel, "custom-arm64");
+        assert_eq!(config.idle_timeout(), Some(Duration::from_secs(30)));
+        assert_eq!(config.child_policy.value, ChildPolicy::Detach);
+        assert!(config.read_only_uids.value.contains(&501));
+        assert_eq!(config.socket_path.source, Source::Default);
+        assert_eq!(config.max_connections.value, 8);
+        assert_eq!(config.read_timeout(), Some(Duration::from_secs(30)));
+        assert_eq!(
+            config.trash_retention(),
+            Retention {
+                max_age: Some(Duration::from_secs(30 * 24 * 60 * 60)),
+                max_bytes: Some(1_000_000),
+            }
+        );
+
+        // typos are reported instead of silently ignored
+        std::fs::write(&path, "sokcet = \"/tmp/x.sock\"\n").unwrap();
+        let err = DaemonConfig::load_from(&path, &no_env).unwrap_err();
+        assert!(format!("{err:#}").contains("sokcet"), "{err:#}");
+        std::fs::write(&path, "[limits]\nmax_request_bytes = 0\n").unwrap();
+        assert!(DaemonConfig::load_from(&path, &no_env).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
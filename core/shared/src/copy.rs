@@ -0,0 +1,184 @@
+use std::fs::{self as stdfs, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// copies a directory tree file by file. each file is cloned when the
+/// filesystem can share blocks between files (FICLONE on linux, clonefile on
+/// macos). where it can't, a file that is unchanged since the `link_dest`
+/// tree was copied is hard linked to that copy, and anything else is copied
+/// in full. symlinks are recreated, not followed: `dosdevices` points at `/`.
+pub(crate) struct Copier {
+    link_dest: Option<PathBuf>,
+    retargets: Vec<(PathBuf, PathBuf)>,
+    clones: bool,
+}
+
+impl Copier {
+    pub(crate) fn new(link_dest: Option<PathBuf>) -> Self {
+        Self {
+            link_dest,
+            retargets: Vec::new(),
+            clones: true,
+        }
+    }
+
+    /// symlinks pointing into `from` are recreated pointing into `to`, so a
+    /// copy doesn't keep links into the original. given more than once, the
+    /// first `from` a link points into wins.
+    pub(crate) fn retarget(mut self, from: PathBuf, to: PathBuf) -> Self {
+        self.retargets.push((from, to));
+        self
+    }
+
+    pub(crate) fn copy_tree(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        self.copy_dir(from, to, Path::new(""))
+    }
+
+    fn copy_dir(&mut self, from: &Path, to: &Path, relative: &Path) -> io::Result<()> {
+        stdfs::create_dir(to)?;
+        for entry in stdfs::read_dir(from)? {
+            let entry = entry?;
+            let source = entry.path();
+            let target = to.join(entry.file_name());
+            let relative = relative.join(entry.file_name());
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                self.copy_dir(&source, &target, &relative)?;
+            } else if file_type.is_symlink() {
+                let link = self.link_target(stdfs::read_link(&source)?);
+                std::os::unix::fs::symlink(link, &target)?;
+            } else if file_type.is_file() {
+                self.copy_file(&source, &target, &relative)?;
+            }
+            // sockets and fifos belong to a running wineserver, not the prefix
+        }
+        let permissions = stdfs::metadata(from)?.permissions();
+        stdfs::set_permissions(to, permissions)
+    }
+
+    fn link_target(&self, link: PathBuf) -> PathBuf {
+        self.retargets
+            .iter()
+            .find_map(|(from, to)| Some(to.join(link.strip_prefix(from).ok()?)))
+            .unwrap_or(link)
+    }
+
+    fn copy_file(&mut self, source: &Path, target: &Path, relative: &Path) -> io::Result<()> {
+        let metadata = stdfs::metadata(source)?;
+        if self.clones {
+            match clone_file(source, target) {
+                Ok(()) => return finish_copy(target, &metadata),
+                Err(err) if unsupported(&err) => self.clones = false,
+                Err(err) => return Err(err),
+            }
+        }
+        if let Some(link_dest) = &self.link_dest {
+            let earlier = link_dest.join(relative);
+            if let Ok(earlier_meta) = stdfs::metadata(&earlier) {
+                if earlier_meta.len() == metadata.len()
+                    && earlier_meta.modified().ok() == metadata.modified().ok()
+                {
+                    return stdfs::hard_link(&earlier, target);
+                }
+            }
+        }
+        stdfs::copy(source, target)?;
+        finish_copy(target, &metadata)
+    }
+}
+
+/// keeps the source's mtime, which is how the next snapshot tells an
+/// unchanged file from a rewritten one.
+fn finish_copy(target: &Path, metadata: &stdfs::Metadata) -> io::Result<()> {
+    // a read-only handle does: setting times only takes owning the file
+    let file = File::open(target)?;
+    file.set_modified(metadata.modified()?)?;
+    file.set_permissions(metadata.permissions())
+}
+
+fn unsupported(err: &io::Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(libc::EOPNOTSUPP | libc::ENOTTY | libc::EXDEV | libc::EINVAL | libc::ENOSYS)
+    )
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn clone_file(source: &Path, target: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let from = File::open(source)?;
+    let to = File::create_new(target)?;
+    // SAFETY: both descriptors stay open for the duration of the call
+    let cloned = unsafe { libc::ioctl(to.as_raw_fd(), libc::FICLONE, from.as_raw_fd()) };
+    if cloned == 0 {
+        return Ok(());
+    }
+    let err = io::Error::last_os_error();
+    drop(to);
+    let _ = stdfs::remove_file(target);
+    Err(err)
+}
+
+#[cfg(target_os = "macos")]
+fn clone_file(source: &Path, target: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let from = CString::new(source.as_os_str().as_bytes())?;
+    let to = CString::new(target.as_os_str().as_bytes())?;
+    // SAFETY: both paths are valid nul-terminated strings
+    if unsafe { libc::clonefile(from.as_ptr(), to.as_ptr(), 0) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn clone_file(_source: &Path, _target: &Path) -> io::Result<()> {
+    Err(io::Error::from_raw_os_error(libc::EOPNOTSUPP))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn links_unchanged_files_and_retargets_symlinks() {
+        let root =
+            std::env::temp_dir().join(format!("silicon-alloy-copy-{}", uuid::Uuid::new_v4()));
+        let original = root.join("original");
+        stdfs::create_dir_all(original.join("drive_c")).unwrap();
+        stdfs::write(original.join("drive_c/app.exe"), "binary").unwrap();
+        std::os::unix::fs::symlink(original.join("drive_c"), original.join("c:")).unwrap();
+        std::os::unix::fs::symlink("/", original.join("z:")).unwrap();
+
+        let first = root.join("first");
+        Copier::new(None).copy_tree(&original, &first).unwrap();
+        // without clones an unchanged file is one inode across copies
+        let mut copier =
+            Copier::new(Some(first.clone())).retarget(original.clone(), root.join("second"));
+        copier.clones = false;
+        copier.copy_tree(&original, &root.join("second")).unwrap();
+        let ino = |path: PathBuf| stdfs::metadata(path).unwrap().ino();
+        assert_eq!(
+            ino(first.join("drive_c/app.exe")),
+            ino(root.join("second/drive_c/app.exe"))
+        );
+        assert_eq!(
+            stdfs::read_link(first.join("c:")).unwrap(),
+            original.join("drive_c")
+        );
+        assert_eq!(
+            stdfs::read_link(root.join("second/c:")).unwrap(),
+            root.join("second/drive_c")
+        );
+        assert_eq!(
+            stdfs::read_link(root.join("second/z:")).unwrap(),
+            Path::new("/")
+        );
+        stdfs::remove_dir_all(&root).unwrap();
+    }
+}
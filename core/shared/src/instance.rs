@@ -0,0 +1,154 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{ErrorKind, Read, Seek, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+/// how long a daemon found on the socket gets to answer the ping probe.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// exclusive claim on a socket path. the lock file sits next to the socket,
+/// holds an flock for as long as the daemon lives and records its pid. the
+/// kernel drops the flock when the process dies, so a crash never leaves a
+/// stale claim behind.
+pub struct InstanceLock {
+    _file: File,
+    path: PathBuf,
+}
+
+impl InstanceLock {
+    pub fn acquire(socket_path: &Path) -> Result<Self> {
+        let path = lock_path(socket_path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("unable to open lock file {}", path.display()))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut pid = String::new();
+                let _ = file.read_to_string(&mut pid);
+                bail!(
+                    "another daemon (pid {}) is already serving {}",
+                    pid.trim(),
+                    socket_path.display()
+                );
+            }
+            Err(TryLockError::Error(err)) => {
+                return Err(err).with_context(|| format!("unable to lock {}", path.display()))
+            }
+        }
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "{}", std::process::id())?;
+        file.flush()?;
+        Ok(Self { _file: file, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+pub fn lock_path(socket_path: &Path) -> PathBuf {
+    socket_path.with_extension("lock")
+}
+
+/// what is sitting at a socket path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketState {
+    Missing,
+    /// a socket nobody is listening on, left behind by a crashed daemon.
+    Stale,
+    /// a daemon answered the ping.
+    Live,
+}
+
+/// connects to `path` and sends `service.ping`. anything that accepts the
+/// connection but doesn't answer within a couple of seconds is an error:
+/// something owns that socket and it isn't ours to delete.
+pub async fn probe_socket(path: &Path) -> Result<SocketState> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(SocketState::Missing),
+        Err(err) => return Err(err.into()),
+    };
+    if !metadata.file_type().is_socket() {
+        bail!(
+            "{} exists and is not a socket, refusing to replace it",
+            path.display()
+        );
+    }
+    let mut stream = match UnixStream::connect(path).await {
+        Ok(stream) => stream,
+        Err(err) if err.kind() == ErrorKind::ConnectionRefused => return Ok(SocketState::Stale),
+        Err(err) => return Err(err.into()),
+    };
+    let ping = json!({ "jsonrpc": "2.0", "id": 0, "method": "service.ping" });
+    let answered = tokio::time::timeout(PROBE_TIMEOUT, async {
+        stream.write_all(format!("{ping}\n").as_bytes()).await?;
+        let mut line = String::new();
+        BufReader::new(&mut stream).read_line(&mut line).await?;
+        Ok::<_, std::io::Error>(serde_json::from_str::<Value>(&line).is_ok())
+    })
+    .await;
+    match answered {
+        Ok(Ok(true)) => Ok(SocketState::Live),
+        _ => bail!(
+            "something is listening on {} but did not answer a ping",
+            path.display()
+        ),
+    }
+}
+
+/// makes `path` free to bind: removes a stale socket and refuses to touch a
+/// live one.
+pub async fn clear_socket(path: &Path) -> Result<()> {
+    match probe_socket(path).await? {
+        SocketState::Missing => Ok(()),
+        SocketState::Stale => {
+            std::fs::remove_file(path)
+                .with_context(|| format!("unable to remove stale socket {}", path.display()))?;
+            Ok(())
+        }
+        SocketState::Live => bail!("a daemon is already listening on {}", path.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn second_instance_is_refused_and_stale_sockets_cleared() {
+        let dir =
+            std::env::temp_dir().join(format!("silicon-alloy-instance-{}", uuid::Uuid::new_v4()));
+        let socket = dir.join("daemon.sock");
+        let lock = InstanceLock::acquire(&socket).unwrap();
+        let pid = std::fs::read_to_string(lock.path()).unwrap();
+        assert_eq!(pid.trim(), std::process::id().to_string());
+        let err = InstanceLock::acquire(&socket).err().unwrap();
+        assert!(err.to_string().contains("already serving"), "{err}");
+
+        // a bound-then-dropped listener leaves exactly what a crash does
+        drop(tokio::net::UnixListener::bind(&socket).unwrap());
+        assert_eq!(probe_socket(&socket).await.unwrap(), SocketState::Stale);
+        clear_socket(&socket).await.unwrap();
+        assert_eq!(probe_socket(&socket).await.unwrap(), SocketState::Missing);
+
+        drop(lock);
+        InstanceLock::acquire(&socket).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
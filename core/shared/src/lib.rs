@@ -1,27 +1,78 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use directories::ProjectDirs;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
 use uuid::Uuid;
 
+use crate::copy::Copier;
+use crate::lock::{BottleLock, LockHolder, LockMode};
+
 const BOTTLE_META: &str = "bottle.json";
+/// registry hives wine keeps at the top of a prefix.
+const REGISTRY_HIVES: &[&str] = &["system.reg", "user.reg", "userdef.reg"];
 
+pub mod access;
+pub mod adopt;
+pub mod activation;
+pub mod alloy;
+pub mod api;
+pub mod archive;
+pub mod config;
+mod copy;
+pub mod instance;
+pub mod lock;
+pub mod metrics;
+mod migrate;
 pub mod recipes;
+pub mod snapshot;
+pub mod trash;
+pub mod usage;
+
+pub use crate::migrate::SCHEMA_VERSION;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct BottleRecord {
+    /// see `SCHEMA_VERSION`; older records are upgraded as they are read
+    pub schema_version: u32,
     pub id: Uuid,
     pub name: String,
     pub created_at: u64,
     pub wine_runtime: WineRuntime,
     pub environment: Vec<(String, String)>,
+    pub notes: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// edits `bottle.update` makes to a bottle's record. anything left out stays
+/// as it is.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct BottleChanges {
+    /// new name, which no other bottle may have
+    #[serde(default)]
+    pub name: Option<String>,
+    /// environment variables to set, replacing any of the same name
+    #[serde(default)]
+    pub env_set: BTreeMap<String, String>,
+    /// environment variables to remove; applied before `env_set`
+    #[serde(default)]
+    pub env_unset: Vec<String>,
+    /// replaces the notes; an empty string clears them
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub add_tags: Vec<String>,
+    /// applied before `add_tags`
+    #[serde(default)]
+    pub remove_tags: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct WineRuntime {
     pub label: String,
     pub wine64_path: PathBuf,
@@ -30,7 +81,7 @@ pub struct WineRuntime {
     pub channel: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct RuntimeDescriptor {
     pub channel: String,
     pub label: String,
@@ -51,9 +102,20 @@ impl RuntimeDescriptor {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct BottleList {
-    pub bottles: Vec<BottleRecord>,
+    pub bottles: Vec<ListedBottle>,
+}
+
+/// a bottle as `bottle.list` shows it: its record, and whether anything is
+/// using it right now.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ListedBottle {
+    #[serde(flatten)]
+    pub bottle: BottleRecord,
+    pub busy: bool,
+    #[serde(default)]
+    pub holders: Vec<LockHolder>,
 }
 
 #[derive(Clone)]
@@ -65,6 +127,11 @@ impl BottleStore {
     pub fn new() -> Result<Self> {
         let dirs = project_dirs()?;
         let root = dirs.data_dir().join("bottles");
+        Self::with_root(root)
+    }
+
+    pub fn with_root(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
         std::fs::create_dir_all(&root).context("failed to create bottle root")?;
         Ok(Self { root })
     }
@@ -80,7 +147,7 @@ impl BottleStore {
             let meta_path = entry.path().join(BOTTLE_META);
             if meta_path.exists() {
                 let data = fs::read(&meta_path).await?;
-                match serde_json::from_slice::<BottleRecord>(&data) {
+                match self.load_record(&entry.path(), &data).await {
                     Ok(record) => bottles.push(record),
                     Err(err) => {
                         tracing::warn!("ignored bottle {:?}: {}", meta_path, err);
@@ -92,8 +159,23 @@ impl BottleStore {
         Ok(bottles)
     }
 
+    /// finds the one bottle `selector` names; see `select_bottle`.
+    pub async fn resolve(&self, selector: &str) -> Result<BottleRecord> {
+        if let Ok(id) = selector.trim().parse::<Uuid>() {
+            // no need to read every record for this
+            if !self.root.join(id.to_string()).exists() {
+                bail!("bottle {id} not found");
+            }
+            return self.record(id).await;
+        }
+        let bottles = self.list().await?;
+        select_bottle(&bottles, selector).cloned()
+    }
+
+    /// creates an empty bottle. no other bottle may have the same name.
     pub async fn create(&self, name: &str, runtime: WineRuntime) -> Result<BottleRecord> {
         let id = Uuid::new_v4();
+        self.check_name(name, id).await?;
         let bottle_dir = self.root.join(id.to_string());
         fs::create_dir_all(&bottle_dir)
             .await
@@ -103,25 +185,100 @@ impl BottleStore {
             .await
             .context("failed to create wine prefix directory")?;
         let record = BottleRecord {
+            schema_version: SCHEMA_VERSION,
             id,
-            name: name.to_string(),
+            name: name.trim().to_string(),
             created_at: unix_timestamp(),
             wine_runtime: runtime,
             environment: Vec::new(),
+            notes: None,
+            tags: Vec::new(),
         };
-        self.write_record(&bottle_dir, &record).await?;
+        if let Err(err) = self.write_named_record(&bottle_dir, &record).await {
+            let _ = fs::remove_dir_all(&bottle_dir).await;
+            return Err(err);
+        }
         Ok(record)
     }
 
-    pub async fn remove(&self, id: Uuid) -> Result<()> {
-        let dir = self.root.join(id.to_string());
-        if dir.exists() {
-            fs::remove_dir_all(&dir)
-                .await
-                .with_context(|| format!("failed to remove bottle {id}"))?;
-        } else {
-            return Err(anyhow!("bottle {id} not found"));
+    /// copies bottle `id` into a new bottle called `name`. everything that
+    /// names the original's directory is pointed at the copy: absolute
+    /// symlinks such as `dosdevices` entries, paths in the registry hives,
+    /// and values in the stored environment. the same goes for the real
+    /// place of a prefix adopted in place, which the clone gets a copy of.
+    /// snapshots stay behind.
+    pub async fn clone_bottle(&self, lock: &BottleLock, name: &str) -> Result<BottleRecord> {
+        let id = lock.covers(LockMode::Exclusive)?;
+        let source = self.record(id).await?;
+        let clone_id = Uuid::new_v4();
+        self.check_name(name, clone_id).await?;
+        let from_dir = self.root.join(id.to_string());
+        let to_dir = self.root.join(clone_id.to_string());
+        let linked = self.linked_prefix(id).await?;
+        fs::create_dir_all(&to_dir)
+            .await
+            .context("failed to create bottle directory")?;
+        let copied = self
+            .copy_prefix(id, clone_id, &from_dir, &to_dir, linked.as_deref())
+            .await;
+        if let Err(err) = copied {
+            let _ = fs::remove_dir_all(&to_dir).await;
+            return Err(err).with_context(|| format!("failed to clone bottle {id}"));
         }
+        let mut environment = source.environment;
+        if let Some(linked) = &linked {
+            environment = rewrite_environment(environment, linked, &self.bottle_prefix(clone_id));
+        }
+        let environment = rewrite_environment(environment, &from_dir, &to_dir);
+        let record = BottleRecord {
+            schema_version: SCHEMA_VERSION,
+            id: clone_id,
+            name: name.trim().to_string(),
+            created_at: unix_timestamp(),
+            wine_runtime: source.wine_runtime,
+            environment,
+            notes: source.notes,
+            tags: source.tags,
+        };
+        // written last, so a clone that failed halfway is never listed
+        if let Err(err) = self.write_named_record(&to_dir, &record).await {
+            let _ = fs::remove_dir_all(&to_dir).await;
+            return Err(err);
+        }
+        Ok(record)
+    }
+
+    async fn copy_prefix(
+        &self,
+        id: Uuid,
+        clone_id: Uuid,
+        from_dir: &Path,
+        to_dir: &Path,
+        linked: Option<&Path>,
+    ) -> Result<()> {
+        let from = self.bottle_prefix(id);
+        let to = self.bottle_prefix(clone_id);
+        let mut copier = Copier::new(None);
+        if let Some(linked) = linked {
+            copier = copier.retarget(linked.to_path_buf(), to.clone());
+        }
+        let mut copier = copier.retarget(from_dir.to_path_buf(), to_dir.to_path_buf());
+        let prefix = to.clone();
+        tokio::task::spawn_blocking(move || copier.copy_tree(&from, &prefix)).await??;
+        if let Some(linked) = linked {
+            rewrite_hives(&to, linked, &to).await?;
+        }
+        rewrite_hives(&to, from_dir, to_dir).await
+    }
+
+    pub async fn remove(&self, lock: &BottleLock) -> Result<()> {
+        let id = lock.covers(LockMode::Exclusive)?;
+        let dir = self.root.join(id.to_string());
+        fs::remove_dir_all(&dir)
+            .await
+            .with_context(|| format!("failed to remove bottle {id}"))?;
+        // anyone still waiting on the old file finds the bottle gone
+        let _ = fs::remove_file(self.lock_path(id)).await;
         Ok(())
     }
 
@@ -130,7 +287,42 @@ impl BottleStore {
         let data = fs::read(dir.join(BOTTLE_META))
             .await
             .with_context(|| format!("failed to read bottle metadata for {id}"))?;
-        Ok(serde_json::from_slice(&data)?)
+        self.load_record(&dir, &data)
+            .await
+            .with_context(|| format!("failed to read bottle metadata for {id}"))
+    }
+
+    /// parses `bottle.json`, writing it back if it had to be upgraded.
+    async fn load_record(&self, dir: &Path, data: &[u8]) -> Result<BottleRecord> {
+        let (record, upgraded) = migrate::upgrade(data)?;
+        if upgraded {
+            self.save_upgraded(dir, record.id).await;
+        }
+        Ok(record)
+    }
+
+    /// writes an upgraded record back, but only while nobody holds the
+    /// bottle: a holder may be changing the record, and writing over it
+    /// would lose that. a record that isn't written back, like one in a
+    /// read-only store or in the trash, is just upgraded again next time.
+    async fn save_upgraded(&self, dir: &Path, id: Uuid) {
+        if dir != self.root.join(id.to_string()) {
+            return;
+        }
+        let Ok(_lock) = self.lock(id, LockMode::Exclusive, "migration") else {
+            return;
+        };
+        // read again, since it may have changed before the lock was taken
+        let saved = async {
+            let (record, upgraded) = migrate::upgrade(&fs::read(dir.join(BOTTLE_META)).await?)?;
+            if upgraded {
+                self.write_record(dir, &record).await?;
+            }
+            Ok::<_, anyhow::Error>(())
+        };
+        if let Err(err) = saved.await {
+            tracing::warn!("unable to save upgraded metadata for {id}: {err:#}");
+        }
     }
 
     pub async fn update_record(&self, id: Uuid, record: &BottleRecord) -> Result<()> {
@@ -141,19 +333,172 @@ impl BottleStore {
         self.write_record(&dir, record).await
     }
 
+    /// applies `changes` to the bottle's record all at once: one that can't
+    /// be made leaves the record untouched.
+    pub async fn update_bottle(
+        &self,
+        lock: &BottleLock,
+        changes: &BottleChanges,
+    ) -> Result<BottleRecord> {
+        let id = lock.covers(LockMode::Exclusive)?;
+        let mut record = self.record(id).await?;
+        if let Some(name) = &changes.name {
+            record.name = name.trim().to_string();
+        }
+        for key in &changes.env_unset {
+            record.environment.retain(|(existing, _)| existing != key);
+        }
+        for (key, value) in &changes.env_set {
+            if key.is_empty() || key.contains('=') {
+                bail!("invalid environment variable name {key:?}");
+            }
+            record.environment.retain(|(existing, _)| existing != key);
+            record.environment.push((key.clone(), value.clone()));
+        }
+        if let Some(notes) = &changes.notes {
+            record.notes = Some(notes.clone()).filter(|notes| !notes.is_empty());
+        }
+        record.tags.retain(|tag| !changes.remove_tags.contains(tag));
+        for tag in &changes.add_tags {
+            let tag = tag.trim();
+            if tag.is_empty() {
+                bail!("a tag can't be empty");
+            }
+            if !record.tags.iter().any(|existing| existing == tag) {
+                record.tags.push(tag.to_string());
+            }
+        }
+        let dir = self.root.join(id.to_string());
+        if changes.name.is_some() {
+            self.write_named_record(&dir, &record).await?;
+        } else {
+            // not checked otherwise, so bottles that shared a name before
+            // names were unique can still be edited
+            self.write_record(&dir, &record).await?;
+        }
+        Ok(record)
+    }
+
+    /// fails unless bottle `id` may be called `name`, as stored: trimmed. it
+    /// can't be empty or a uuid, and no other bottle may have it.
+    async fn check_name(&self, name: &str, id: Uuid) -> Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            bail!("a bottle name can't be empty");
+        }
+        if name.parse::<Uuid>().is_ok() {
+            bail!("a bottle name can't be a uuid");
+        }
+        let bottles = self.list().await?;
+        let taken = bottles
+            .iter()
+            .find(|other| other.id != id && other.name.trim() == name);
+        if let Some(other) = taken {
+            bail!("bottle {} is already called {name}", other.id);
+        }
+        Ok(())
+    }
+
+    /// writes the record of a bottle that is new or renamed, unless another
+    /// bottle has taken the name in the meantime.
+    async fn write_named_record(&self, dir: &Path, record: &BottleRecord) -> Result<()> {
+        let _names = self.lock_names().await?;
+        self.check_name(&record.name, record.id).await?;
+        self.write_record(dir, record).await
+    }
+
     pub fn bottle_prefix(&self, id: Uuid) -> PathBuf {
         self.root.join(id.to_string()).join("prefix")
     }
 
     async fn write_record(&self, dir: &Path, record: &BottleRecord) -> Result<()> {
-        let meta_path = dir.join(BOTTLE_META);
-        let mut file = fs::File::create(&meta_path)
-            .await
-            .with_context(|| format!("failed to write {:?}", meta_path))?;
         let data = serde_json::to_vec_pretty(record)?;
-        file.write_all(&data).await?;
-        Ok(())
+        write_atomic(&dir.join(BOTTLE_META), &data).await
+    }
+}
+
+/// replaces `path` with `data` through a temporary file and a rename, so a
+/// reader or a crash finds either the old contents or the new, never a
+/// truncated file.
+async fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| anyhow!("{} has no parent directory", path.display()))?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = dir.join(format!(".{name}.{}.tmp", Uuid::new_v4()));
+    let written = async {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+            .await?;
+        file.write_all(data).await?;
+        file.sync_all().await?;
+        fs::rename(&temp, path).await?;
+        // the rename only survives a crash once the directory is synced too
+        fs::File::open(dir).await?.sync_all().await
     }
+    .await;
+    if written.is_err() {
+        let _ = fs::remove_file(&temp).await;
+    }
+    written.with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// replaces every mention of the directory `from` with `to`, both as a unix
+/// path and the way the registry spells it: through the `Z:` drive, with
+/// each separator an escaped backslash.
+fn rewrite_paths(data: &[u8], from: &Path, to: &Path) -> Vec<u8> {
+    let (from, to) = (from.to_string_lossy(), to.to_string_lossy());
+    let unix = replace_bytes(data, from.as_bytes(), to.as_bytes());
+    let escaped = |path: &str| path.replace('/', "\\\\");
+    replace_bytes(&unix, escaped(&from).as_bytes(), escaped(&to).as_bytes())
+}
+
+fn rewrite_environment(
+    environment: Vec<(String, String)>,
+    from: &Path,
+    to: &Path,
+) -> Vec<(String, String)> {
+    environment
+        .into_iter()
+        .map(|(key, value)| {
+            let value = rewrite_paths(value.as_bytes(), from, to);
+            (key, String::from_utf8_lossy(&value).into_owned())
+        })
+        .collect()
+}
+
+/// points the registry hives of `prefix` at `to` wherever they name `from`.
+async fn rewrite_hives(prefix: &Path, from: &Path, to: &Path) -> Result<()> {
+    for hive in REGISTRY_HIVES {
+        let path = prefix.join(hive);
+        // never through a symlink, which could name any file of the user's
+        match fs::symlink_metadata(&path).await {
+            Ok(metadata) if metadata.is_file() => {}
+            Ok(_) => continue,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        }
+        let data = fs::read(&path).await?;
+        let rewritten = rewrite_paths(&data, from, to);
+        if rewritten != data {
+            fs::write(&path, rewritten).await?;
+        }
+    }
+    Ok(())
+}
+
+fn replace_bytes(data: &[u8], from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut rest = data;
+    while let Some(at) = rest.windows(from.len()).position(|window| window == from) {
+        out.extend_from_slice(&rest[..at]);
+        out.extend_from_slice(to);
+        rest = &rest[at + from.len()..];
+    }
+    out.extend_from_slice(rest);
+    out
 }
 
 pub fn project_dirs() -> Result<ProjectDirs> {
@@ -161,6 +506,14 @@ pub fn project_dirs() -> Result<ProjectDirs> {
         .ok_or_else(|| anyhow!("unable to determine project directories"))
 }
 
+/// the file the daemon logs to, rolled over daily as `daemon.log.<date>`.
+pub const DAEMON_LOG: &str = "daemon.log";
+
+/// where the daemon keeps `daemon.log`, under the data root.
+pub fn log_dir() -> Result<PathBuf> {
+    Ok(project_dirs()?.data_dir().join("logs"))
+}
+
 pub fn runtime_root() -> Result<PathBuf> {
     let dirs = project_dirs()?;
     let path = dirs.data_dir().join("runtime");
@@ -168,11 +521,10 @@ pub fn runtime_root() -> Result<PathBuf> {
     Ok(path)
 }
 
+/// the socket the daemon listens on, from the config file or
+/// `SILICON_ALLOY_SOCKET`.
 pub fn daemon_socket_path() -> Result<PathBuf> {
-    let dirs = project_dirs()?;
-    let runtime_dir = dirs.runtime_dir().unwrap_or_else(|| dirs.data_dir());
-    std::fs::create_dir_all(runtime_dir)?;
-    Ok(runtime_dir.join("daemon.sock"))
+    Ok(config::DaemonConfig::load()?.socket_path.value)
 }
 
 pub fn discover_runtimes(root: &Path) -> Result<Vec<RuntimeDescriptor>> {
@@ -222,10 +574,190 @@ pub fn discover_runtimes(root: &Path) -> Result<Vec<RuntimeDescriptor>> {
     Ok(runtimes)
 }
 
-fn unix_timestamp() -> u64 {
+/// the one bottle `selector` names, tried as a uuid, then as an exact name,
+/// then as the start of a uuid. names are unique for bottles made since
+/// that was enforced; older ones may still share a name, which then has to
+/// be told apart by uuid.
+pub fn select_bottle<'a>(bottles: &'a [BottleRecord], selector: &str) -> Result<&'a BottleRecord> {
+    if let Ok(id) = selector.trim().parse::<Uuid>() {
+        return bottles
+            .iter()
+            .find(|bottle| bottle.id == id)
+            .ok_or_else(|| anyhow!("bottle {id} not found"));
+    }
+    let named: Vec<_> = bottles.iter().filter(|bottle| bottle.name == selector).collect();
+    match named.len() {
+        0 => {}
+        1 => return Ok(named[0]),
+        n => bail!("{n} bottles are called {selector}; use the uuid of the one you mean"),
+    }
+    let prefix = selector.trim().to_ascii_lowercase();
+    let started: Vec<_> = bottles
+        .iter()
+        .filter(|bottle| !prefix.is_empty() && bottle.id.to_string().starts_with(&prefix))
+        .collect();
+    match started.len() {
+        0 => bail!("bottle {selector} not found"),
+        1 => Ok(started[0]),
+        n => bail!("{n} bottle ids start with {prefix}; give more of the uuid"),
+    }
+}
+
+pub fn unix_timestamp() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .map(|d| d.as_secs())
         .unwrap_or_default()
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn clones_point_at_their_own_prefix() {
+        let root = std::env::temp_dir().join(format!("silicon-alloy-clone-{}", Uuid::new_v4()));
+        let store = BottleStore::with_root(&root).unwrap();
+        let runtime = WineRuntime {
+            label: "wine".to_string(),
+            wine64_path: PathBuf::from("/opt/wine64"),
+            version: "9.0".to_string(),
+            channel: None,
+        };
+        let mut base = store.create("base", runtime).await.unwrap();
+        let base_dir = root.join(base.id.to_string());
+        let prefix = store.bottle_prefix(base.id);
+        std::fs::create_dir_all(prefix.join("dosdevices")).unwrap();
+        std::os::unix::fs::symlink(prefix.join("drive_c"), prefix.join("dosdevices/c:"))
+            .unwrap();
+        std::os::unix::fs::symlink("/", prefix.join("dosdevices/z:")).unwrap();
+        let windows_path = |dir: &Path| format!("Z:{}", dir.display()).replace('/', "\\\\");
+        let hive = format!(
+            "\"Temp\"=\"{}\\\\drive_c\"\n\"Home\"=\"{}\"\n",
+            windows_path(&prefix),
+            prefix.display()
+        );
+        std::fs::write(prefix.join("user.reg"), &hive).unwrap();
+        base.environment.push((
+            "DXVK_LOG_PATH".to_string(),
+            format!("{}/logs", base_dir.display()),
+        ));
+        store.update_record(base.id, &base).await.unwrap();
+
+        let lock = store.lock(base.id, LockMode::Exclusive, "test").unwrap();
+        let copy = store.clone_bottle(&lock, "engineer").await.unwrap();
+        assert_ne!(copy.id, base.id);
+        assert_eq!(store.record(copy.id).await.unwrap().name, "engineer");
+        let copy_dir = root.join(copy.id.to_string());
+        let copy_prefix = store.bottle_prefix(copy.id);
+        assert_eq!(
+            std::fs::read_link(copy_prefix.join("dosdevices/c:")).unwrap(),
+            copy_prefix.join("drive_c")
+        );
+        assert_eq!(
+            std::fs::read_link(copy_prefix.join("dosdevices/z:")).unwrap(),
+            Path::new("/")
+        );
+        let copied_hive = std::fs::read_to_string(copy_prefix.join("user.reg")).unwrap();
+        assert!(copied_hive.contains(&windows_path(&copy_prefix)), "{copied_hive}");
+        assert!(copied_hive.contains(&copy_prefix.display().to_string()));
+        assert!(!copied_hive.contains(&base.id.to_string()));
+        assert_eq!(copy.environment[0].1, format!("{}/logs", copy_dir.display()));
+        // the original is left as it was
+        assert_eq!(std::fs::read_to_string(prefix.join("user.reg")).unwrap(), hive);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn updates_apply_all_or_nothing() {
+        let root = std::env::temp_dir().join(format!("silicon-alloy-update-{}", Uuid::new_v4()));
+        let store = BottleStore::with_root(&root).unwrap();
+        let runtime = WineRuntime {
+            label: "wine".to_string(),
+            wine64_path: PathBuf::from("/opt/wine64"),
+            version: "9.0".to_string(),
+            channel: None,
+        };
+        let steam = store.create("steam", runtime.clone()).await.unwrap();
+        store.create("office", runtime).await.unwrap();
+        let lock = store.lock(steam.id, LockMode::Exclusive, "test").unwrap();
+
+        let changes = BottleChanges {
+            name: Some(" games ".to_string()),
+            env_set: [("DXVK_HUD".to_string(), "1".to_string())].into(),
+            notes: Some("for the kids".to_string()),
+            add_tags: vec!["dxvk".to_string(), "dxvk".to_string()],
+            ..Default::default()
+        };
+        let updated = store.update_bottle(&lock, &changes).await.unwrap();
+        assert_eq!(updated.name, "games");
+        assert_eq!(updated.tags, vec!["dxvk"]);
+        let changes = BottleChanges {
+            env_unset: vec!["DXVK_HUD".to_string()],
+            notes: Some(String::new()),
+            remove_tags: vec!["dxvk".to_string()],
+            ..Default::default()
+        };
+        let updated = store.update_bottle(&lock, &changes).await.unwrap();
+        assert!(updated.environment.is_empty());
+        assert_eq!(updated.notes, None);
+        assert!(updated.tags.is_empty());
+
+        // a taken name fails the whole update
+        let changes = BottleChanges {
+            name: Some("office".to_string()),
+            add_tags: vec!["work".to_string()],
+            ..Default::default()
+        };
+        let err = store.update_bottle(&lock, &changes).await.unwrap_err();
+        assert!(err.to_string().contains("already called office"), "{err}");
+        let stored = store.record(steam.id).await.unwrap();
+        assert_eq!(stored.name, "games");
+        assert!(stored.tags.is_empty());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn bottles_resolve_by_uuid_name_or_prefix() {
+        let root = std::env::temp_dir().join(format!("silicon-alloy-resolve-{}", Uuid::new_v4()));
+        let store = BottleStore::with_root(&root).unwrap();
+        let runtime = WineRuntime {
+            label: "wine".to_string(),
+            wine64_path: PathBuf::from("/opt/wine64"),
+            version: "9.0".to_string(),
+            channel: None,
+        };
+        let steam = store.create("steam", runtime.clone()).await.unwrap();
+        let err = store.create("steam", runtime.clone()).await.unwrap_err();
+        assert!(err.to_string().contains("already called steam"), "{err}");
+        // names are stored trimmed, so padding doesn't make a new one
+        let err = store.create("steam ", runtime.clone()).await.unwrap_err();
+        assert!(err.to_string().contains("already called steam"), "{err}");
+        assert!(store.create(&Uuid::new_v4().to_string(), runtime.clone()).await.is_err());
+        assert_eq!(store.list().await.unwrap().len(), 1);
+        let office = store.create(" office ", runtime.clone()).await.unwrap();
+        assert_eq!(office.name, "office");
+        assert_eq!(store.resolve("office").await.unwrap().id, office.id);
+        let lock = store.lock(office.id, LockMode::Exclusive, "test").unwrap();
+        assert!(store.clone_bottle(&lock, "steam\t").await.is_err());
+        store.remove(&lock).await.unwrap();
+        drop(lock);
+
+        let id = steam.id.to_string();
+        assert_eq!(store.resolve(&id).await.unwrap().id, steam.id);
+        assert_eq!(store.resolve("steam").await.unwrap().id, steam.id);
+        assert_eq!(store.resolve(&id[..8].to_uppercase()).await.unwrap().id, steam.id);
+        assert!(store.resolve("Steam").await.is_err());
+        assert!(store.resolve(&Uuid::new_v4().to_string()).await.is_err());
+
+        // bottles from before names were unique are told apart by uuid
+        let mut bottles = vec![steam.clone(), steam.clone()];
+        bottles[1].id = Uuid::new_v4();
+        let err = select_bottle(&bottles, "steam").unwrap_err();
+        assert!(err.to_string().starts_with("2 bottles are called steam"), "{err}");
+        assert_eq!(select_bottle(&bottles, &id).unwrap().id, steam.id);
+        assert!(select_bottle(&bottles, "").is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,288 @@
+use std::fmt;
+use std::fs::{self as stdfs, File, OpenOptions, TryLockError};
+use std::io;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{unix_timestamp, BottleStore};
+
+/// per-bottle lock files, kept outside the bottles so they never end up in
+/// a copy of one.
+const LOCK_DIR: &str = ".locks";
+/// taken while a bottle's name is checked and written, across bottles.
+const NAMES_LOCK: &str = "names.lock";
+
+/// how a bottle is being used. any number of shared holders can run programs
+/// in a bottle at once; changing or removing it needs it exclusively.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LockMode {
+    Shared,
+    Exclusive,
+}
+
+/// someone holding a bottle's lock, as `bottle.list` and busy errors show it.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct LockHolder {
+    pub mode: LockMode,
+    /// what the lock is held for, usually a method name like `recipe.apply`
+    pub holder: String,
+    pub pid: u32,
+    pub since: u64,
+}
+
+/// an flock on one bottle, released on drop. the lock is taken on a file
+/// rather than in memory so a daemon and an in-process cli sharing the same
+/// bottle root keep out of each other's way too. next to it sits a note
+/// saying who holds it, since an flock can't tell.
+pub struct BottleLock {
+    id: Uuid,
+    mode: LockMode,
+    note: PathBuf,
+    _file: File,
+}
+
+impl BottleLock {
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// the bottle this lock is on, if it is held at least as strongly as
+    /// `mode` needs.
+    pub(crate) fn covers(&self, mode: LockMode) -> Result<Uuid> {
+        if mode == LockMode::Exclusive && self.mode == LockMode::Shared {
+            return Err(anyhow!("bottle {} is only locked shared", self.id));
+        }
+        Ok(self.id)
+    }
+}
+
+impl Drop for BottleLock {
+    fn drop(&mut self) {
+        let _ = stdfs::remove_file(&self.note);
+    }
+}
+
+/// a lock request that found the bottle held in a conflicting mode and was
+/// told not to wait.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BottleBusy {
+    pub bottle_id: Uuid,
+    pub holders: Vec<LockHolder>,
+}
+
+impl fmt::Display for BottleBusy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bottle {} is busy", self.bottle_id)?;
+        for (n, holder) in self.holders.iter().enumerate() {
+            let sep = if n == 0 { ": " } else { ", " };
+            write!(f, "{sep}{} (pid {})", holder.holder, holder.pid)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for BottleBusy {}
+
+impl BottleStore {
+    /// takes the bottle's lock for `holder` without waiting. a bottle held in
+    /// a conflicting mode fails with `BottleBusy`: waiting on a running
+    /// program could take hours, so only callers who ask for it wait.
+    pub fn lock(&self, id: Uuid, mode: LockMode, holder: &str) -> Result<BottleLock> {
+        let file = self.lock_file(id)?;
+        let locked = match mode {
+            LockMode::Shared => file.try_lock_shared(),
+            LockMode::Exclusive => file.try_lock(),
+        };
+        match locked {
+            Ok(()) => self.held(id, mode, holder, file),
+            Err(TryLockError::WouldBlock) => Err(BottleBusy {
+                bottle_id: id,
+                holders: self.holders(id),
+            }
+            .into()),
+            Err(TryLockError::Error(err)) => {
+                Err(err).with_context(|| format!("unable to lock bottle {id}"))
+            }
+        }
+    }
+
+    /// takes the bottle's lock for `holder`, waiting for whoever has it now.
+    pub async fn lock_waiting(&self, id: Uuid, mode: LockMode, holder: &str) -> Result<BottleLock> {
+        let file = self.lock_file(id)?;
+        // flock blocks the thread; if this future is dropped the file is
+        // dropped with the result and the lock goes straight back
+        let file = tokio::task::spawn_blocking(move || {
+            match mode {
+                LockMode::Shared => file.lock_shared(),
+                LockMode::Exclusive => file.lock(),
+            }
+            .map(|()| file)
+        })
+        .await?
+        .with_context(|| format!("unable to lock bottle {id}"))?;
+        // the bottle may have been deleted while we waited
+        if !self.root.join(id.to_string()).exists() {
+            return Err(anyhow!("bottle {id} not found"));
+        }
+        self.held(id, mode, holder, file)
+    }
+
+    /// who holds the bottle's lock now; empty when nobody does. notes left
+    /// behind by processes that died holding the lock are cleared away.
+    pub fn holders(&self, id: Uuid) -> Vec<LockHolder> {
+        let Ok(entries) = stdfs::read_dir(self.root.join(LOCK_DIR)) else {
+            return Vec::new();
+        };
+        let prefix = format!("{id}.");
+        let mut holders = Vec::new();
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if !name.starts_with(&prefix) || !name.ends_with(".holder") {
+                continue;
+            }
+            let Ok(data) = stdfs::read(entry.path()) else {
+                continue;
+            };
+            // one that doesn't parse may still be being written
+            let Ok(holder) = serde_json::from_slice::<LockHolder>(&data) else {
+                continue;
+            };
+            if alive(holder.pid) {
+                holders.push(holder);
+            } else {
+                let _ = stdfs::remove_file(entry.path());
+            }
+        }
+        holders.sort_by_key(|holder| holder.since);
+        holders
+    }
+
+    /// blocks until no one else is naming a bottle. released when the file
+    /// is dropped.
+    pub(crate) async fn lock_names(&self) -> Result<File> {
+        stdfs::create_dir_all(self.root.join(LOCK_DIR))?;
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.root.join(LOCK_DIR).join(NAMES_LOCK))
+            .context("unable to open the bottle names lock")?;
+        tokio::task::spawn_blocking(move || file.lock().map(|()| file))
+            .await?
+            .context("unable to lock bottle names")
+    }
+
+    pub(crate) fn lock_path(&self, id: Uuid) -> PathBuf {
+        self.root.join(LOCK_DIR).join(format!("{id}.lock"))
+    }
+
+    fn lock_file(&self, id: Uuid) -> Result<File> {
+        // checked first so a typo doesn't leave a lock file behind
+        if !self.root.join(id.to_string()).exists() {
+            return Err(anyhow!("bottle {id} not found"));
+        }
+        let path = self.lock_path(id);
+        stdfs::create_dir_all(self.root.join(LOCK_DIR))?;
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("unable to open lock file {}", path.display()))
+    }
+
+    fn held(&self, id: Uuid, mode: LockMode, holder: &str, file: File) -> Result<BottleLock> {
+        let note = self
+            .root
+            .join(LOCK_DIR)
+            .join(format!("{id}.{}.holder", Uuid::new_v4()));
+        let holder = LockHolder {
+            mode,
+            holder: holder.to_string(),
+            pid: std::process::id(),
+            since: unix_timestamp(),
+        };
+        stdfs::write(&note, serde_json::to_vec(&holder)?)?;
+        Ok(BottleLock {
+            id,
+            mode,
+            note,
+            _file: file,
+        })
+    }
+}
+
+fn alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: signal 0 only checks that the process exists
+    let sent = unsafe { libc::kill(pid, 0) };
+    sent == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WineRuntime;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn busy_bottles_name_their_holders() {
+        let root = std::env::temp_dir().join(format!("silicon-alloy-lock-{}", Uuid::new_v4()));
+        let store = BottleStore::with_root(&root).unwrap();
+        let runtime = WineRuntime {
+            label: "wine".to_string(),
+            wine64_path: PathBuf::from("/opt/wine64"),
+            version: "9.0".to_string(),
+            channel: None,
+        };
+        let bottle = store.create("busy", runtime).await.unwrap();
+        assert!(store.holders(bottle.id).is_empty());
+
+        let run = store
+            .lock(bottle.id, LockMode::Shared, "bottle.run")
+            .unwrap();
+        let other = store
+            .lock(bottle.id, LockMode::Shared, "bottle.run")
+            .unwrap();
+        assert_eq!(store.holders(bottle.id).len(), 2);
+        let err = store
+            .lock(bottle.id, LockMode::Exclusive, "recipe.apply")
+            .err()
+            .unwrap();
+        let busy = err.downcast_ref::<BottleBusy>().unwrap();
+        assert_eq!(busy.holders.len(), 2);
+        assert_eq!(busy.holders[0].holder, "bottle.run");
+        assert!(err.to_string().starts_with("bottle "));
+        drop(other);
+
+        let waiting = {
+            let store = store.clone();
+            tokio::spawn(async move {
+                store
+                    .lock_waiting(bottle.id, LockMode::Exclusive, "recipe.apply")
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+        drop(run);
+        let applied = waiting.await.unwrap().unwrap();
+        let holders = store.holders(bottle.id);
+        assert_eq!(holders.len(), 1);
+        assert_eq!(holders[0].mode, LockMode::Exclusive);
+        drop(applied);
+        assert!(store.holders(bottle.id).is_empty());
+        assert!(store
+            .lock(Uuid::new_v4(), LockMode::Shared, "bottle.run")
+            .is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
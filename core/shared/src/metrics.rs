@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// prefix of every metric in the prometheus output.
+const PREFIX: &str = "silicon_alloy";
+
+/// what `service.metrics` reports. counters start at zero when the daemon
+/// starts and are never persisted.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct MetricsSnapshot {
+    pub uptime_seconds: u64,
+    /// unix socket and gateway clients connected right now
+    pub active_connections: u64,
+    pub methods: Vec<MethodMetrics>,
+    /// bottles on disk, keyed by the channel of their runtime
+    pub bottles_by_channel: BTreeMap<String, u64>,
+    pub processes_started: u64,
+    /// finished wine processes keyed by exit code, or `signal` when one
+    /// killed them
+    pub process_exits: BTreeMap<String, u64>,
+    pub recipes_succeeded: u64,
+    pub recipes_failed: u64,
+    /// time spent in recipe steps, per step action
+    pub recipe_steps: Vec<StepMetrics>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct MethodMetrics {
+    /// unknown method names are counted together as `other`
+    pub method: String,
+    pub calls: u64,
+    /// calls answered with an error, refused ones included
+    pub errors: u64,
+    pub latency: Histogram,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StepMetrics {
+    pub action: String,
+    pub duration: Histogram,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Histogram {
+    /// cumulative, as in prometheus: each bucket also counts everything
+    /// in the buckets before it
+    pub buckets: Vec<Bucket>,
+    pub count: u64,
+    pub sum_seconds: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Bucket {
+    /// upper bound in seconds
+    pub le: f64,
+    pub count: u64,
+}
+
+impl MetricsSnapshot {
+    /// renders the snapshot in the prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = Prometheus::default();
+        out.header(
+            "uptime_seconds",
+            "gauge",
+            "seconds since the daemon started",
+        );
+        out.sample("uptime_seconds", &[], self.uptime_seconds);
+        out.header("active_connections", "gauge", "clients connected right now");
+        out.sample("active_connections", &[], self.active_connections);
+
+        out.header(
+            "rpc_calls_total",
+            "counter",
+            "rpc calls handled, per method",
+        );
+        for method in &self.methods {
+            out.sample(
+                "rpc_calls_total",
+                &[("method", &method.method)],
+                method.calls,
+            );
+        }
+        out.header(
+            "rpc_errors_total",
+            "counter",
+            "rpc calls answered with an error",
+        );
+        for method in &self.methods {
+            out.sample(
+                "rpc_errors_total",
+                &[("method", &method.method)],
+                method.errors,
+            );
+        }
+        out.header(
+            "rpc_duration_seconds",
+            "histogram",
+            "time spent handling rpc calls",
+        );
+        for method in &self.methods {
+            out.histogram(
+                "rpc_duration_seconds",
+                ("method", &method.method),
+                &method.latency,
+            );
+        }
+
+        out.header("bottles", "gauge", "bottles on disk, per runtime channel");
+        for (channel, count) in &self.bottles_by_channel {
+            out.sample("bottles", &[("channel", channel)], *count);
+        }
+
+        out.header(
+            "processes_started_total",
+            "counter",
+            "wine processes launched",
+        );
+        out.sample("processes_started_total", &[], self.processes_started);
+        out.header(
+            "process_exits_total",
+            "counter",
+            "wine processes exited, per exit code",
+        );
+        for (code, count) in &self.process_exits {
+            out.sample("process_exits_total", &[("code", code)], *count);
+        }
+
+        out.header(
+            "recipes_applied_total",
+            "counter",
+            "recipe applies, per outcome",
+        );
+        out.sample(
+            "recipes_applied_total",
+            &[("outcome", "succeeded")],
+            self.recipes_succeeded,
+        );
+        out.sample(
+            "recipes_applied_total",
+            &[("outcome", "failed")],
+            self.recipes_failed,
+        );
+        out.header(
+            "recipe_step_duration_seconds",
+            "histogram",
+            "time spent in recipe steps, per action",
+        );
+        for step in &self.recipe_steps {
+            out.histogram(
+                "recipe_step_duration_seconds",
+                ("action", &step.action),
+                &step.duration,
+            );
+        }
+        out.text
+    }
+}
+
+#[derive(Default)]
+struct Prometheus {
+    text: String,
+}
+
+impl Prometheus {
+    fn header(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.text, "# HELP {PREFIX}_{name} {help}");
+        let _ = writeln!(self.text, "# TYPE {PREFIX}_{name} {kind}");
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl std::fmt::Display) {
+        let _ = write!(self.text, "{PREFIX}_{name}");
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(key, value)| format!("{key}=\"{}\"", escape(value)))
+                .collect();
+            let _ = write!(self.text, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.text, " {value}");
+    }
+
+    fn histogram(&mut self, name: &str, label: (&str, &str), histogram: &Histogram) {
+        let bucket = format!("{name}_bucket");
+        for entry in &histogram.buckets {
+            let le = entry.le.to_string();
+            self.sample(&bucket, &[label, ("le", &le)], entry.count);
+        }
+        self.sample(&bucket, &[label, ("le", "+Inf")], histogram.count);
+        self.sample(&format!("{name}_sum"), &[label], histogram.sum_seconds);
+        self.sample(&format!("{name}_count"), &[label], histogram.count);
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_prometheus_text() {
+        let snapshot = MetricsSnapshot {
+            methods: vec![MethodMetrics {
+                method: "bottle.list".to_string(),
+                calls: 3,
+                errors: 1,
+                latency: Histogram {
+                    buckets: vec![
+                        Bucket {
+                            le: 0.005,
+                            count: 2,
+                        },
+                        Bucket { le: 1.0, count: 3 },
+                    ],
+                    count: 3,
+                    sum_seconds: 0.25,
+                },
+            }],
+            bottles_by_channel: BTreeMap::from([("custom \"x\"".to_string(), 2)]),
+            process_exits: BTreeMap::from([("signal".to_string(), 1)]),
+            ..Default::default()
+        };
+        let text = snapshot.to_prometheus();
+        for line in [
+            "# TYPE silicon_alloy_rpc_calls_total counter",
+            "silicon_alloy_rpc_calls_total{method=\"bottle.list\"} 3",
+            "silicon_alloy_rpc_errors_total{method=\"bottle.list\"} 1",
+            "silicon_alloy_rpc_duration_seconds_bucket{method=\"bottle.list\",le=\"0.005\"} 2",
+            "silicon_alloy_rpc_duration_seconds_bucket{method=\"bottle.list\",le=\"+Inf\"} 3",
+            "silicon_alloy_rpc_duration_seconds_sum{method=\"bottle.list\"} 0.25",
+            "silicon_alloy_bottles{channel=\"custom \\\"x\\\"\"} 2",
+            "silicon_alloy_process_exits_total{code=\"signal\"} 1",
+            "silicon_alloy_recipes_applied_total{outcome=\"failed\"} 0",
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "missing {line:?} in:\n{text}"
+            );
+        }
+    }
+}
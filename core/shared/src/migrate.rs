@@ -0,0 +1,168 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{Map, Value};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::BottleRecord;
+
+/// the `schema_version` this build writes into `bottle.json`.
+pub const SCHEMA_VERSION: u32 = 2;
+
+type Step = fn(&mut Map<String, Value>) -> Result<()>;
+
+/// `STEPS[n]` upgrades a version `n` record to version `n + 1`. a change to
+/// `BottleRecord` that older records can't be read as gets a step here and a
+/// bump of `SCHEMA_VERSION`.
+const STEPS: &[Step] = &[v0_to_v1, v1_to_v2];
+
+/// reads a `bottle.json` of any version this build knows, upgrading it one
+/// step at a time. the flag says whether anything was upgraded, so the
+/// caller can write the record back.
+pub(crate) fn upgrade(data: &[u8]) -> Result<(BottleRecord, bool)> {
+    let mut value: Value = serde_json::from_slice(data)?;
+    let record = value
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("bottle metadata is not an object"))?;
+    let version = match record.get("schema_version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| anyhow!("invalid schema_version {version}"))?,
+    };
+    if version > SCHEMA_VERSION {
+        bail!("bottle metadata has schema version {version}, newer than this build's {SCHEMA_VERSION}");
+    }
+    for (from, step) in (version..).zip(&STEPS[version as usize..]) {
+        step(record).with_context(|| format!("failed to upgrade schema version {from}"))?;
+        record.insert("schema_version".to_string(), (from + 1).into());
+    }
+    Ok((serde_json::from_value(value)?, version < SCHEMA_VERSION))
+}
+
+/// version 0 had no `schema_version`. bottles made by alloy-core carry
+/// `created_at` as rfc 3339 text rather than unix seconds, and the earliest
+/// records had no `environment`.
+fn v0_to_v1(record: &mut Map<String, Value>) -> Result<()> {
+    if let Some(Value::String(text)) = record.get("created_at") {
+        let created_at = OffsetDateTime::parse(text, &Rfc3339)
+            .with_context(|| format!("invalid created_at {text:?}"))?;
+        let seconds = u64::try_from(created_at.unix_timestamp()).unwrap_or_default();
+        record.insert("created_at".to_string(), seconds.into());
+    }
+    record
+        .entry("environment")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    Ok(())
+}
+
+/// version 2 added `notes` and `tags`. alloy-core records have had `notes`
+/// all along, and keep them.
+fn v1_to_v2(record: &mut Map<String, Value>) -> Result<()> {
+    record.entry("notes").or_insert(Value::Null);
+    record
+        .entry("tags")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BottleStore, BOTTLE_META};
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn v0() -> Value {
+        json!({
+            "id": "5f0c6f6e-2a41-4a8e-9c57-2f2f1b9e6d11",
+            "name": "steam",
+            "created_at": 1700000000,
+            "wine_runtime": {
+                "label": "wine 9.0",
+                "wine64_path": "/opt/wine/bin/wine64",
+                "version": "9.0"
+            },
+            "environment": [["DXVK_HUD", "1"]]
+        })
+    }
+
+    #[test]
+    fn v0_upgrades_to_current() {
+        let (record, upgraded) = upgrade(v0().to_string().as_bytes()).unwrap();
+        assert!(upgraded);
+        assert_eq!(record.schema_version, SCHEMA_VERSION);
+        assert_eq!(record.created_at, 1_700_000_000);
+        assert_eq!(record.environment, vec![("DXVK_HUD".into(), "1".into())]);
+    }
+
+    #[test]
+    fn v0_with_rfc3339_timestamps_upgrades_to_v1() {
+        let mut record = v0();
+        record["created_at"] = json!("2023-11-14T22:13:20Z");
+        record.as_object_mut().unwrap().remove("environment");
+        let (record, _) = upgrade(record.to_string().as_bytes()).unwrap();
+        assert_eq!(record.created_at, 1_700_000_000);
+        assert!(record.environment.is_empty());
+
+        let mut garbled = v0();
+        garbled["created_at"] = json!("last tuesday");
+        assert!(upgrade(garbled.to_string().as_bytes()).is_err());
+    }
+
+    #[test]
+    fn v1_upgrades_to_v2_keeping_alloy_core_notes() {
+        let mut record = v0();
+        record["schema_version"] = json!(1);
+        let (record_without, _) = upgrade(record.to_string().as_bytes()).unwrap();
+        assert_eq!(record_without.notes, None);
+        assert!(record_without.tags.is_empty());
+
+        record["notes"] = json!("from alloyctl");
+        let (record, upgraded) = upgrade(record.to_string().as_bytes()).unwrap();
+        assert!(upgraded);
+        assert_eq!(record.notes.as_deref(), Some("from alloyctl"));
+    }
+
+    #[test]
+    fn current_and_newer_versions() {
+        let mut record = v0();
+        record["schema_version"] = json!(SCHEMA_VERSION);
+        record["tags"] = json!([]);
+        let (_, upgraded) = upgrade(record.to_string().as_bytes()).unwrap();
+        assert!(!upgraded);
+        record["schema_version"] = json!(SCHEMA_VERSION + 1);
+        assert!(upgrade(record.to_string().as_bytes()).is_err());
+    }
+
+    #[tokio::test]
+    async fn old_records_are_written_back_upgraded() {
+        let root = std::env::temp_dir().join(format!("silicon-alloy-migrate-{}", Uuid::new_v4()));
+        let store = BottleStore::with_root(&root).unwrap();
+        let id: Uuid = v0()["id"].as_str().unwrap().parse().unwrap();
+        let dir = root.join(id.to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(BOTTLE_META), v0().to_string()).unwrap();
+
+        // a holder of the lock may be writing the record itself
+        let lock = store
+            .lock(id, crate::lock::LockMode::Exclusive, "bottle.update")
+            .unwrap();
+        assert_eq!(store.record(id).await.unwrap().schema_version, SCHEMA_VERSION);
+        let untouched: Value =
+            serde_json::from_slice(&std::fs::read(dir.join(BOTTLE_META)).unwrap()).unwrap();
+        assert_eq!(untouched, v0());
+        drop(lock);
+
+        assert_eq!(
+            store.list().await.unwrap()[0].schema_version,
+            SCHEMA_VERSION
+        );
+        let written: Value =
+            serde_json::from_slice(&std::fs::read(dir.join(BOTTLE_META)).unwrap()).unwrap();
+        assert_eq!(written["schema_version"], json!(SCHEMA_VERSION));
+        // nothing of the temporary file is left next to it
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
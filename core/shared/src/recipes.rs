@@ -68,6 +68,33 @@ pub fn find_recipe(dir: &Path, id: &str) -> Result<Recipe> {
         .ok_or_else(|| anyhow!("recipe {id} not found in {}", dir.display()))
 }
 
+/// loads every directory of a search path. when two directories hold a
+/// recipe with the same id, the one listed first wins.
+pub fn load_search_path(dirs: &[PathBuf]) -> Result<Vec<Recipe>> {
+    let mut recipes: Vec<Recipe> = Vec::new();
+    for dir in dirs {
+        for recipe in load_all(dir)? {
+            if !recipes.iter().any(|seen| seen.manifest.id == recipe.manifest.id) {
+                recipes.push(recipe);
+            }
+        }
+    }
+    recipes.sort_by(|a, b| a.manifest.name.cmp(&b.manifest.name));
+    Ok(recipes)
+}
+
+pub fn find_in_search_path(dirs: &[PathBuf], id: &str) -> Result<Recipe> {
+    for dir in dirs {
+        if let Some(recipe) = load_all(dir)?
+            .into_iter()
+            .find(|recipe| recipe.manifest.id == id)
+        {
+            return Ok(recipe);
+        }
+    }
+    Err(anyhow!("recipe {id} not found in any recipe path"))
+}
+
 pub fn load_recipe(path: &Path) -> Result<Recipe> {
     let data = fs::read_to_string(path)
         .with_context(|| format!("failed to read recipe manifest at {}", path.display()))?;
@@ -0,0 +1,326 @@
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use uuid::Uuid;
+
+use crate::access::current_uid;
+use crate::copy::Copier;
+use crate::lock::{BottleBusy, BottleLock, LockMode};
+use crate::{migrate, unix_timestamp, write_atomic, BottleRecord, BottleStore, BOTTLE_META};
+
+const SNAPSHOT_DIR: &str = "snapshots";
+const SNAPSHOT_META: &str = "snapshot.json";
+
+/// a point-in-time copy of a bottle's prefix and `bottle.json`, kept under
+/// `<bottle>/snapshots/<id>` so it goes away with the bottle.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SnapshotRecord {
+    pub id: Uuid,
+    pub bottle_id: Uuid,
+    pub label: String,
+    pub created_at: u64,
+    /// counts up per bottle, ordering snapshots taken within the same second.
+    #[serde(default)]
+    pub sequence: u64,
+}
+
+impl BottleStore {
+    /// copies the bottle as it is now. nothing may be running in it, or the
+    /// snapshot could catch the prefix halfway through a write.
+    pub async fn create_snapshot(&self, lock: &BottleLock, label: &str) -> Result<SnapshotRecord> {
+        let id = lock.covers(LockMode::Exclusive)?;
+        let bottle = self.record(id).await?;
+        let previous = self.list_snapshots(id).await?.pop();
+        let snapshot = SnapshotRecord {
+            id: Uuid::new_v4(),
+            bottle_id: id,
+            label: label.to_string(),
+            created_at: unix_timestamp(),
+            sequence: previous
+                .as_ref()
+                .map_or(0, |previous| previous.sequence + 1),
+        };
+        let dir = self.snapshot_dir(id, snapshot.id);
+        fs::create_dir_all(&dir)
+            .await
+            .context("failed to create snapshot directory")?;
+        let from = self.bottle_prefix(id);
+        let to = dir.join("prefix");
+        // files unchanged since the last snapshot can share its copy
+        let link_dest = previous.map(|previous| self.snapshot_dir(id, previous.id).join("prefix"));
+        let copied =
+            tokio::task::spawn_blocking(move || Copier::new(link_dest).copy_tree(&from, &to))
+                .await?;
+        if let Err(err) = copied {
+            let _ = fs::remove_dir_all(&dir).await;
+            return Err(err).with_context(|| format!("failed to snapshot bottle {id}"));
+        }
+        self.write_record(&dir, &bottle).await?;
+        // written last: a snapshot without it never finished and isn't listed
+        write_atomic(
+            &dir.join(SNAPSHOT_META),
+            &serde_json::to_vec_pretty(&snapshot)?,
+        )
+        .await?;
+        Ok(snapshot)
+    }
+
+    /// the bottle's snapshots, oldest first.
+    pub async fn list_snapshots(&self, id: Uuid) -> Result<Vec<SnapshotRecord>> {
+        let dir = self.root.join(id.to_string());
+        if !dir.exists() {
+            return Err(anyhow!("bottle {id} not found"));
+        }
+        let mut snapshots = Vec::new();
+        let mut entries = match fs::read_dir(dir.join(SNAPSHOT_DIR)).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(snapshots),
+            Err(err) => return Err(err.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let meta_path = entry.path().join(SNAPSHOT_META);
+            let Ok(data) = fs::read(&meta_path).await else {
+                continue;
+            };
+            match serde_json::from_slice::<SnapshotRecord>(&data) {
+                Ok(snapshot) => snapshots.push(snapshot),
+                Err(err) => tracing::warn!("ignored snapshot {:?}: {}", meta_path, err),
+            }
+        }
+        snapshots.sort_by_key(|snapshot| (snapshot.sequence, snapshot.created_at, snapshot.id));
+        Ok(snapshots)
+    }
+
+    pub async fn snapshot(&self, id: Uuid, snapshot: Uuid) -> Result<SnapshotRecord> {
+        let data = fs::read(self.snapshot_dir(id, snapshot).join(SNAPSHOT_META))
+            .await
+            .map_err(|_| anyhow!("bottle {id} has no snapshot {snapshot}"))?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// puts the prefix and metadata back the way they were when `snapshot`
+    /// was taken, except for the name: another bottle may have taken the old
+    /// one since. the snapshot itself stays, so it can be restored again. a
+    /// prefix adopted in place is restored where it really is, keeping the
+    /// bottle's link to it.
+    pub async fn restore_snapshot(&self, lock: &BottleLock, snapshot: Uuid) -> Result<BottleRecord> {
+        let id = lock.covers(LockMode::Exclusive)?;
+        self.snapshot(id, snapshot).await?;
+        let dir = self.snapshot_dir(id, snapshot);
+        let data = fs::read(dir.join(BOTTLE_META)).await?;
+        // a snapshot from before an upgrade is restored upgraded
+        let (mut record, _) = migrate::upgrade(&data)?;
+        record.name = self.record(id).await?.name;
+
+        let bottle_dir = self.root.join(id.to_string());
+        let prefix = match self.linked_prefix(id).await? {
+            Some(linked) => linked,
+            None => self.bottle_prefix(id),
+        };
+        // the lock only keeps out programs started through silicon-alloy
+        if wineserver_running(&prefix) {
+            let busy = BottleBusy {
+                bottle_id: id,
+                holders: Vec::new(),
+            };
+            return Err(anyhow::Error::new(busy).context(
+                "a wineserver is running in the bottle, stop its programs before restoring",
+            ));
+        }
+        // next to the prefix, so swapping them is a rename. for a linked
+        // prefix that's outside the bottle, and the id keeps these names
+        // from meeting anything else there
+        let beside = |suffix: &str| {
+            let mut path = prefix.clone().into_os_string();
+            path.push(format!(".{id}.{suffix}"));
+            PathBuf::from(path)
+        };
+        let staged = beside("restore");
+        let retired = beside("old");
+        for leftover in [&staged, &retired] {
+            if leftover.exists() {
+                fs::remove_dir_all(leftover).await?;
+            }
+        }
+        let from = dir.join("prefix");
+        let to = staged.clone();
+        // never hard links: the prefix is written to, the snapshot must not be
+        tokio::task::spawn_blocking(move || Copier::new(None).copy_tree(&from, &to))
+            .await?
+            .with_context(|| format!("failed to copy snapshot {snapshot}"))?;
+        // swap the whole tree at once, so a failure leaves one prefix or the other
+        fs::rename(&prefix, &retired).await?;
+        if let Err(err) = fs::rename(&staged, &prefix).await {
+            // put the old prefix back rather than leave the bottle without one
+            fs::rename(&retired, &prefix).await?;
+            let _ = fs::remove_dir_all(&staged).await;
+            return Err(err).with_context(|| format!("failed to restore snapshot {snapshot}"));
+        }
+        fs::remove_dir_all(&retired).await?;
+        self.write_record(&bottle_dir, &record).await?;
+        Ok(record)
+    }
+
+    /// needs the bottle only shared: that keeps the snapshot from vanishing
+    /// under a restore reading from it.
+    pub async fn remove_snapshot(&self, lock: &BottleLock, snapshot: Uuid) -> Result<()> {
+        let id = lock.covers(LockMode::Shared)?;
+        self.snapshot(id, snapshot).await?;
+        fs::remove_dir_all(self.snapshot_dir(id, snapshot))
+            .await
+            .with_context(|| format!("failed to remove snapshot {snapshot}"))
+    }
+
+    fn snapshot_dir(&self, id: Uuid, snapshot: Uuid) -> PathBuf {
+        self.root
+            .join(id.to_string())
+            .join(SNAPSHOT_DIR)
+            .join(snapshot.to_string())
+    }
+}
+
+/// whether a wineserver is up for `prefix`, which means wine programs run
+/// there, whoever started them. wine names the server's directory after the
+/// prefix's device and inode, under `.wine-<uid>` in /tmp or, on macos, in
+/// the per-user temp dir. a socket nobody answers is left from a dead server.
+pub fn wineserver_running(prefix: &Path) -> bool {
+    let Ok(metadata) = std::fs::metadata(prefix) else {
+        return false;
+    };
+    let server = format!("server-{:x}-{:x}", metadata.dev(), metadata.ino());
+    let mut roots = vec![PathBuf::from("/tmp")];
+    if cfg!(target_os = "macos") {
+        roots.push(std::env::temp_dir());
+    }
+    roots.iter().any(|root| {
+        let socket = root
+            .join(format!(".wine-{}", current_uid()))
+            .join(&server)
+            .join("socket");
+        UnixStream::connect(socket).is_ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WineRuntime;
+    use std::fs as stdfs;
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn snapshots_round_trip() {
+        let root = std::env::temp_dir().join(format!("silicon-alloy-snapshot-{}", Uuid::new_v4()));
+        let store = BottleStore::with_root(&root).unwrap();
+        let runtime = WineRuntime {
+            label: "wine".to_string(),
+            wine64_path: PathBuf::from("/opt/wine64"),
+            version: "9.0".to_string(),
+            channel: None,
+        };
+        let bottle = store.create("snap", runtime).await.unwrap();
+        let prefix = store.bottle_prefix(bottle.id);
+        stdfs::create_dir_all(prefix.join("drive_c")).unwrap();
+        stdfs::write(prefix.join("system.reg"), "before").unwrap();
+        stdfs::write(prefix.join("drive_c/app.exe"), "binary").unwrap();
+        std::os::unix::fs::symlink("/", prefix.join("z:")).unwrap();
+
+        let lock = |mode| store.lock(bottle.id, mode, "test").unwrap();
+        let first = store
+            .create_snapshot(&lock(LockMode::Exclusive), "clean")
+            .await
+            .unwrap();
+        let second = store
+            .create_snapshot(&lock(LockMode::Exclusive), "again")
+            .await
+            .unwrap();
+        let listed = store.list_snapshots(bottle.id).await.unwrap();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].label, "clean");
+        let copy = |snapshot: Uuid| store.snapshot_dir(bottle.id, snapshot).join("prefix");
+        assert_eq!(
+            stdfs::read_link(copy(first.id).join("z:")).unwrap(),
+            Path::new("/")
+        );
+
+        stdfs::write(prefix.join("system.reg"), "after").unwrap();
+        stdfs::remove_file(prefix.join("drive_c/app.exe")).unwrap();
+        let mut renamed = bottle.clone();
+        renamed.name = "renamed".to_string();
+        store.update_record(bottle.id, &renamed).await.unwrap();
+        // the old name is free for another bottle, and stays with it
+        let other = store
+            .create("snap", bottle.wine_runtime.clone())
+            .await
+            .unwrap();
+
+        let restored = store
+            .restore_snapshot(&lock(LockMode::Exclusive), first.id)
+            .await
+            .unwrap();
+        assert_eq!(restored.name, "renamed");
+        assert_eq!(store.record(bottle.id).await.unwrap().name, "renamed");
+        assert_eq!(store.resolve("snap").await.unwrap().id, other.id);
+        assert_eq!(
+            stdfs::read_to_string(prefix.join("system.reg")).unwrap(),
+            "before"
+        );
+        assert!(prefix.join("drive_c/app.exe").exists());
+        // the restored prefix is a copy; writing to it leaves the snapshot be
+        stdfs::write(prefix.join("system.reg"), "later").unwrap();
+        assert_eq!(
+            stdfs::read_to_string(copy(first.id).join("system.reg")).unwrap(),
+            "before"
+        );
+
+        let running = lock(LockMode::Shared);
+        assert!(store.restore_snapshot(&running, second.id).await.is_err());
+        store.remove_snapshot(&running, second.id).await.unwrap();
+        drop(running);
+        assert_eq!(store.list_snapshots(bottle.id).await.unwrap().len(), 1);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn restores_refuse_a_running_wineserver() {
+        let root = std::env::temp_dir().join(format!("silicon-alloy-snapshot-{}", Uuid::new_v4()));
+        let store = BottleStore::with_root(&root).unwrap();
+        let runtime = WineRuntime {
+            label: "wine".to_string(),
+            wine64_path: PathBuf::from("/opt/wine64"),
+            version: "9.0".to_string(),
+            channel: None,
+        };
+        let bottle = store.create("busy", runtime).await.unwrap();
+        let prefix = store.bottle_prefix(bottle.id);
+        let lock = store.lock(bottle.id, LockMode::Exclusive, "test").unwrap();
+        let snapshot = store.create_snapshot(&lock, "clean").await.unwrap();
+
+        // a wineserver started behind the daemon's back
+        let metadata = stdfs::metadata(&prefix).unwrap();
+        let server = PathBuf::from("/tmp")
+            .join(format!(".wine-{}", current_uid()))
+            .join(format!("server-{:x}-{:x}", metadata.dev(), metadata.ino()));
+        stdfs::create_dir_all(&server).unwrap();
+        let listener = std::os::unix::net::UnixListener::bind(server.join("socket")).unwrap();
+        assert!(wineserver_running(&prefix));
+        let err = store
+            .restore_snapshot(&lock, snapshot.id)
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<BottleBusy>().is_some(), "{err:#}");
+
+        // once it's gone only its socket is left, which doesn't count
+        drop(listener);
+        assert!(!wineserver_running(&prefix));
+        store.restore_snapshot(&lock, snapshot.id).await.unwrap();
+        stdfs::remove_dir_all(&server).unwrap();
+        stdfs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,287 @@
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use uuid::Uuid;
+
+use crate::lock::{BottleLock, LockMode};
+use crate::usage::dir_size;
+use crate::{unix_timestamp, write_atomic, BottleRecord, BottleStore, BOTTLE_META};
+
+/// deleted bottles, kept under the bottle root so moving one there is a
+/// rename rather than a copy.
+const TRASH_DIR: &str = ".trash";
+/// written into a bottle as it is trashed, and removed when it comes back.
+const TRASH_META: &str = "trash.json";
+/// a trashed bottle being purged is renamed to this first, so a restore
+/// can't pick up a half-removed tree.
+const PURGING_SUFFIX: &str = ".purging";
+
+/// a bottle in the trash, as `trash.list` shows it.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TrashedBottle {
+    #[serde(flatten)]
+    pub bottle: BottleRecord,
+    /// unix seconds
+    pub deleted_at: u64,
+    /// bytes the bottle takes up on disk, snapshots included
+    pub size: u64,
+}
+
+/// the part of a `TrashedBottle` that isn't in `bottle.json`.
+#[derive(Debug, Serialize, Deserialize)]
+struct Deletion {
+    deleted_at: u64,
+    size: u64,
+}
+
+/// how much of the trash is kept. `None` keeps everything by that measure.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Retention {
+    pub max_age: Option<Duration>,
+    pub max_bytes: Option<u64>,
+}
+
+impl BottleStore {
+    /// moves the bottle into the trash, from where it can be restored until
+    /// it is purged.
+    pub async fn trash(&self, lock: &BottleLock) -> Result<TrashedBottle> {
+        let id = lock.covers(LockMode::Exclusive)?;
+        let bottle = self.record(id).await?;
+        let dir = self.root.join(id.to_string());
+        let measured = dir.clone();
+        let size = tokio::task::spawn_blocking(move || dir_size(&measured))
+            .await?
+            .with_context(|| format!("failed to measure bottle {id}"))?;
+        let deletion = Deletion {
+            deleted_at: unix_timestamp(),
+            size,
+        };
+        fs::create_dir_all(self.trash_root())
+            .await
+            .context("failed to create trash directory")?;
+        write_atomic(
+            &dir.join(TRASH_META),
+            &serde_json::to_vec_pretty(&deletion)?,
+        )
+        .await?;
+        if let Err(err) = fs::rename(&dir, self.trash_dir(id)).await {
+            let _ = fs::remove_file(dir.join(TRASH_META)).await;
+            return Err(err).with_context(|| format!("failed to move bottle {id} to the trash"));
+        }
+        // anyone still waiting on the old file finds the bottle gone
+        let _ = fs::remove_file(self.lock_path(id)).await;
+        Ok(TrashedBottle {
+            bottle,
+            deleted_at: deletion.deleted_at,
+            size: deletion.size,
+        })
+    }
+
+    /// the trashed bottles, longest deleted first.
+    pub async fn list_trash(&self) -> Result<Vec<TrashedBottle>> {
+        let mut trashed = Vec::new();
+        let mut entries = match fs::read_dir(self.trash_root()).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(trashed),
+            Err(err) => return Err(err.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let Ok(id) = entry.file_name().to_string_lossy().parse::<Uuid>() else {
+                continue;
+            };
+            match self.trashed(id).await {
+                Ok(bottle) => trashed.push(bottle),
+                Err(err) => tracing::warn!("ignored trashed bottle {id}: {err:#}"),
+            }
+        }
+        trashed.sort_by_key(|trashed| (trashed.deleted_at, trashed.bottle.id));
+        Ok(trashed)
+    }
+
+    pub async fn trashed(&self, id: Uuid) -> Result<TrashedBottle> {
+        let dir = self.trash_dir(id);
+        let data = fs::read(dir.join(TRASH_META))
+            .await
+            .map_err(|_| anyhow!("bottle {id} is not in the trash"))?;
+        let deletion: Deletion = serde_json::from_slice(&data)?;
+        let data = fs::read(dir.join(BOTTLE_META)).await?;
+        Ok(TrashedBottle {
+            bottle: self.load_record(&dir, &data).await?,
+            deleted_at: deletion.deleted_at,
+            size: deletion.size,
+        })
+    }
+
+    /// moves a trashed bottle back, as `name` if given. like a new bottle it
+    /// can't take a name another bottle has meanwhile.
+    pub async fn restore_trashed(&self, id: Uuid, name: Option<&str>) -> Result<BottleRecord> {
+        let _names = self.lock_names().await?;
+        let mut record = self.trashed(id).await?.bottle;
+        if let Some(name) = name {
+            record.name = name.trim().to_string();
+        }
+        self.check_name(&record.name, id).await?;
+        let dir = self.trash_dir(id);
+        let to = self.root.join(id.to_string());
+        if to.exists() {
+            bail!("bottle {id} already exists");
+        }
+        if name.is_some() {
+            self.write_record(&dir, &record).await?;
+        }
+        fs::rename(&dir, &to)
+            .await
+            .with_context(|| format!("failed to restore bottle {id}"))?;
+        let _ = fs::remove_file(to.join(TRASH_META)).await;
+        Ok(record)
+    }
+
+    /// removes a trashed bottle for good.
+    pub async fn purge_trashed(&self, id: Uuid) -> Result<()> {
+        let doomed = self.trash_root().join(format!("{id}{PURGING_SUFFIX}"));
+        match fs::rename(self.trash_dir(id), &doomed).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                bail!("bottle {id} is not in the trash")
+            }
+            Err(err) => return Err(err).with_context(|| format!("failed to purge bottle {id}")),
+        }
+        fs::remove_dir_all(&doomed)
+            .await
+            .with_context(|| format!("failed to purge bottle {id}"))
+    }
+
+    /// purges every trashed bottle, returning their ids.
+    pub async fn empty_trash(&self) -> Result<Vec<Uuid>> {
+        let mut purged = Vec::new();
+        for trashed in self.list_trash().await? {
+            self.purge_trashed(trashed.bottle.id).await?;
+            purged.push(trashed.bottle.id);
+        }
+        Ok(purged)
+    }
+
+    /// purges what `retention` no longer keeps: bottles deleted longer ago
+    /// than `max_age`, then the longest deleted until the rest fits in
+    /// `max_bytes`. purges interrupted earlier are finished too.
+    pub async fn enforce_retention(&self, retention: Retention) -> Result<Vec<Uuid>> {
+        self.finish_purges().await?;
+        let trashed = self.list_trash().await?;
+        let now = unix_timestamp();
+        let mut total: u64 = trashed.iter().map(|trashed| trashed.size).sum();
+        let mut purged = Vec::new();
+        for trashed in trashed {
+            let expired = retention
+                .max_age
+                .is_some_and(|max_age| now.saturating_sub(trashed.deleted_at) > max_age.as_secs());
+            let over = retention
+                .max_bytes
+                .is_some_and(|max_bytes| total > max_bytes);
+            if !expired && !over {
+                continue;
+            }
+            self.purge_trashed(trashed.bottle.id).await?;
+            total -= trashed.size;
+            purged.push(trashed.bottle.id);
+        }
+        Ok(purged)
+    }
+
+    async fn finish_purges(&self) -> Result<()> {
+        let mut entries = match fs::read_dir(self.trash_root()).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            if entry
+                .file_name()
+                .to_string_lossy()
+                .ends_with(PURGING_SUFFIX)
+            {
+                fs::remove_dir_all(entry.path()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn trash_root(&self) -> PathBuf {
+        self.root.join(TRASH_DIR)
+    }
+
+    fn trash_dir(&self, id: Uuid) -> PathBuf {
+        self.trash_root().join(id.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WineRuntime;
+
+    #[tokio::test]
+    async fn trashed_bottles_restore_or_expire() {
+        let root = std::env::temp_dir().join(format!("silicon-alloy-trash-{}", Uuid::new_v4()));
+        let store = BottleStore::with_root(&root).unwrap();
+        let runtime = WineRuntime {
+            label: "wine".to_string(),
+            wine64_path: PathBuf::from("/opt/wine64"),
+            version: "9.0".to_string(),
+            channel: None,
+        };
+        let bottle = store.create("steam", runtime.clone()).await.unwrap();
+        let prefix = store.bottle_prefix(bottle.id);
+        std::fs::write(prefix.join("system.reg"), "0123456789").unwrap();
+        std::fs::hard_link(prefix.join("system.reg"), prefix.join("user.reg")).unwrap();
+        let record = root.join(bottle.id.to_string()).join(BOTTLE_META);
+        let size = std::fs::metadata(record).unwrap().len() + 10;
+
+        let lock = store
+            .lock(bottle.id, LockMode::Exclusive, "bottle.delete")
+            .unwrap();
+        let trashed = store.trash(&lock).await.unwrap();
+        drop(lock);
+        assert_eq!(trashed.size, size);
+        assert!(store.list().await.unwrap().is_empty());
+        assert!(store.resolve("steam").await.is_err());
+        assert_eq!(store.list_trash().await.unwrap().len(), 1);
+
+        // the name is free while the bottle is in the trash
+        store.create("steam", runtime.clone()).await.unwrap();
+        assert!(store.restore_trashed(bottle.id, None).await.is_err());
+        let restored = store
+            .restore_trashed(bottle.id, Some("steam (old)"))
+            .await
+            .unwrap();
+        assert_eq!(restored.name, "steam (old)");
+        assert_eq!(store.resolve("steam (old)").await.unwrap().id, bottle.id);
+        assert!(store.list_trash().await.unwrap().is_empty());
+        assert!(!root.join(bottle.id.to_string()).join(TRASH_META).exists());
+
+        for bottle in store.list().await.unwrap() {
+            let lock = store
+                .lock(bottle.id, LockMode::Exclusive, "bottle.delete")
+                .unwrap();
+            store.trash(&lock).await.unwrap();
+        }
+        let keep_all = Retention::default();
+        assert!(store.enforce_retention(keep_all).await.unwrap().is_empty());
+        // the oldest goes first until the rest fits
+        let fits_one = Retention {
+            max_age: None,
+            max_bytes: Some(size * 3 / 2),
+        };
+        let purged = store.enforce_retention(fits_one).await.unwrap();
+        assert_eq!(purged.len(), 1);
+        assert_eq!(store.list_trash().await.unwrap().len(), 1);
+        assert!(store.purge_trashed(purged[0]).await.is_err());
+        assert_eq!(store.empty_trash().await.unwrap().len(), 1);
+        assert!(store.list_trash().await.unwrap().is_empty());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
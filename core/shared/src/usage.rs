@@ -0,0 +1,533 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs as stdfs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use uuid::Uuid;
+
+use crate::lock::{BottleLock, LockMode};
+use crate::{unix_timestamp, write_atomic, BottleStore, DAEMON_LOG};
+
+/// what the last measurement found, kept next to `bottle.json`.
+const USAGE_CACHE: &str = "usage.json";
+/// a directory changed this recently may change again within the same
+/// mtime tick, so it isn't trusted to the cache.
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+/// directories wine and gpu drivers keep compiled shaders in, wherever they
+/// turn up in the prefix.
+const SHADER_CACHE_DIRS: &[&str] = &["d3dscache", "dxcache", "glcache", "mesa_shader_cache"];
+
+/// what the space in a bottle is taken up by.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageCategory {
+    /// `drive_c/Program Files` and `drive_c/Program Files (x86)`
+    ProgramFiles,
+    /// the temp directories of `drive_c/windows` and each user
+    Temp,
+    /// `drive_c/windows/Installer`, where msi packages are cached
+    InstallerCache,
+    /// dxvk and vkd3d-proton caches and the shader caches of gpu drivers
+    ShaderCache,
+    /// `*.log` files
+    Logs,
+    Snapshots,
+    Other,
+}
+
+impl UsageCategory {
+    /// whether `bottle.clean` may remove it. everything else is part of
+    /// what's installed.
+    pub fn cleanable(self) -> bool {
+        matches!(
+            self,
+            Self::Temp | Self::InstallerCache | Self::ShaderCache | Self::Logs
+        )
+    }
+}
+
+impl fmt::Display for UsageCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = serde_json::to_value(self).map_err(|_| fmt::Error)?;
+        f.write_str(name.as_str().unwrap_or_default())
+    }
+}
+
+/// a bottle's disk usage as `bottle.usage` reports it.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BottleUsage {
+    pub bottle_id: Uuid,
+    pub name: String,
+    /// bytes, over all categories
+    pub total: u64,
+    /// bytes per category; categories taking no space are left out
+    pub categories: BTreeMap<UsageCategory, u64>,
+    /// unix seconds
+    pub measured_at: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UsageCache {
+    /// keyed by path relative to the prefix, `""` for the prefix itself
+    dirs: BTreeMap<String, CachedDir>,
+    #[serde(default)]
+    snapshots: Option<CachedSnapshots>,
+}
+
+/// a directory as it was when last read. while its mtime stays the same no
+/// entry was added, removed or renamed in it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedDir {
+    modified_ns: u64,
+    /// bytes of the files directly in it, per category
+    files: BTreeMap<UsageCategory, u64>,
+    subdirs: Vec<String>,
+}
+
+/// snapshots are never changed once taken, so the same set of them always
+/// takes the same space.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedSnapshots {
+    ids: Vec<String>,
+    size: u64,
+}
+
+impl BottleStore {
+    /// measures the bottle. directories unchanged since the last measurement
+    /// are taken from the cache; `refresh` reads everything again, which also
+    /// catches files that grew in place.
+    pub async fn usage(&self, id: Uuid, refresh: bool) -> Result<BottleUsage> {
+        let record = self.record(id).await?;
+        let dir = self.root.join(id.to_string());
+        let cache_path = dir.join(USAGE_CACHE);
+        let cache = match fs::read(&cache_path).await {
+            Ok(data) if !refresh => serde_json::from_slice(&data).unwrap_or_default(),
+            _ => UsageCache::default(),
+        };
+        let (categories, cache) =
+            tokio::task::spawn_blocking(move || measure(&dir, cache)).await??;
+        // a bottle deleted meanwhile has nowhere to keep it, and that's fine
+        if let Err(err) = write_atomic(&cache_path, &serde_json::to_vec(&cache)?).await {
+            tracing::debug!("unable to cache the usage of bottle {id}: {err:#}");
+        }
+        Ok(BottleUsage {
+            bottle_id: id,
+            name: record.name,
+            total: categories.values().sum(),
+            categories,
+            measured_at: unix_timestamp(),
+        })
+    }
+
+    /// removes the files in `categories` from the bottle's prefix and returns
+    /// the bytes freed per category. the directories holding them stay.
+    pub async fn clean_bottle(
+        &self,
+        lock: &BottleLock,
+        categories: &[UsageCategory],
+    ) -> Result<BTreeMap<UsageCategory, u64>> {
+        let id = lock.covers(LockMode::Exclusive)?;
+        if categories.is_empty() {
+            bail!("no categories to clean");
+        }
+        if let Some(category) = categories.iter().find(|category| !category.cleanable()) {
+            bail!("{category} can't be cleaned");
+        }
+        let prefix = self.bottle_prefix(id);
+        if !prefix.exists() {
+            return Err(anyhow!("bottle {id} not found"));
+        }
+        let categories = categories.to_vec();
+        tokio::task::spawn_blocking(move || {
+            let mut reclaimed = BTreeMap::new();
+            clean_dir(&prefix, Path::new(""), &categories, &mut reclaimed)?;
+            Ok(reclaimed)
+        })
+        .await?
+    }
+}
+
+/// bytes the daemon's own logs take up in `log_dir`. they belong to no
+/// bottle, so they are reported apart from the bottles.
+pub async fn log_usage(log_dir: &Path) -> Result<u64> {
+    let log_dir = log_dir.to_path_buf();
+    let size = tokio::task::spawn_blocking(move || match dir_size(&log_dir) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(0),
+        size => size,
+    })
+    .await??;
+    Ok(size)
+}
+
+/// removes the daemon logs in `log_dir` it has rolled over from, keeping
+/// the newest one it is still writing to, and returns the bytes freed.
+pub async fn clean_logs(log_dir: &Path) -> Result<u64> {
+    let mut logs = Vec::new();
+    let mut entries = match fs::read_dir(log_dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = fs::symlink_metadata(entry.path()).await?;
+        if metadata.is_file() && entry.file_name().to_string_lossy().starts_with(DAEMON_LOG) {
+            logs.push((metadata.modified()?, metadata.len(), entry.path()));
+        }
+    }
+    logs.sort();
+    logs.pop();
+    let mut reclaimed = 0;
+    for (_, size, path) in logs {
+        fs::remove_file(&path).await?;
+        reclaimed += size;
+    }
+    Ok(reclaimed)
+}
+
+/// which category the file or directory at `relative`, a path inside the
+/// prefix, counts towards. the directories named by a category, such as a
+/// temp directory itself, don't belong to it: only what's inside does.
+pub(crate) fn classify(relative: &Path) -> UsageCategory {
+    let parts: Vec<String> = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy().to_lowercase())
+        .collect();
+    let parts: Vec<&str> = parts.iter().map(String::as_str).collect();
+    match parts.as_slice() {
+        ["drive_c", "windows", "temp", _, ..]
+        | ["drive_c", "users", _, "temp", _, ..]
+        | ["drive_c", "users", _, "appdata", "local", "temp", _, ..]
+        | ["drive_c", "users", _, "local settings", "temp", _, ..] => return UsageCategory::Temp,
+        ["drive_c", "windows", "installer", _, ..] => return UsageCategory::InstallerCache,
+        _ => {}
+    }
+    let Some((name, parents)) = parts.split_last() else {
+        return UsageCategory::Other;
+    };
+    if parents
+        .iter()
+        .any(|parent| SHADER_CACHE_DIRS.contains(parent))
+        || name.ends_with(".dxvk-cache")
+        || name.starts_with("vkd3d-proton.cache")
+    {
+        return UsageCategory::ShaderCache;
+    }
+    if name.ends_with(".log") {
+        return UsageCategory::Logs;
+    }
+    match parts.as_slice() {
+        ["drive_c", "program files" | "program files (x86)", _, ..] => UsageCategory::ProgramFiles,
+        _ => UsageCategory::Other,
+    }
+}
+
+/// walks the bottle's prefix, reading only the directories that changed
+/// since `cache` was made, and sizes its snapshots. returns the usage and
+/// the cache to keep for next time.
+fn measure(
+    bottle_dir: &Path,
+    cache: UsageCache,
+) -> io::Result<(BTreeMap<UsageCategory, u64>, UsageCache)> {
+    let prefix = bottle_dir.join("prefix");
+    let settled = SystemTime::now() - SETTLE_TIME;
+    let mut usage = BTreeMap::new();
+    let mut next = UsageCache::default();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        let dir = prefix.join(&relative);
+        // followed: the prefix of an adopted bottle may be a link to it
+        let metadata = match dir.metadata() {
+            Ok(metadata) => metadata,
+            // gone since its parent was read
+            Err(err)
+                if err.kind() == io::ErrorKind::NotFound && !relative.as_os_str().is_empty() =>
+            {
+                continue
+            }
+            Err(err) => return Err(err),
+        };
+        let modified = metadata.modified()?;
+        let modified_ns = modified
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos() as u64);
+        let key = relative.to_string_lossy().into_owned();
+        let cached = cache
+            .dirs
+            .get(&key)
+            .filter(|cached| cached.modified_ns == modified_ns);
+        let (entry, cacheable) = match cached {
+            Some(cached) => {
+                pending.extend(cached.subdirs.iter().map(|name| relative.join(name)));
+                (cached.clone(), true)
+            }
+            None => read_dir(&dir, &relative, modified_ns, &mut pending)?,
+        };
+        for (category, size) in &entry.files {
+            *usage.entry(*category).or_default() += size;
+        }
+        if cacheable && modified < settled {
+            next.dirs.insert(key, entry);
+        }
+    }
+    let snapshots = measure_snapshots(bottle_dir, cache.snapshots)?;
+    usage.insert(UsageCategory::Snapshots, snapshots.size);
+    next.snapshots = Some(snapshots);
+    usage.retain(|_, size| *size > 0);
+    Ok((usage, next))
+}
+
+/// sizes the files directly in `dir` and queues its subdirectories. the
+/// flag is false when a subdirectory's name isn't utf-8 and so can't be
+/// kept in the cache.
+fn read_dir(
+    dir: &Path,
+    relative: &Path,
+    modified_ns: u64,
+    pending: &mut Vec<PathBuf>,
+) -> io::Result<(CachedDir, bool)> {
+    let mut files = BTreeMap::new();
+    let mut subdirs = Vec::new();
+    let mut cacheable = true;
+    for entry in stdfs::read_dir(dir)? {
+        let entry = entry?;
+        let relative = relative.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            match entry.file_name().into_string() {
+                Ok(name) => subdirs.push(name),
+                Err(_) => cacheable = false,
+            }
+            pending.push(relative);
+            continue;
+        }
+        let size = entry.path().symlink_metadata()?.len();
+        *files.entry(classify(&relative)).or_default() += size;
+    }
+    let entry = CachedDir {
+        modified_ns,
+        files,
+        subdirs,
+    };
+    Ok((entry, cacheable))
+}
+
+fn measure_snapshots(
+    bottle_dir: &Path,
+    cached: Option<CachedSnapshots>,
+) -> io::Result<CachedSnapshots> {
+    let dir = bottle_dir.join("snapshots");
+    let mut ids = match stdfs::read_dir(&dir) {
+        Ok(entries) => entries
+            .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+            .collect::<io::Result<Vec<_>>>()?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err),
+    };
+    ids.sort();
+    if let Some(cached) = cached.filter(|cached| cached.ids == ids) {
+        return Ok(cached);
+    }
+    let size = if ids.is_empty() { 0 } else { dir_size(&dir)? };
+    Ok(CachedSnapshots { ids, size })
+}
+
+/// removes what's in `categories` under `relative`, adding up what it took.
+/// directories go once emptied, unless they hold other files.
+fn clean_dir(
+    prefix: &Path,
+    relative: &Path,
+    categories: &[UsageCategory],
+    reclaimed: &mut BTreeMap<UsageCategory, u64>,
+) -> io::Result<()> {
+    for entry in stdfs::read_dir(prefix.join(relative))? {
+        let entry = entry?;
+        let relative = relative.join(entry.file_name());
+        let category = classify(&relative);
+        // never followed: a symlink to a directory is removed like a file
+        if entry.file_type()?.is_dir() {
+            clean_dir(prefix, &relative, categories, reclaimed)?;
+            if categories.contains(&category) {
+                let _ = stdfs::remove_dir(entry.path());
+            }
+        } else if categories.contains(&category) {
+            let size = entry.path().symlink_metadata()?.len();
+            stdfs::remove_file(entry.path())?;
+            *reclaimed.entry(category).or_default() += size;
+        }
+    }
+    Ok(())
+}
+
+/// bytes taken by the files under `path`, without following symlinks. a
+/// file hard linked more than once, as snapshots do, counts once.
+pub(crate) fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut seen = HashSet::new();
+    let mut pending = vec![path.to_path_buf()];
+    let mut size = 0;
+    while let Some(dir) = pending.pop() {
+        for entry in stdfs::read_dir(&dir)? {
+            let entry = entry?;
+            let metadata = entry.path().symlink_metadata()?;
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else if metadata.nlink() < 2 || seen.insert((metadata.dev(), metadata.ino())) {
+                size += metadata.len();
+            }
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WineRuntime;
+    use std::fs::File;
+
+    #[test]
+    fn paths_fall_into_categories() {
+        let cases = [
+            (
+                "drive_c/Program Files/Steam/steam.exe",
+                UsageCategory::ProgramFiles,
+            ),
+            (
+                "drive_c/Program Files/Steam/logs/bootstrap.log",
+                UsageCategory::Logs,
+            ),
+            (
+                "drive_c/Program Files/game/game.dxvk-cache",
+                UsageCategory::ShaderCache,
+            ),
+            (
+                "drive_c/users/steamuser/Temp/setup.tmp",
+                UsageCategory::Temp,
+            ),
+            (
+                "drive_c/users/steamuser/AppData/Local/Temp/a/b.msi",
+                UsageCategory::Temp,
+            ),
+            (
+                "drive_c/users/steamuser/AppData/Local/D3DSCache/x",
+                UsageCategory::ShaderCache,
+            ),
+            (
+                "drive_c/windows/Installer/1a2b.msi",
+                UsageCategory::InstallerCache,
+            ),
+            ("drive_c/windows/temp/dd_vcredist.log", UsageCategory::Temp),
+            // the directories themselves are kept by a clean
+            ("drive_c/windows/Installer", UsageCategory::Other),
+            ("drive_c/users/steamuser/Temp", UsageCategory::Other),
+            ("system.reg", UsageCategory::Other),
+        ];
+        for (path, category) in cases {
+            assert_eq!(classify(Path::new(path)), category, "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn usage_is_cached_and_cleaned() {
+        let root = std::env::temp_dir().join(format!("silicon-alloy-usage-{}", Uuid::new_v4()));
+        let store = BottleStore::with_root(&root).unwrap();
+        let runtime = WineRuntime {
+            label: "wine".to_string(),
+            wine64_path: PathBuf::from("/opt/wine64"),
+            version: "9.0".to_string(),
+            channel: None,
+        };
+        let bottle = store.create("usage", runtime).await.unwrap();
+        let prefix = store.bottle_prefix(bottle.id);
+        let write = |path: &str, size: usize| {
+            let path = prefix.join(path);
+            stdfs::create_dir_all(path.parent().unwrap()).unwrap();
+            stdfs::write(path, vec![0; size]).unwrap();
+        };
+        write("drive_c/Program Files/app/app.exe", 1000);
+        write("drive_c/users/me/Temp/setup/setup.exe", 300);
+        write("drive_c/windows/Installer/cache.msi", 200);
+        write("drive_c/app.log", 10);
+        // everything is old enough to be cached
+        let old = SystemTime::now() - Duration::from_secs(60);
+        let mut dirs = vec![prefix.clone()];
+        while let Some(dir) = dirs.pop() {
+            for entry in stdfs::read_dir(&dir).unwrap() {
+                let entry = entry.unwrap();
+                if entry.file_type().unwrap().is_dir() {
+                    dirs.push(entry.path());
+                }
+            }
+            File::open(&dir).unwrap().set_modified(old).unwrap();
+        }
+
+        let usage = store.usage(bottle.id, false).await.unwrap();
+        assert_eq!(usage.categories[&UsageCategory::ProgramFiles], 1000);
+        assert_eq!(usage.categories[&UsageCategory::Temp], 300);
+        assert_eq!(usage.total, 1510);
+        // a file growing in place leaves its directory's mtime alone, so
+        // only a refresh sees it
+        let exe = prefix.join("drive_c/Program Files/app/app.exe");
+        stdfs::write(&exe, vec![0; 2000]).unwrap();
+        let cached = store.usage(bottle.id, false).await.unwrap();
+        assert_eq!(cached.categories[&UsageCategory::ProgramFiles], 1000);
+        let refreshed = store.usage(bottle.id, true).await.unwrap();
+        assert_eq!(refreshed.categories[&UsageCategory::ProgramFiles], 2000);
+
+        let lock = store
+            .lock(bottle.id, LockMode::Exclusive, "bottle.clean")
+            .unwrap();
+        assert!(store
+            .clean_bottle(&lock, &[UsageCategory::ProgramFiles])
+            .await
+            .is_err());
+        let reclaimed = store
+            .clean_bottle(&lock, &[UsageCategory::Temp, UsageCategory::Logs])
+            .await
+            .unwrap();
+        assert_eq!(reclaimed[&UsageCategory::Temp], 300);
+        assert_eq!(reclaimed[&UsageCategory::Logs], 10);
+        assert!(prefix.join("drive_c/users/me/Temp").exists());
+        assert!(!prefix.join("drive_c/users/me/Temp/setup").exists());
+        // the directories that lost files are read again without a refresh
+        let usage = store.usage(bottle.id, false).await.unwrap();
+        assert!(!usage.categories.contains_key(&UsageCategory::Temp));
+        assert!(!usage.categories.contains_key(&UsageCategory::Logs));
+        assert_eq!(usage.categories[&UsageCategory::InstallerCache], 200);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rolled_daemon_logs_are_cleaned() {
+        let logs = std::env::temp_dir().join(format!("silicon-alloy-logs-{}", Uuid::new_v4()));
+        assert_eq!(log_usage(&logs).await.unwrap(), 0);
+        assert_eq!(clean_logs(&logs).await.unwrap(), 0);
+        stdfs::create_dir_all(&logs).unwrap();
+        let old = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+        let rolled = logs.join("daemon.log.2024-01-01");
+        stdfs::write(&rolled, vec![0; 100]).unwrap();
+        File::options()
+            .write(true)
+            .open(&rolled)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        stdfs::write(logs.join("daemon.log.2024-01-02"), vec![0; 20]).unwrap();
+        stdfs::write(logs.join("notes.txt"), vec![0; 5]).unwrap();
+
+        assert_eq!(log_usage(&logs).await.unwrap(), 125);
+        // the one still being written to stays, and so does anything else
+        assert_eq!(clean_logs(&logs).await.unwrap(), 100);
+        assert!(!rolled.exists());
+        assert_eq!(log_usage(&logs).await.unwrap(), 25);
+        assert_eq!(clean_logs(&logs).await.unwrap(), 0);
+        std::fs::remove_dir_all(&logs).unwrap();
+    }
+}
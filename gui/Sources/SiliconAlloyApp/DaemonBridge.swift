@@ -99,6 +99,7 @@ struct DaemonBridge {
         let supportDir = fileManager.urls(for: .applicationSupportDirectory, in: .userDomainMask).first
         let socketURL = supportDir?
             .appendingPathComponent("com.SiliconAlloy.SiliconAlloy", isDirectory: true)
+            .appendingPathComponent("run", isDirectory: true)
             .appendingPathComponent("daemon.sock", isDirectory: false)
         return socketURL?.path ?? "/tmp/silicon-alloy-daemon.sock"
     }
@@ -121,6 +122,7 @@ enum BridgeError: Error, LocalizedError {
 private struct EmptyParams: Encodable {}
 
 private struct RpcRequest<Params: Encodable>: Encodable {
+    let jsonrpc = "2.0"
     let id: UUID
     let method: String
     let params: Params